@@ -0,0 +1,590 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use lru::LruCache;
+
+use crate::error::{CommandError, ErrorKind};
+use crate::sort::natural_path_cmp;
+
+// アーカイブのパスとアーカイブ内のエントリ名の区切り
+// アーカイブ内の画像は "{アーカイブのパス}!/{エントリ名}" という仮想的なパスで扱う
+pub const ENTRY_SEPARATOR: &str = "!/";
+
+// macOSで作成したZIPに含まれるリソースフォーク用のフォルダ（画像ではないので常に除外する）
+const MACOS_METADATA_DIR: &str = "__MACOSX";
+
+// 1エントリあたりの展開後の最大バイト数（壊れた・悪意のあるアーカイブでメモリを使い切らないため）
+const MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+
+// RAR（CBR）の拡張子
+const RAR_EXTENSIONS: &[&str] = &["rar", "cbr"];
+
+// RAR（CBR）を扱うか（rar フィーチャーでunrarを組み込んだ場合のみ）
+const RAR_SUPPORTED: bool = cfg!(feature = "rar");
+
+// 7zの展開済みエントリを保持するアーカイブの数と、1アーカイブあたりの最大バイト数
+// ソリッド圧縮ではエントリを読むたびに先頭から展開し直すことになるので、
+// 読んだエントリに続くエントリもまとめて展開しておき、順に読むときは展開し直さないようにする
+const SEVEN_ZIP_CACHE_ARCHIVES: usize = 3;
+const SEVEN_ZIP_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+
+// 7zの展開済みエントリのキャッシュ（アーカイブのパスがキー）
+static SEVEN_ZIP_CACHE: Mutex<Option<LruCache<PathBuf, SevenZipEntries>>> = Mutex::new(None);
+
+// アーカイブ内で連続するエントリの展開済みの内容
+struct SevenZipEntries {
+    // 展開したときのアーカイブの更新日時（変わっていたら使わない）
+    modified: SystemTime,
+    entries: HashMap<String, Vec<u8>>,
+}
+
+// アプリで扱うアーカイブ形式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArchiveFormat {
+    // ZIP・CBZ
+    Zip,
+    // 7z・CB7
+    SevenZip,
+    // RAR・CBR
+    Rar,
+}
+
+impl ArchiveFormat {
+    // 拡張子（大文字小文字は区別しない）からアーカイブ形式を返す
+    fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "zip" | "cbz" => Some(Self::Zip),
+            "7z" | "cb7" => Some(Self::SevenZip),
+            ext if RAR_SUPPORTED && RAR_EXTENSIONS.contains(&ext) => Some(Self::Rar),
+            _ => None,
+        }
+    }
+
+    fn detect(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_extension)
+    }
+}
+
+// 対応しているアーカイブの拡張子かどうか
+pub fn is_archive_file(path: &Path) -> bool {
+    ArchiveFormat::detect(path).is_some()
+}
+
+// ドロップされたパスに対応していないアーカイブ（rar フィーチャーなしのRAR・CBR）があればエラーを返す
+// フォルダやZIPと同じように開けると思って落とされるので、黙って無視せずに知らせる
+pub fn check_supported(paths: &[String]) -> Result<(), CommandError> {
+    if RAR_SUPPORTED {
+        return Ok(());
+    }
+    let unsupported = paths.iter().find(|path| {
+        Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| RAR_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+    });
+    match unsupported {
+        Some(path) => Err(CommandError::new(
+            ErrorKind::UnsupportedFormat,
+            "RAR archives are not supported",
+        )
+        .with_path(path.as_str())),
+        None => Ok(()),
+    }
+}
+
+// アーカイブのパスとエントリ名から仮想的なパスを返す
+pub fn entry_path(archive_path: &str, entry_name: &str) -> String {
+    format!("{archive_path}{ENTRY_SEPARATOR}{entry_name}")
+}
+
+// 仮想的なパスをアーカイブのパスとエントリ名に分割する
+// 区切りの直前がアーカイブの拡張子でない（アーカイブ内のエントリでない）場合は None を返す
+pub fn split_entry_path(path: &str) -> Option<(&str, &str)> {
+    path.match_indices(ENTRY_SEPARATOR).find_map(|(index, _)| {
+        let archive_path = &path[..index];
+        let entry_name = &path[index + ENTRY_SEPARATOR.len()..];
+        (is_archive_file(Path::new(archive_path)) && !entry_name.is_empty())
+            .then_some((archive_path, entry_name))
+    })
+}
+
+// アーカイブ内のファイル（フォルダを除く）のエントリ名を返す
+// エントリ名は "/" 区切りで、フォルダ毎にファイル名の自然順に並べる
+pub fn list_entries(archive_path: &Path) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = match detect_format(archive_path)? {
+        ArchiveFormat::Zip => open_zip(archive_path)?
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(|name| name.to_string())
+            .collect(),
+        ArchiveFormat::SevenZip => sevenz_rust::Archive::open(archive_path)
+            .map_err(|e| format!("Failed to open archive: {e}"))?
+            .files
+            .iter()
+            .filter(|entry| !entry.is_directory() && !entry.is_anti_item())
+            .map(|entry| entry.name().replace('\\', "/"))
+            .collect(),
+        ArchiveFormat::Rar => rar::list_entries(archive_path)?,
+    };
+    names.retain(|name| !name.split('/').any(|c| c == MACOS_METADATA_DIR));
+    names.sort_by(|a, b| natural_path_cmp(a, b));
+    Ok(names)
+}
+
+// エントリの内容を展開して返す
+// ディスクには書き出さずにメモリ上に展開する
+pub fn read_entry(archive_path: &Path, entry_name: &str) -> Result<Vec<u8>, String> {
+    match detect_format(archive_path)? {
+        ArchiveFormat::Zip => {
+            let mut archive = open_zip(archive_path)?;
+            let file = archive
+                .by_name(entry_name)
+                .map_err(|e| format!("Failed to find {entry_name} in archive: {e}"))?;
+            read_limited(file, entry_name)
+        }
+        ArchiveFormat::SevenZip => read_7z_entry(archive_path, entry_name),
+        ArchiveFormat::Rar => rar::read_entry(archive_path, entry_name),
+    }
+}
+
+// 7zのエントリをキャッシュから、なければ展開して返す
+fn read_7z_entry(archive_path: &Path, entry_name: &str) -> Result<Vec<u8>, String> {
+    let modified = std::fs::metadata(archive_path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| format!("Failed to open archive: {e}"))?;
+    {
+        let mut cache = SEVEN_ZIP_CACHE.lock().expect("failed to lock 7z cache");
+        let cached = cache
+            .as_mut()
+            .and_then(|cache| cache.get(archive_path))
+            .filter(|cached| cached.modified == modified)
+            .and_then(|cached| cached.entries.get(entry_name));
+        if let Some(data) = cached {
+            return Ok(data.clone());
+        }
+    }
+
+    // 展開中は他のアーカイブの読み込みを待たせないように、ロックを外して展開する
+    let entries = decode_7z_entries(archive_path, entry_name, SEVEN_ZIP_CACHE_BYTES)?;
+    let data = entries[entry_name].clone();
+    let mut cache = SEVEN_ZIP_CACHE.lock().expect("failed to lock 7z cache");
+    cache
+        .get_or_insert_with(|| {
+            LruCache::new(NonZeroUsize::new(SEVEN_ZIP_CACHE_ARCHIVES).expect("cache size is zero"))
+        })
+        .put(
+            archive_path.to_path_buf(),
+            SevenZipEntries { modified, entries },
+        );
+    Ok(data)
+}
+
+// 7zのentry_nameのエントリと、アーカイブ内でそれに続くエントリを合計max_bytesまで展開して返す
+// entry_nameのエントリはmax_bytesを超えていても含める
+fn decode_7z_entries(
+    archive_path: &Path,
+    entry_name: &str,
+    max_bytes: u64,
+) -> Result<HashMap<String, Vec<u8>>, String> {
+    let mut reader = sevenz_rust::SevenZReader::open(archive_path, sevenz_rust::Password::empty())
+        .map_err(|e| format!("Failed to open archive: {e}"))?;
+    let mut entries = HashMap::new();
+    let mut total = 0;
+    let mut error = None;
+    reader
+        .for_each_entries(|entry, entry_reader| {
+            let name = entry.name().replace('\\', "/");
+            if entries.is_empty() {
+                if name != entry_name {
+                    // ソリッド圧縮では後続のエントリを読むために、読み飛ばすエントリも展開する必要がある
+                    std::io::copy(entry_reader, &mut std::io::sink())?;
+                    return Ok(true);
+                }
+                match read_limited(entry_reader, entry_name) {
+                    Ok(data) => {
+                        total = data.len() as u64;
+                        entries.insert(name, data);
+                        return Ok(true);
+                    }
+                    Err(e) => {
+                        error = Some(e);
+                        return Ok(false);
+                    }
+                }
+            }
+            if entry.is_directory() {
+                return Ok(true);
+            }
+            if total + entry.size() > max_bytes {
+                return Ok(false);
+            }
+            // 続くエントリは読めなければキャッシュしないだけ
+            match read_limited(entry_reader, &name) {
+                Ok(data) => {
+                    total += data.len() as u64;
+                    entries.insert(name, data);
+                    Ok(true)
+                }
+                Err(_) => Ok(false),
+            }
+        })
+        .map_err(|e| format!("Failed to read archive: {e}"))?;
+    if let Some(e) = error {
+        return Err(e);
+    }
+    if entries.is_empty() {
+        return Err(format!("Failed to find {entry_name} in archive"));
+    }
+    Ok(entries)
+}
+
+// エントリの展開後のバイト数を返す（内容は展開しない）
+pub fn entry_size(archive_path: &Path, entry_name: &str) -> Result<u64, String> {
+    match detect_format(archive_path)? {
+        ArchiveFormat::Zip => open_zip(archive_path)?
+            .by_name(entry_name)
+            .map(|file| file.size())
+            .map_err(|e| format!("Failed to find {entry_name} in archive: {e}")),
+        ArchiveFormat::SevenZip => sevenz_rust::Archive::open(archive_path)
+            .map_err(|e| format!("Failed to open archive: {e}"))?
+            .files
+            .iter()
+            .find(|entry| entry.name().replace('\\', "/") == entry_name)
+            .map(|entry| entry.size())
+            .ok_or_else(|| format!("Failed to find {entry_name} in archive")),
+        ArchiveFormat::Rar => rar::entry_size(archive_path, entry_name),
+    }
+}
+
+fn detect_format(archive_path: &Path) -> Result<ArchiveFormat, String> {
+    ArchiveFormat::detect(archive_path)
+        .ok_or_else(|| "File is not a supported archive format".to_string())
+}
+
+fn open_zip(archive_path: &Path) -> Result<zip::ZipArchive<BufReader<File>>, String> {
+    let file = File::open(archive_path).map_err(|e| format!("Failed to open archive: {e}"))?;
+    zip::ZipArchive::new(BufReader::new(file)).map_err(|e| format!("Failed to open archive: {e}"))
+}
+
+fn read_limited(reader: impl Read, entry_name: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    reader
+        .take(MAX_ENTRY_SIZE + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Failed to read {entry_name} in archive: {e}"))?;
+    if data.len() as u64 > MAX_ENTRY_SIZE {
+        return Err(format!("{entry_name} in archive is too large"));
+    }
+    Ok(data)
+}
+
+// RAR・CBRの読み込み（unrarのネイティブライブラリを使う）
+// ソリッド圧縮かどうかに関わらず、エントリを読むたびにアーカイブの先頭から読み進める
+#[cfg(feature = "rar")]
+mod rar {
+    use std::path::Path;
+
+    use super::MAX_ENTRY_SIZE;
+
+    // アーカイブ内のファイルのエントリ名（"/" 区切り）と展開後のバイト数を返す
+    fn list(archive_path: &Path) -> Result<Vec<(String, u64)>, String> {
+        let archive = unrar::Archive::new(archive_path)
+            .open_for_listing()
+            .map_err(|e| format!("Failed to open archive: {e}"))?;
+        let mut entries = Vec::new();
+        for header in archive {
+            let header = header.map_err(to_error)?;
+            if header.is_directory() {
+                continue;
+            }
+            let name = header.filename.to_string_lossy().replace('\\', "/");
+            entries.push((name, header.unpacked_size));
+        }
+        Ok(entries)
+    }
+
+    pub fn list_entries(archive_path: &Path) -> Result<Vec<String>, String> {
+        Ok(list(archive_path)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    pub fn entry_size(archive_path: &Path, entry_name: &str) -> Result<u64, String> {
+        list(archive_path)?
+            .into_iter()
+            .find(|(name, _)| name == entry_name)
+            .map(|(_, size)| size)
+            .ok_or_else(|| format!("Failed to find {entry_name} in archive"))
+    }
+
+    fn to_error(e: impl std::fmt::Display) -> String {
+        format!("Failed to read archive: {e}")
+    }
+
+    pub fn read_entry(archive_path: &Path, entry_name: &str) -> Result<Vec<u8>, String> {
+        let mut archive = unrar::Archive::new(archive_path)
+            .open_for_processing()
+            .map_err(|e| format!("Failed to open archive: {e}"))?;
+        while let Some(header) = archive.read_header().map_err(to_error)? {
+            let entry = header.entry();
+            if entry.is_directory()
+                || entry.filename.to_string_lossy().replace('\\', "/") != entry_name
+            {
+                archive = header.skip().map_err(to_error)?;
+                continue;
+            }
+            if entry.unpacked_size > MAX_ENTRY_SIZE {
+                return Err(format!("{entry_name} in archive is too large"));
+            }
+            let (data, _) = header.read().map_err(to_error)?;
+            return Ok(data);
+        }
+        Err(format!("Failed to find {entry_name} in archive"))
+    }
+}
+
+// rar フィーチャーなしではRAR・CBRをアーカイブとして扱わないので、ここには来ない
+#[cfg(not(feature = "rar"))]
+mod rar {
+    use std::path::Path;
+
+    const UNSUPPORTED: &str = "Reading RAR archives is not supported in this build";
+
+    pub fn list_entries(_archive_path: &Path) -> Result<Vec<String>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn entry_size(_archive_path: &Path, _entry_name: &str) -> Result<u64, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn read_entry(_archive_path: &Path, _entry_name: &str) -> Result<Vec<u8>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+}
+
+#[cfg(test)]
+pub mod test_files {
+    use std::io::Write;
+    use std::path::Path;
+
+    // 指定されたエントリ（名前と内容）を含むZIPファイルを作成する
+    // 名前が "/" で終わるエントリはフォルダになる
+    pub fn write_zip(path: &Path, entries: &[(&str, &[u8])]) {
+        let file = std::fs::File::create(path).expect("Failed to create zip file");
+        let mut writer = zip::ZipWriter::new(file);
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, data) in entries {
+            if name.ends_with('/') {
+                writer.add_directory(*name, options).unwrap();
+            } else {
+                writer.start_file(*name, options).unwrap();
+                writer.write_all(data).unwrap();
+            }
+        }
+        writer.finish().expect("Failed to write zip file");
+    }
+
+    // 指定されたエントリを含む7zファイルをソリッド圧縮で作成する
+    pub fn write_7z(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut writer = sevenz_rust::SevenZWriter::create(path).expect("Failed to create 7z file");
+        let archive_entries = entries
+            .iter()
+            .map(|(name, _)| {
+                let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+                entry.name = name.to_string();
+                entry.has_stream = true;
+                entry
+            })
+            .collect();
+        let readers: Vec<_> = entries
+            .iter()
+            .map(|(_, data)| sevenz_rust::SourceReader::from(*data))
+            .collect();
+        writer
+            .push_archive_entries(archive_entries, readers.into())
+            .unwrap();
+        writer.finish().expect("Failed to write 7z file");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_files::*;
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_split_entry_path() {
+        assert_eq!(
+            split_entry_path("/comics/vol1.cbz!/ch1/001.jpg"),
+            Some(("/comics/vol1.cbz", "ch1/001.jpg"))
+        );
+        // アーカイブでないファイル名に含まれる区切りは無視する
+        assert_eq!(
+            split_entry_path("/photos/wow!/packs/a.ZIP!/b.png"),
+            Some(("/photos/wow!/packs/a.ZIP", "b.png"))
+        );
+        assert_eq!(split_entry_path("/photos/wow!/a.png"), None);
+        assert_eq!(split_entry_path("/comics/vol1.cbz!/"), None);
+        assert_eq!(split_entry_path("/comics/vol1.cbz"), None);
+        assert_eq!(
+            entry_path("/comics/vol1.cbz", "ch1/001.jpg"),
+            "/comics/vol1.cbz!/ch1/001.jpg"
+        );
+    }
+
+    #[test]
+    fn test_check_supported() {
+        let paths = |paths: &[&str]| paths.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        assert!(check_supported(&paths(&["/photos", "/books/a.cbz", "/books/b.7z"])).is_ok());
+
+        // RAR・CBRは rar フィーチャーでunrarを組み込んだ場合のみ扱う
+        let result = check_supported(&paths(&["/photos", "/books/c.CBR"]));
+        assert_eq!(is_archive_file(Path::new("/books/c.CBR")), RAR_SUPPORTED);
+        if RAR_SUPPORTED {
+            assert!(result.is_ok());
+        } else {
+            let error = result.unwrap_err();
+            assert_eq!(error.kind, ErrorKind::UnsupportedFormat);
+            assert_eq!(error.path.as_deref(), Some("/books/c.CBR"));
+        }
+    }
+
+    #[test]
+    fn test_list_zip_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let zip = temp_dir.path().join("comic.cbz");
+        write_zip(
+            &zip,
+            &[
+                ("page10.jpg", b"10"),
+                ("page2.jpg", b"2"),
+                ("extra/", b""),
+                ("extra/cover.png", b"cover"),
+                ("__MACOSX/._page2.jpg", b"fork"),
+            ],
+        );
+
+        assert_eq!(
+            list_entries(&zip).unwrap(),
+            vec!["extra/cover.png", "page2.jpg", "page10.jpg"]
+        );
+        assert_eq!(read_entry(&zip, "extra/cover.png").unwrap(), b"cover");
+        assert_eq!(entry_size(&zip, "page10.jpg"), Ok(2));
+        assert!(read_entry(&zip, "missing.jpg").is_err());
+    }
+
+    #[test]
+    fn test_list_7z_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("pack.7z");
+        write_7z(
+            &archive,
+            &[
+                ("b/2.png", b"two"),
+                ("a10.png", b"ten"),
+                ("a9.png", b"nine"),
+            ],
+        );
+
+        assert_eq!(
+            list_entries(&archive).unwrap(),
+            vec!["a9.png", "a10.png", "b/2.png"]
+        );
+        // ソリッド圧縮で後ろにあるエントリも読める
+        assert_eq!(read_entry(&archive, "a9.png").unwrap(), b"nine");
+        assert_eq!(read_entry(&archive, "b/2.png").unwrap(), b"two");
+        assert_eq!(entry_size(&archive, "a9.png"), Ok(4));
+        assert!(read_entry(&archive, "missing.png").is_err());
+    }
+
+    #[test]
+    fn test_decode_7z_entries_after_requested_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("pack.7z");
+        write_7z(
+            &archive,
+            &[
+                ("1.png", b"one"),
+                ("2.png", b"two"),
+                ("3.png", b"three"),
+                ("4.png", b"four"),
+            ],
+        );
+
+        // 要求したエントリに続くエントリを、合計の上限まで展開する
+        let entries = decode_7z_entries(&archive, "2.png", 8).unwrap();
+        let mut names: Vec<_> = entries.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["2.png", "3.png"]);
+        assert_eq!(entries["3.png"], b"three");
+
+        // 上限を超えていても要求したエントリは返す
+        let entries = decode_7z_entries(&archive, "4.png", 1).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries["4.png"], b"four");
+        assert!(decode_7z_entries(&archive, "missing.png", 8).is_err());
+    }
+
+    #[test]
+    fn test_read_7z_entry_ignores_cache_of_modified_archive() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("pack.7z");
+        write_7z(&archive, &[("1.png", b"one"), ("2.png", b"two")]);
+        assert_eq!(read_entry(&archive, "1.png").unwrap(), b"one");
+
+        write_7z(&archive, &[("1.png", b"new one"), ("2.png", b"new two")]);
+        let modified = SystemTime::now() + std::time::Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(&archive)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        assert_eq!(read_entry(&archive, "2.png").unwrap(), b"new two");
+    }
+
+    #[test]
+    fn test_list_entries_invalid_archive() {
+        let temp_dir = TempDir::new().unwrap();
+        let broken = temp_dir.path().join("broken.zip");
+        std::fs::write(&broken, "not a zip").unwrap();
+
+        assert!(list_entries(&broken).is_err());
+        assert!(list_entries(&temp_dir.path().join("comic.rar")).is_err());
+    }
+
+    // "unrar-0.4.0" という内容の VERSION だけを無圧縮で格納したRAR
+    #[cfg(feature = "rar")]
+    const VERSION_RAR: &[u8] = &[
+        0x52, 0x61, 0x72, 0x21, 0x1a, 0x07, 0x00, 0xcf, 0x90, 0x73, 0x00, 0x00, 0x0d, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x0f, 0x0c, 0x74, 0x20, 0x80, 0x27, 0x00, 0x15, 0x00, 0x00,
+        0x00, 0x0b, 0x00, 0x00, 0x00, 0x03, 0x45, 0xf3, 0x7d, 0xc6, 0xa4, 0x8a, 0x07, 0x47, 0x1d,
+        0x33, 0x07, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x56, 0x45, 0x52, 0x53, 0x49, 0x4f, 0x4e, 0x0c,
+        0x00, 0x8f, 0xec, 0x8a, 0x45, 0xcc, 0x23, 0xc8, 0x48, 0x08, 0x83, 0x62, 0xfe, 0x5f, 0xdd,
+        0x5c, 0x53, 0x88, 0xf0, 0x72, 0xc4, 0x3d, 0x7b, 0x00, 0x40, 0x07, 0x00,
+    ];
+
+    #[cfg(feature = "rar")]
+    #[test]
+    fn test_read_rar_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let rar = temp_dir.path().join("comic.cbr");
+        std::fs::write(&rar, VERSION_RAR).unwrap();
+
+        assert_eq!(list_entries(&rar).unwrap(), vec!["VERSION"]);
+        assert_eq!(entry_size(&rar, "VERSION").unwrap(), 11);
+        assert_eq!(read_entry(&rar, "VERSION").unwrap(), b"unrar-0.4.0");
+        assert!(read_entry(&rar, "missing.jpg").is_err());
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::file_ops;
+
+// {exif_date} の書式を省略した場合の書式
+const DEFAULT_DATE_FORMAT: &str = "%Y%m%d";
+
+// {tags} の区切りを省略した場合の区切り
+const DEFAULT_TAG_SEPARATOR: &str = "_";
+
+// 名前のテンプレートの構成要素
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    // 元のファイル名（拡張子を除く）
+    Name,
+    // 元の拡張子
+    Ext,
+    // 1から始まる連番（widthの桁数まで0で埋める）
+    Seq { width: usize },
+    // EXIFの撮影日時
+    ExifDate { format: String },
+    // 画像のタグ
+    Tags { separator: String },
+}
+
+// "{exif_date:%Y%m%d}_{seq:04}_{tags}.{ext}" のような名前のテンプレート
+// "{{" と "}}" はそれぞれ "{" と "}" そのものを表す
+#[derive(Debug)]
+pub struct RenameTemplate {
+    segments: Vec<Segment>,
+}
+
+// テンプレートから名前を作るための画像毎の情報
+pub struct RenameContext<'a> {
+    pub seq: usize,
+    pub path: &'a Path,
+    pub capture_date: Option<&'a str>,
+    pub tags: &'a [String],
+}
+
+impl RenameTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '}' {
+                if chars.next_if_eq(&'}').is_none() {
+                    return Err(format!("Unmatched }} in template {template}"));
+                }
+                literal.push('}');
+                continue;
+            }
+            if c != '{' {
+                literal.push(c);
+                continue;
+            }
+            if chars.next_if_eq(&'{').is_some() {
+                literal.push('{');
+                continue;
+            }
+
+            let mut placeholder = String::new();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(c) => placeholder.push(c),
+                    None => return Err(format!("Unclosed {{ in template {template}")),
+                }
+            }
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(parse_placeholder(&placeholder)?);
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    // EXIFの撮影日時が必要か（不要な場合はEXIFの読み込みを省く）
+    pub fn uses_exif_date(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::ExifDate { .. }))
+    }
+
+    // タグが必要か（不要な場合はタグファイルの読み込みを省く）
+    pub fn uses_tags(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Tags { .. }))
+    }
+
+    // テンプレートから画像の新しいファイル名を作る
+    pub fn render(&self, context: &RenameContext) -> Result<String, String> {
+        let mut name = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => name.push_str(text),
+                Segment::Name => name.push_str(&os_str_to_string(context.path.file_stem())),
+                Segment::Ext => name.push_str(&os_str_to_string(context.path.extension())),
+                Segment::Seq { width } => name.push_str(&format!("{:0width$}", context.seq)),
+                Segment::ExifDate { format } => {
+                    let date = context
+                        .capture_date
+                        .ok_or_else(|| "The image has no capture date in EXIF".to_string())?;
+                    name.push_str(&format_date(date, format)?);
+                }
+                Segment::Tags { separator } => name.push_str(&context.tags.join(separator)),
+            }
+        }
+        Ok(name)
+    }
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<Segment, String> {
+    let (key, spec) = match placeholder.split_once(':') {
+        Some((key, spec)) => (key, Some(spec)),
+        None => (placeholder, None),
+    };
+    match (key, spec) {
+        ("name", None) => Ok(Segment::Name),
+        ("ext", None) => Ok(Segment::Ext),
+        ("seq", None) => Ok(Segment::Seq { width: 0 }),
+        ("seq", Some(spec)) => spec
+            .parse()
+            .map(|width| Segment::Seq { width })
+            .map_err(|_| format!("Invalid width {spec} of {{seq}}")),
+        ("exif_date", spec) => {
+            let format = spec.unwrap_or(DEFAULT_DATE_FORMAT).to_string();
+            // 書式の誤りは画像毎ではなくテンプレートの誤りとして扱う
+            format_date("2000-01-01T00:00:00", &format)?;
+            Ok(Segment::ExifDate { format })
+        }
+        ("tags", spec) => Ok(Segment::Tags {
+            separator: spec.unwrap_or(DEFAULT_TAG_SEPARATOR).to_string(),
+        }),
+        _ => Err(format!("Unknown placeholder {{{placeholder}}}")),
+    }
+}
+
+fn os_str_to_string(s: Option<&std::ffi::OsStr>) -> String {
+    s.map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+// "YYYY-MM-DDTHH:MM:SS" 形式の日時を書式に従って整形する
+// 使える指定子は %Y %m %d %H %M %S %% のみ
+fn format_date(date: &str, format: &str) -> Result<String, String> {
+    let field = |range: std::ops::Range<usize>| {
+        date.get(range)
+            .ok_or_else(|| format!("Invalid capture date {date}"))
+    };
+    let mut formatted = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => formatted.push_str(field(0..4)?),
+            Some('m') => formatted.push_str(field(5..7)?),
+            Some('d') => formatted.push_str(field(8..10)?),
+            Some('H') => formatted.push_str(field(11..13)?),
+            Some('M') => formatted.push_str(field(14..16)?),
+            Some('S') => formatted.push_str(field(17..19)?),
+            Some('%') => formatted.push('%'),
+            Some(c) => return Err(format!("Unsupported date format %{c}")),
+            None => return Err("Date format must not end with %".to_string()),
+        }
+    }
+    Ok(formatted)
+}
+
+// 各画像の新しいファイル名から名前変更後のパスを求めて、衝突を検出する
+// 一覧外の既存のファイルと同じ名前になる画像や、他の画像と同じ名前になる画像はエラーにする
+// （一覧内の画像同士の入れ替えや連鎖する名前変更は apply_renames で扱えるので許可する）
+pub fn plan_renames(names: &[(&Path, Result<String, String>)]) -> Vec<Result<PathBuf, String>> {
+    let sources: HashSet<&Path> = names.iter().map(|(path, _)| *path).collect();
+    let mut planned: Vec<Result<PathBuf, String>> = names
+        .iter()
+        .map(|(path, name)| {
+            let name = name.as_ref().map_err(|e| e.clone())?;
+            file_ops::validate_file_name(name)?;
+            let dest = path.with_file_name(name);
+            if !sources.contains(dest.as_path()) && dest.exists() {
+                return Err(format!("{} already exists", dest.display()));
+            }
+            Ok(dest)
+        })
+        .collect();
+
+    let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+    for dest in planned.iter().flatten() {
+        *counts.entry(dest.clone()).or_default() += 1;
+    }
+    for result in &mut planned {
+        if let Ok(dest) = result {
+            if counts[dest.as_path()] > 1 {
+                *result = Err(format!("{} is used by more than one image", dest.display()));
+            }
+        }
+    }
+    planned
+}
+
+// 計画した名前変更を行い、画像毎の結果を返す
+// 入れ替えや連鎖する名前変更でも上書きしないように、一度一時的な名前に変更してから新しい名前にする
+// 失敗した画像は可能な限り元の名前に戻す
+pub fn apply_renames(renames: &[(&Path, &Path)]) -> Vec<Result<(), String>> {
+    let temp_paths: Vec<PathBuf> = renames
+        .iter()
+        .enumerate()
+        .map(|(i, (src, _))| temp_path(src, i))
+        .collect();
+    let mut results: Vec<Result<(), String>> = renames
+        .iter()
+        .zip(&temp_paths)
+        .map(|((src, _), temp)| {
+            std::fs::rename(src, temp)
+                .map_err(|e| format!("Failed to rename {}: {e}", src.display()))
+        })
+        .collect();
+
+    for (result, ((src, dest), temp)) in results.iter_mut().zip(renames.iter().zip(&temp_paths)) {
+        if result.is_err() {
+            continue;
+        }
+        // 一時的な名前に変更できなかった画像が残っている場合は上書きしない
+        let renamed = if dest.exists() {
+            Err(format!("{} already exists", dest.display()))
+        } else {
+            std::fs::rename(temp, dest)
+                .map_err(|e| format!("Failed to rename {}: {e}", src.display()))
+        };
+        if let Err(e) = renamed {
+            *result = if src.exists() || std::fs::rename(temp, src).is_err() {
+                Err(format!("{e} (the image is left as {})", temp.display()))
+            } else {
+                Err(e)
+            };
+        }
+    }
+    results
+}
+
+// 名前変更中の一時的な名前（画像として扱われないように、隠しファイルにして拡張子も変える）
+fn temp_path(path: &Path, index: usize) -> PathBuf {
+    path.with_file_name(format!(
+        ".{}.{index}.renaming",
+        os_str_to_string(path.file_name())
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn render(template: &str, context: &RenameContext) -> Result<String, String> {
+        RenameTemplate::parse(template)?.render(context)
+    }
+
+    #[test]
+    fn test_render_template() {
+        let tags = vec!["cat".to_string(), "sleep".to_string()];
+        let context = RenameContext {
+            seq: 7,
+            path: Path::new("/p/IMG_0001.JPG"),
+            capture_date: Some("2024-03-09T13:05:42"),
+            tags: &tags,
+        };
+
+        assert_eq!(
+            render("{exif_date:%Y%m%d}_{seq:04}_{tags}.{ext}", &context),
+            Ok("20240309_0007_cat_sleep.JPG".to_string())
+        );
+        assert_eq!(
+            render("{exif_date} {exif_date:%H%M%S}-{seq}", &context),
+            Ok("20240309 130542-7".to_string())
+        );
+        assert_eq!(
+            render("{{{name}}}{tags:+}.png", &context),
+            Ok("{IMG_0001}cat+sleep.png".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_template() {
+        for template in [
+            "{name",
+            "name}",
+            "{unknown}",
+            "{seq:x}",
+            "{exif_date:%Q}",
+            "{ext:x}",
+        ] {
+            assert!(RenameTemplate::parse(template).is_err(), "{template}");
+        }
+    }
+
+    #[test]
+    fn test_render_without_capture_date() {
+        let context = RenameContext {
+            seq: 1,
+            path: Path::new("/p/a.png"),
+            capture_date: None,
+            tags: &[],
+        };
+
+        assert!(render("{exif_date}.{ext}", &context).is_err());
+        assert_eq!(
+            render("{name}{tags}.{ext}", &context),
+            Ok("a.png".to_string())
+        );
+    }
+
+    #[test]
+    fn test_plan_renames_detects_collisions() {
+        let temp_dir = TempDir::new().unwrap();
+        let [a, b, c, d] = ["a.png", "b.png", "c.png", "d.png"].map(|name| {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, name).unwrap();
+            path
+        });
+        fs::write(temp_dir.path().join("other.png"), "other").unwrap();
+
+        let planned = plan_renames(&[
+            (a.as_path(), Ok("b.png".to_string())),
+            (b.as_path(), Ok("x.png".to_string())),
+            (c.as_path(), Ok("x.png".to_string())),
+            (d.as_path(), Ok("other.png".to_string())),
+        ]);
+
+        // 一覧内の画像の名前になるのは、その画像も名前変更されるので許可する
+        assert_eq!(planned[0], Ok(b.clone()));
+        assert!(planned[1].as_ref().unwrap_err().contains("more than one"));
+        assert!(planned[2].as_ref().unwrap_err().contains("more than one"));
+        assert!(planned[3].as_ref().unwrap_err().contains("already exists"));
+    }
+
+    #[test]
+    fn test_apply_renames_swaps_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let a = temp_dir.path().join("a.png");
+        let b = temp_dir.path().join("b.png");
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+
+        let results = apply_renames(&[(a.as_path(), b.as_path()), (b.as_path(), a.as_path())]);
+
+        assert_eq!(results, vec![Ok(()), Ok(())]);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "b");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "a");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+
+// フロントエンドがエラーの種類によって表示を変えたり、メッセージを翻訳したりするための分類
+#[derive(Clone, Copy, serde::Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    // ファイル・ディレクトリが存在しない（操作中に削除された場合を含む）
+    NotFound,
+    // ファイルを期待したがディレクトリなどだった
+    NotAFile,
+    // ディレクトリを期待したがファイルなどだった
+    NotADirectory,
+    // OSの権限がない（読み取り専用のディレクトリなど）
+    PermissionDenied,
+    // 呼び出し元のウィンドウのセッションが管理していないパスが渡された
+    Unauthorized,
+    // 引数の値が不正（タグ・パターン・ファイル名など）
+    InvalidInput,
+    // 移動先・名前変更先に同名のファイルがある
+    AlreadyExists,
+    // 対応していない画像形式・アーカイブ形式
+    UnsupportedFormat,
+    // タグファイルなどの内容が壊れている、または新しい形式で読めない
+    InvalidData,
+    // その他の入出力エラー
+    Io,
+    // 上記に分類できないエラー
+    Other,
+}
+
+// Tauriコマンドが返すエラー
+// messageはログ・デバッグ用の英語のメッセージで、画面にはkindに応じたメッセージを表示する想定
+#[derive(Clone, serde::Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandError {
+    pub kind: ErrorKind,
+    pub message: String,
+    // エラーの対象のパス
+    pub path: Option<String>,
+    // 元になったエラー（I/Oエラーなど）のメッセージ
+    pub source: Option<String>,
+}
+
+impl CommandError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            path: None,
+            source: None,
+        }
+    }
+
+    // I/Oエラーから、そのエラーの種類に応じたkindのエラーを作る
+    pub fn io(message: impl Into<String>, error: &std::io::Error) -> Self {
+        let kind = match error.kind() {
+            std::io::ErrorKind::NotFound => ErrorKind::NotFound,
+            std::io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            std::io::ErrorKind::AlreadyExists => ErrorKind::AlreadyExists,
+            _ => ErrorKind::Io,
+        };
+        Self::new(kind, message).with_source(error)
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn with_source(mut self, source: impl fmt::Display) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// まだ文字列のエラーを返す関数からも ? で呼び出せるようにする
+impl From<CommandError> for String {
+    fn from(error: CommandError) -> Self {
+        error.message
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        Self::new(ErrorKind::Other, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_kind() {
+        let not_found = std::io::Error::from(std::io::ErrorKind::NotFound);
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let other = std::io::Error::other("disk on fire");
+
+        assert_eq!(CommandError::io("a", &not_found).kind, ErrorKind::NotFound);
+        assert_eq!(
+            CommandError::io("a", &denied).kind,
+            ErrorKind::PermissionDenied
+        );
+        let error = CommandError::io("Failed to read", &other).with_path("/p/a.png");
+        assert_eq!(error.kind, ErrorKind::Io);
+        assert_eq!(error.source.as_deref(), Some("disk on fire"));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let error = CommandError::new(ErrorKind::NotAFile, "/p is not a file").with_path("/p");
+
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "kind": "notAFile",
+                "message": "/p is not a file",
+                "path": "/p",
+                "source": null,
+            })
+        );
+    }
+}
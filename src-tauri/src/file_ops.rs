@@ -0,0 +1,283 @@
+use std::path::Path;
+
+use crate::archive;
+use crate::error::{CommandError, ErrorKind};
+use crate::image_format::{self, ImageFormat};
+
+// 削除・移動の対象にできる画像ファイルか検証する
+// アーカイブ内の画像はアーカイブの書き換えが必要になるため対象外とする
+pub fn check_image_file(path: &str) -> Result<&Path, CommandError> {
+    if archive::split_entry_path(path).is_some() {
+        return Err(CommandError::new(
+            ErrorKind::InvalidInput,
+            "Deleting or moving an entry in an archive is not supported",
+        )
+        .with_path(path));
+    }
+    let path_obj = Path::new(path);
+    if !path_obj.exists() {
+        return Err(CommandError::new(ErrorKind::NotFound, "not found").with_path(path));
+    }
+    if !path_obj.is_file() {
+        return Err(CommandError::new(ErrorKind::NotAFile, "not a file").with_path(path));
+    }
+    if !image_format::is_image_file(path_obj) {
+        return Err(CommandError::new(
+            ErrorKind::UnsupportedFormat,
+            "File is not a supported image format",
+        )
+        .with_path(path));
+    }
+    Ok(path_obj)
+}
+
+// 画像ファイルをゴミ箱に移動する
+pub fn trash_image_file(path: &str) -> Result<(), CommandError> {
+    let path_obj = check_image_file(path)?;
+    trash::delete(path_obj).map_err(|e| {
+        CommandError::new(ErrorKind::Io, format!("Failed to move {path} to the trash"))
+            .with_path(path)
+            .with_source(e)
+    })
+}
+
+// 画像ファイルを指定されたフォルダに移動して、移動後のパスを返す
+// 移動先に同名のファイルがある場合は上書きせずにエラーにする
+pub fn move_image_file(path: &str, dest_dir: &Path) -> Result<String, CommandError> {
+    let path_obj = check_image_file(path)?;
+    let file_name = path_obj.file_name().ok_or_else(|| {
+        CommandError::new(ErrorKind::InvalidInput, format!("{path} has no file name"))
+            .with_path(path)
+    })?;
+    let dest = dest_dir.join(file_name);
+    let dest_str = path_to_string(&dest)?;
+    if dest.exists() {
+        return Err(already_exists(&dest_str));
+    }
+    move_file(path_obj, &dest)?;
+    Ok(dest_str)
+}
+
+// 画像ファイルを同じフォルダ内でnew_nameに名前変更して、名前変更後のパスを返す
+// 同名のファイルがある場合は上書きせずにエラーにする（大文字・小文字だけの変更は許可する）
+pub fn rename_image_file(path: &str, new_name: &str) -> Result<String, CommandError> {
+    let path_obj = check_image_file(path)?;
+    validate_file_name(new_name)?;
+    if path_obj.file_name().and_then(|name| name.to_str()) == Some(new_name) {
+        return Ok(path.to_string());
+    }
+    let dest = path_obj.with_file_name(new_name);
+    let dest_str = path_to_string(&dest)?;
+    // 大文字・小文字を区別しないファイルシステムでは、大文字・小文字だけの変更で自分自身が見つかる
+    let is_same_file = dest.canonicalize().ok() == path_obj.canonicalize().ok();
+    if dest.exists() && !is_same_file {
+        return Err(already_exists(&dest_str));
+    }
+    std::fs::rename(path_obj, &dest).map_err(|e| {
+        CommandError::io(format!("Failed to rename {path}: {e}"), &e).with_path(path)
+    })?;
+    Ok(dest_str)
+}
+
+// 移動先・名前変更先に同名のファイルがある場合のエラー
+pub fn already_exists(dest: &str) -> CommandError {
+    CommandError::new(ErrorKind::AlreadyExists, format!("{dest} already exists")).with_path(dest)
+}
+
+fn path_to_string(path: &Path) -> Result<String, CommandError> {
+    path.to_str().map(|path| path.to_string()).ok_or_else(|| {
+        CommandError::new(
+            ErrorKind::InvalidInput,
+            "Failed to convert destination path to string",
+        )
+        .with_path(path.to_string_lossy())
+    })
+}
+
+// 名前変更後のファイル名として使えるか検証する
+// フォルダをまたぐ名前や、画像として扱われなくなる拡張子は許可しない
+pub fn validate_file_name(name: &str) -> Result<(), CommandError> {
+    let invalid = |message: String| CommandError::new(ErrorKind::InvalidInput, message);
+    if name.trim().is_empty() {
+        return Err(invalid("File name must not be empty".to_string()));
+    }
+    if name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(invalid(format!("{name} is not a valid file name")));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(invalid(
+            "File name must not contain control characters".to_string(),
+        ));
+    }
+    let is_image_extension = Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(ImageFormat::from_extension)
+        .is_some();
+    if !is_image_extension {
+        return Err(invalid(format!(
+            "{name} does not have a supported image extension"
+        )));
+    }
+    Ok(())
+}
+
+// ファイルをdestに移動する（destが存在する場合は上書きする）
+// 別のドライブへの移動など名前変更で移動できない場合は、コピーしてから元のファイルを削除する
+pub fn move_file(src: &Path, dest: &Path) -> Result<(), CommandError> {
+    if std::fs::rename(src, dest).is_ok() {
+        return Ok(());
+    }
+    copy_and_remove(src, dest)
+}
+
+// srcをdestと同じディレクトリの一時ファイルにコピーし、srcを削除できてからdestに置き換える
+// srcを削除できなかった場合にdestの元のファイル（上書き対象）を失わないようにする
+fn copy_and_remove(src: &Path, dest: &Path) -> Result<(), CommandError> {
+    let to_error = |e: std::io::Error| {
+        CommandError::io(format!("Failed to move {}: {e}", src.display()), &e)
+            .with_path(src.to_string_lossy())
+    };
+    let file_name = dest.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = dest.with_file_name(format!(".{file_name}.{}.moving", std::process::id()));
+    if let Err(e) = std::fs::copy(src, &temp_path) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(to_error(e));
+    }
+    if let Err(e) = std::fs::remove_file(src) {
+        // 元のファイルを残す場合は、コピーしたファイルを消して移動前の状態に戻す
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(to_error(e));
+    }
+    // 元のファイルは削除済みなので、失敗した場合は一時ファイルを残してその場所を伝える
+    std::fs::rename(&temp_path, dest).map_err(|e| {
+        CommandError::io(
+            format!(
+                "Failed to move {} (copied to {}): {e}",
+                src.display(),
+                temp_path.display()
+            ),
+            &e,
+        )
+        .with_path(src.to_string_lossy())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_format::test_files::png;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_image_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let image = temp_dir.path().join("a.png");
+        let text = temp_dir.path().join("a.txt");
+        let fake = temp_dir.path().join("fake.png");
+        fs::write(&image, png(0)).unwrap();
+        fs::write(&text, "text").unwrap();
+        fs::write(&fake, "text").unwrap();
+
+        assert!(check_image_file(image.to_str().unwrap()).is_ok());
+        let kind = |path: &Path| check_image_file(path.to_str().unwrap()).unwrap_err().kind;
+        assert_eq!(kind(&text), ErrorKind::UnsupportedFormat);
+        // 拡張子だけが画像のものは対象にしない
+        assert_eq!(kind(&fake), ErrorKind::UnsupportedFormat);
+        assert_eq!(kind(temp_dir.path()), ErrorKind::NotAFile);
+        assert_eq!(
+            kind(&temp_dir.path().join("missing.png")),
+            ErrorKind::NotFound
+        );
+        assert!(check_image_file("/p/comic.cbz!/001.png")
+            .unwrap_err()
+            .message
+            .contains("archive"));
+    }
+
+    #[test]
+    fn test_move_image_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest_dir = temp_dir.path().join("keep");
+        fs::create_dir(&dest_dir).unwrap();
+        let image = temp_dir.path().join("a.png");
+        fs::write(&image, png(0)).unwrap();
+
+        let dest = move_image_file(image.to_str().unwrap(), &dest_dir).unwrap();
+
+        assert_eq!(Path::new(&dest), dest_dir.join("a.png"));
+        assert_eq!(fs::read(&dest).unwrap(), png(0));
+        assert!(!image.exists());
+    }
+
+    #[test]
+    fn test_copy_and_remove_overwrites_dest() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path().join("a.png");
+        let dest_dir = temp_dir.path().join("keep");
+        fs::create_dir(&dest_dir).unwrap();
+        let dest = dest_dir.join("a.png");
+        fs::write(&src, "new").unwrap();
+        fs::write(&dest, "existing").unwrap();
+
+        copy_and_remove(&src, &dest).unwrap();
+
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dest_dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_copy_and_remove_keeps_dest_when_copy_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("a.png");
+        fs::write(&dest, "existing").unwrap();
+
+        let error = copy_and_remove(&temp_dir.path().join("missing.png"), &dest).unwrap_err();
+
+        assert_eq!(error.kind, ErrorKind::NotFound);
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "existing");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_rename_image_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let image = temp_dir.path().join("a.png");
+        fs::write(&image, png(0)).unwrap();
+        fs::write(temp_dir.path().join("b.png"), png(1)).unwrap();
+        let path = image.to_str().unwrap();
+
+        assert_eq!(
+            rename_image_file(path, "b.png").unwrap_err().kind,
+            ErrorKind::AlreadyExists
+        );
+        for invalid in ["", " ", "..", "sub/c.png", "c\tx.png", "c.txt", "c"] {
+            assert!(rename_image_file(path, invalid).is_err(), "{invalid:?}");
+        }
+        assert!(image.exists());
+
+        let dest = rename_image_file(path, "c.jpg").unwrap();
+
+        assert_eq!(Path::new(&dest), temp_dir.path().join("c.jpg"));
+        assert_eq!(fs::read(&dest).unwrap(), png(0));
+        assert!(!image.exists());
+    }
+
+    #[test]
+    fn test_move_image_file_does_not_overwrite() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest_dir = temp_dir.path().join("keep");
+        fs::create_dir(&dest_dir).unwrap();
+        let image = temp_dir.path().join("a.png");
+        fs::write(&image, png(0)).unwrap();
+        fs::write(dest_dir.join("a.png"), png(1)).unwrap();
+
+        let result = move_image_file(image.to_str().unwrap(), &dest_dir);
+
+        assert_eq!(result.unwrap_err().kind, ErrorKind::AlreadyExists);
+        assert!(image.exists());
+        assert_eq!(fs::read(dest_dir.join("a.png")).unwrap(), png(1));
+    }
+}
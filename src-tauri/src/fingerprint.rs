@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+// フィンガープリントに使う先頭・中央・末尾それぞれのバイト数
+const SAMPLE_SIZE: u64 = 16 * 1024;
+
+// 画像ファイルの内容のフィンガープリント
+// アプリの外で名前変更・移動された画像にタグを付け直すために、タグと一緒に保存する
+// 大きなファイルでも全体を読まないように、ファイルサイズと先頭・中央・末尾の一部のハッシュから作る
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub hash: String,
+}
+
+impl Fingerprint {
+    // ファイルのフィンガープリントを求める（読み込めない場合はNone）
+    pub fn of_file(path: &Path) -> Option<Self> {
+        let mut file = std::fs::File::open(path).ok()?;
+        let size = file.metadata().ok()?.len();
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&size.to_le_bytes());
+        if size <= SAMPLE_SIZE * 3 {
+            hasher.update_reader(&mut file).ok()?;
+        } else {
+            for offset in [0, (size - SAMPLE_SIZE) / 2, size - SAMPLE_SIZE] {
+                let mut sample = vec![0; SAMPLE_SIZE as usize];
+                file.seek(SeekFrom::Start(offset)).ok()?;
+                file.read_exact(&mut sample).ok()?;
+                hasher.update(&sample);
+            }
+        }
+        Some(Self {
+            size,
+            hash: hasher.finalize().to_hex().to_string(),
+        })
+    }
+
+    // "{size}:{hash}" の形式の文字列から読み込む
+    pub fn parse(s: &str) -> Option<Self> {
+        let (size, hash) = s.split_once(':')?;
+        Some(Self {
+            size: size.parse().ok()?,
+            hash: hash.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.size, self.hash)
+    }
+}
+
+// 行き先のファイルがなくなったタグのエントリ（orphans）と、タグのないファイル（candidates）の中から
+// フィンガープリントが一致するものの組（エントリのキー, ファイル）を返す
+// 同じ内容のファイルやエントリが複数ある場合はどれのものか分からないので、組にしない
+// フィンガープリントは、サイズが一致したファイルについてだけ求める
+pub fn match_orphans<K: Clone + Eq + Hash>(
+    orphans: &[(K, Fingerprint)],
+    candidates: &[PathBuf],
+) -> Vec<(K, PathBuf)> {
+    let sizes: HashMap<u64, usize> = orphans.iter().fold(HashMap::new(), |mut sizes, (_, fp)| {
+        *sizes.entry(fp.size).or_default() += 1;
+        sizes
+    });
+    let mut candidates_by_fingerprint: HashMap<Fingerprint, Vec<&PathBuf>> = HashMap::new();
+    for candidate in candidates {
+        let same_size =
+            std::fs::metadata(candidate).is_ok_and(|metadata| sizes.contains_key(&metadata.len()));
+        if !same_size {
+            continue;
+        }
+        if let Some(fingerprint) = Fingerprint::of_file(candidate) {
+            candidates_by_fingerprint
+                .entry(fingerprint)
+                .or_default()
+                .push(candidate);
+        }
+    }
+
+    let mut orphans_by_fingerprint: HashMap<&Fingerprint, Vec<&K>> = HashMap::new();
+    for (key, fingerprint) in orphans {
+        orphans_by_fingerprint
+            .entry(fingerprint)
+            .or_default()
+            .push(key);
+    }
+    orphans_by_fingerprint
+        .into_iter()
+        .filter_map(|(fingerprint, keys)| {
+            match (
+                keys.as_slice(),
+                candidates_by_fingerprint.get(fingerprint)?.as_slice(),
+            ) {
+                ([key], [candidate]) => Some(((*key).clone(), (*candidate).clone())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_of_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let large: Vec<u8> = (0..SAMPLE_SIZE * 5).map(|i| (i % 251) as u8).collect();
+        let mut edited = large.clone();
+        // サンプルに含まれない位置の変更は区別しない
+        edited[(SAMPLE_SIZE + 10) as usize] ^= 1;
+        let mut edited_head = large.clone();
+        edited_head[0] ^= 1;
+        std::fs::write(temp_dir.path().join("a.png"), &large).unwrap();
+        std::fs::write(temp_dir.path().join("b.png"), &edited).unwrap();
+        std::fs::write(temp_dir.path().join("c.png"), &edited_head).unwrap();
+        std::fs::write(temp_dir.path().join("small.png"), "small").unwrap();
+
+        let fingerprint = |name: &str| Fingerprint::of_file(&temp_dir.path().join(name)).unwrap();
+        assert_eq!(fingerprint("a.png"), fingerprint("b.png"));
+        assert_ne!(fingerprint("a.png"), fingerprint("c.png"));
+        assert_eq!(fingerprint("a.png").size, SAMPLE_SIZE * 5);
+        assert_eq!(fingerprint("small.png").size, 5);
+        assert_eq!(
+            Fingerprint::parse(&fingerprint("a.png").to_string()),
+            Some(fingerprint("a.png"))
+        );
+        assert_eq!(
+            Fingerprint::of_file(&temp_dir.path().join("missing.png")),
+            None
+        );
+        assert_eq!(Fingerprint::parse("broken"), None);
+    }
+
+    #[test]
+    fn test_match_orphans() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| temp_dir.path().join(name);
+        std::fs::write(path("renamed.png"), "cat image").unwrap();
+        std::fs::write(path("copy1.png"), "same image").unwrap();
+        std::fs::write(path("copy2.png"), "same image").unwrap();
+        std::fs::write(path("other.png"), "other image").unwrap();
+        let cat = Fingerprint::of_file(&path("renamed.png")).unwrap();
+        let same = Fingerprint::of_file(&path("copy1.png")).unwrap();
+
+        let matches = match_orphans(
+            &[("cat.png", cat), ("same.png", same)],
+            &[
+                path("renamed.png"),
+                path("copy1.png"),
+                path("copy2.png"),
+                path("other.png"),
+            ],
+        );
+
+        // 同じ内容のファイルが2つあるものは組にしない
+        assert_eq!(matches, vec![("cat.png", path("renamed.png"))]);
+    }
+}
@@ -0,0 +1,404 @@
+use std::io::Read;
+use std::path::Path;
+
+use crate::raw;
+
+// 形式の判定のためにファイル先頭から読み込むバイト数
+const HEADER_SIZE: u64 = 256;
+
+// SVGの寸法を探すためにファイル先頭から読み込む最大バイト数
+const SVG_SEARCH_LIMIT: u64 = 64 * 1024;
+
+// HEIC・HEIFを扱うか（heic フィーチャーでlibheifを組み込んだ場合のみ）
+// 組み込んでいない場合は表示できないので、画像として一覧に含めない
+const HEIC_SUPPORTED: bool = cfg!(feature = "heic");
+
+// アプリで扱う画像形式
+#[derive(Clone, Copy, serde::Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Avif,
+    Heic,
+    Bmp,
+    Tiff,
+    Ico,
+    Svg,
+    Jxl,
+    // カメラのRAW画像（CR2・CR3・NEF・ARW・DNG・RAF）
+    Raw,
+}
+
+impl ImageFormat {
+    // 拡張子（大文字小文字は区別しない）から画像形式を返す
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "gif" => Some(Self::Gif),
+            "webp" => Some(Self::Webp),
+            "avif" => Some(Self::Avif),
+            "heic" | "heif" if HEIC_SUPPORTED => Some(Self::Heic),
+            "bmp" => Some(Self::Bmp),
+            "tif" | "tiff" => Some(Self::Tiff),
+            "ico" => Some(Self::Ico),
+            "svg" => Some(Self::Svg),
+            "jxl" => Some(Self::Jxl),
+            ext if raw::is_raw_extension(ext) => Some(Self::Raw),
+            _ => None,
+        }
+    }
+
+    fn from_image_type(image_type: imagesize::ImageType) -> Option<Self> {
+        match image_type {
+            imagesize::ImageType::Png => Some(Self::Png),
+            imagesize::ImageType::Jpeg => Some(Self::Jpeg),
+            imagesize::ImageType::Gif => Some(Self::Gif),
+            imagesize::ImageType::Webp => Some(Self::Webp),
+            imagesize::ImageType::Avif => Some(Self::Avif),
+            imagesize::ImageType::Heif if HEIC_SUPPORTED => Some(Self::Heic),
+            imagesize::ImageType::Bmp => Some(Self::Bmp),
+            imagesize::ImageType::Tiff => Some(Self::Tiff),
+            imagesize::ImageType::Ico => Some(Self::Ico),
+            imagesize::ImageType::Jxl => Some(Self::Jxl),
+            _ => None,
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::Webp => "image/webp",
+            Self::Avif => "image/avif",
+            Self::Heic => "image/heic",
+            Self::Bmp => "image/bmp",
+            Self::Tiff => "image/tiff",
+            Self::Ico => "image/x-icon",
+            Self::Svg => "image/svg+xml",
+            Self::Jxl => "image/jxl",
+            // RAW画像はMIMEタイプが形式毎に異なるため、汎用のものにする
+            Self::Raw => "application/octet-stream",
+        }
+    }
+
+    // Webview（WebView2・WebKitGTK）でそのまま表示できない形式かどうか
+    // これらの形式はURIスキームのプロトコル経由で変換して表示する
+    pub fn needs_transcoding(self) -> bool {
+        matches!(self, Self::Heic | Self::Tiff | Self::Jxl | Self::Raw)
+    }
+}
+
+// ファイルの画像形式を判定する
+// 先頭バイト（マジックナンバー）から判定し、読み込めない場合（存在しない等）は拡張子から判定する
+// 中身から判定できない場合は、マジックナンバーを持たない形式（SVG・CR3やRAF等のRAW画像）の
+// 拡張子であればその形式とし、それ以外（拡張子だけが画像のテキストファイル等）は None を返す
+// マジックナンバーから扱えない形式と判定された場合は拡張子に関わらず None を返す
+// CR2・NEF・ARW・DNGはTIFFと同じ構造なので、拡張子がRAW画像のものであればRAW画像とする
+pub fn detect_image_format(path: &Path) -> Option<ImageFormat> {
+    let extension_format = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(ImageFormat::from_extension);
+    detect_from_header(read_header(path).as_deref(), extension_format)
+}
+
+// メモリ上の画像データ（アーカイブ内のエントリ等）の画像形式を判定する
+// 判定の方法は detect_image_format と同じで、拡張子はnameから取得する
+pub fn detect_image_format_from_bytes(name: &str, data: &[u8]) -> Option<ImageFormat> {
+    let extension_format = Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(ImageFormat::from_extension);
+    detect_from_header(Some(data), extension_format)
+}
+
+fn detect_from_header(
+    header: Option<&[u8]>,
+    extension_format: Option<ImageFormat>,
+) -> Option<ImageFormat> {
+    match header.map(imagesize::image_type) {
+        Some(Ok(imagesize::ImageType::Tiff)) if extension_format == Some(ImageFormat::Raw) => {
+            Some(ImageFormat::Raw)
+        }
+        Some(Ok(image_type)) => ImageFormat::from_image_type(image_type),
+        Some(Err(_)) => {
+            extension_format.filter(|format| matches!(format, ImageFormat::Svg | ImageFormat::Raw))
+        }
+        None => extension_format,
+    }
+}
+
+pub fn is_image_file(path: &Path) -> bool {
+    detect_image_format(path).is_some()
+}
+
+// 画像の幅・高さを返す
+// SVGはルート要素のwidth・height属性、またはviewBox属性から求める
+// RAW画像は埋め込まれたプレビューの寸法とする
+pub fn image_dimensions(path: &Path) -> Result<(u32, u32), String> {
+    match detect_image_format(path) {
+        Some(ImageFormat::Svg) => {
+            return svg_dimensions(path).ok_or_else(|| "SVG has no intrinsic size".to_string())
+        }
+        Some(ImageFormat::Raw) => return raw::preview_dimensions(path),
+        _ => {}
+    }
+    imagesize::size(path)
+        .map(|size| (size.width as u32, size.height as u32))
+        .map_err(|e| e.to_string())
+}
+
+// メモリ上の画像データの幅・高さを返す
+// 形式毎の扱いは image_dimensions と同じ
+pub fn image_dimensions_from_bytes(format: ImageFormat, data: &[u8]) -> Result<(u32, u32), String> {
+    match format {
+        ImageFormat::Svg => {
+            return parse_svg_dimensions(data)
+                .ok_or_else(|| "SVG has no intrinsic size".to_string())
+        }
+        ImageFormat::Raw => return raw::preview_dimensions_from_bytes(data),
+        _ => {}
+    }
+    imagesize::blob_size(data)
+        .map(|size| (size.width as u32, size.height as u32))
+        .map_err(|e| e.to_string())
+}
+
+fn read_header(path: &Path) -> Option<Vec<u8>> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut header = Vec::new();
+    file.take(HEADER_SIZE).read_to_end(&mut header).ok()?;
+    Some(header)
+}
+
+fn svg_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut data = Vec::new();
+    file.take(SVG_SEARCH_LIMIT).read_to_end(&mut data).ok()?;
+    parse_svg_dimensions(&data)
+}
+
+fn parse_svg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let data = &data[..data.len().min(SVG_SEARCH_LIMIT as usize)];
+    let text = String::from_utf8_lossy(data);
+
+    let start = text.find("<svg")?;
+    let end = text[start..].find('>')? + start;
+    let root = &text[start..end];
+
+    let width = get_svg_attribute(root, "width").and_then(parse_svg_length);
+    let height = get_svg_attribute(root, "height").and_then(parse_svg_length);
+    let view_box = get_svg_attribute(root, "viewBox").and_then(|view_box| {
+        let values: Vec<f64> = view_box
+            .split([' ', ','])
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse().ok())
+            .collect::<Option<_>>()?;
+        match values[..] {
+            [_, _, w, h] if w > 0.0 && h > 0.0 => Some((w, h)),
+            _ => None,
+        }
+    });
+
+    // width・heightの片方しかない場合はviewBoxの縦横比から求める
+    let (width, height) = match (width, height, view_box) {
+        (Some(w), Some(h), _) => (w, h),
+        (Some(w), None, Some((vw, vh))) => (w, w * vh / vw),
+        (None, Some(h), Some((vw, vh))) => (h * vw / vh, h),
+        (None, None, Some((vw, vh))) => (vw, vh),
+        _ => return None,
+    };
+    Some((width.round() as u32, height.round() as u32))
+}
+
+// 属性の値を返す（"name=" の直前が空白の場合のみ一致させる）
+fn get_svg_attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    // 改行・タブも属性の区切りとして扱う（1文字ずつの置き換えなので位置は変わらない）
+    let normalized = element.replace(['\n', '\t', '\r'], " ");
+    ['"', '\''].into_iter().find_map(|quote| {
+        let prefix = format!(" {name}={quote}");
+        let start = normalized.find(&prefix)? + prefix.len();
+        let end = element[start..].find(quote)? + start;
+        Some(&element[start..end])
+    })
+}
+
+// 長さの値をピクセル数として返す
+// 単位なしとpx以外（%やemなど）は表示環境に依存するため扱わない
+fn parse_svg_length(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let number = value.strip_suffix("px").unwrap_or(value);
+    number.parse().ok().filter(|v: &f64| *v > 0.0)
+}
+
+#[cfg(test)]
+pub mod test_files {
+    use std::io::Cursor;
+
+    // 小さいPNG画像のデータを返す
+    // 内容（フィンガープリント）が重ならないように、seed毎に色を変える
+    pub fn png(seed: u8) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        image::RgbImage::from_pixel(2, 2, image::Rgb([seed, 0, 0]))
+            .write_to(&mut data, image::ImageFormat::Png)
+            .expect("Failed to encode PNG");
+        data.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const PNG_HEADER: [u8; 24] = [
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, // PNG signature
+        0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52, // IHDR chunk
+        0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, // Width, Height
+    ];
+    const BMP_HEADER: [u8; 26] = [
+        0x42, 0x4D, 0x3A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1A, 0x00, 0x00, 0x00, 0x0C,
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x18, 0x00,
+    ];
+    const QOI_HEADER: [u8; 14] = [
+        0x71, 0x6F, 0x69, 0x66, // "qoif"
+        0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x04, 0x00,
+    ];
+
+    #[test]
+    fn test_detect_by_extension_when_not_readable() {
+        assert_eq!(
+            detect_image_format(Path::new("nonexistent.JPG")),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(
+            detect_image_format(Path::new("nonexistent.webp")),
+            Some(ImageFormat::Webp)
+        );
+        // 拡張子は完全一致で判定する
+        assert_eq!(detect_image_format(Path::new("notes.xjpg")), None);
+        assert_eq!(detect_image_format(Path::new("nonexistent")), None);
+    }
+
+    #[test]
+    fn test_detect_by_content() {
+        let temp_dir = TempDir::new().unwrap();
+
+        // 拡張子なし
+        let no_ext = temp_dir.path().join("IMG_0001");
+        fs::write(&no_ext, PNG_HEADER).unwrap();
+        assert_eq!(detect_image_format(&no_ext), Some(ImageFormat::Png));
+
+        // 拡張子と中身が異なる
+        let misnamed = temp_dir.path().join("photo.jpg");
+        fs::write(&misnamed, PNG_HEADER).unwrap();
+        assert_eq!(detect_image_format(&misnamed), Some(ImageFormat::Png));
+
+        // 中身から判定できない場合は、拡張子が画像のものでも画像としない
+        let broken = temp_dir.path().join("broken.gif");
+        fs::write(&broken, "fake image content").unwrap();
+        assert_eq!(detect_image_format(&broken), None);
+
+        // マジックナンバーを持たない形式は拡張子で判定する
+        for (name, content) in [
+            (
+                "icon.svg",
+                &b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"[..],
+            ),
+            ("IMG_0001.CR3", b"\0\0\0\x18ftypcrx \0\0\0\x01crx isom"),
+            ("DSCF0001.RAF", b"FUJIFILMCCD-RAW 0201FF383501"),
+        ] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            assert!(detect_image_format(&path).is_some(), "{name}");
+        }
+
+        let text = temp_dir.path().join("notes.txt");
+        fs::write(&text, "fake image content").unwrap();
+        assert_eq!(detect_image_format(&text), None);
+    }
+
+    #[test]
+    fn test_detect_unsupported_content() {
+        let temp_dir = TempDir::new().unwrap();
+        let qoi = temp_dir.path().join("image.png");
+        fs::write(&qoi, QOI_HEADER).unwrap();
+
+        assert_eq!(detect_image_format(&qoi), None);
+    }
+
+    #[test]
+    fn test_detect_additional_formats() {
+        let temp_dir = TempDir::new().unwrap();
+        let bmp = temp_dir.path().join("image.png");
+        fs::write(&bmp, BMP_HEADER).unwrap();
+        assert_eq!(detect_image_format(&bmp), Some(ImageFormat::Bmp));
+
+        for (name, format) in [
+            ("a.avif", ImageFormat::Avif),
+            ("a.tif", ImageFormat::Tiff),
+            ("a.ico", ImageFormat::Ico),
+            ("a.svg", ImageFormat::Svg),
+            ("a.jxl", ImageFormat::Jxl),
+            ("a.CR3", ImageFormat::Raw),
+            ("a.raf", ImageFormat::Raw),
+        ] {
+            assert_eq!(detect_image_format(Path::new(name)), Some(format));
+        }
+        // HEIC・HEIFは heic フィーチャーでlibheifを組み込んだ場合のみ扱う
+        let heic = HEIC_SUPPORTED.then_some(ImageFormat::Heic);
+        assert_eq!(detect_image_format(Path::new("a.HEIC")), heic);
+        assert_eq!(detect_image_format(Path::new("a.heif")), heic);
+    }
+
+    #[test]
+    fn test_detect_tiff_based_raw() {
+        let temp_dir = TempDir::new().unwrap();
+        let tiff_header = b"II*\0\x08\0\0\0\0\0\0\0";
+
+        let nef = temp_dir.path().join("DSC_0001.nef");
+        fs::write(&nef, tiff_header).unwrap();
+        assert_eq!(detect_image_format(&nef), Some(ImageFormat::Raw));
+
+        let tiff = temp_dir.path().join("scan.tif");
+        fs::write(&tiff, tiff_header).unwrap();
+        assert_eq!(detect_image_format(&tiff), Some(ImageFormat::Tiff));
+    }
+
+    #[test]
+    fn test_svg_dimensions() {
+        let temp_dir = TempDir::new().unwrap();
+        let svg = temp_dir.path().join("icon.svg");
+
+        fs::write(
+            &svg,
+            r#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" width="120px" height='80'></svg>"#,
+        )
+        .unwrap();
+        assert_eq!(image_dimensions(&svg), Ok((120, 80)));
+
+        fs::write(
+            &svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"
+                width="100%" viewBox="0 0 64 32"></svg>"#,
+        )
+        .unwrap();
+        assert_eq!(image_dimensions(&svg), Ok((64, 32)));
+
+        fs::write(
+            &svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" height="16" viewBox="0,0,64,32"/>"#,
+        )
+        .unwrap();
+        assert_eq!(image_dimensions(&svg), Ok((32, 16)));
+
+        fs::write(&svg, r#"<svg xmlns="http://www.w3.org/2000/svg"></svg>"#).unwrap();
+        assert!(image_dimensions(&svg).is_err());
+    }
+}
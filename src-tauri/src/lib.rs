@@ -1,137 +1,1020 @@
-use std::collections::HashMap;
-use std::io::{BufRead, Write};
-use std::path::Path;
-use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
 use tauri::{Emitter, Manager};
 
-const VIEWER_LABEL: &str = "viewer";
+mod archive;
+mod batch_rename;
+mod error;
+mod file_ops;
+mod fingerprint;
+mod image_format;
+mod metadata;
+mod raw;
+mod scan;
+mod session;
+mod sort;
+mod sort_bin;
+mod tag_db;
+mod tag_file;
+mod tag_index;
+mod tag_store;
+mod thumbnail;
+mod transcode;
+mod undo;
+mod watcher;
+
+use batch_rename::{RenameContext, RenameTemplate};
+use error::{CommandError, ErrorKind};
+use image_format::ImageFormat;
+use scan::{ScanOptions, Scanner};
+use session::Sessions;
+use sort::SortOrder;
+use sort_bin::{SortBin, SortBinStore, TransferMode};
+use tag_index::{TagDirIndex, TagQuery};
+use tag_store::{ModifiedDirTags, TagBackend, TagStorage, TagStore};
+use thumbnail::ThumbnailCache;
+use undo::DeletedImage;
+use watcher::{DirectoryWatcher, FileChange};
+
 const VIEWER_PAGE: &str = "viewer";
 
+// タグファイルを書き換えたときや、外から変更されたことを検知したときに全ウィンドウへ送るイベントの
+// ペイロード（tags-changed イベント）
+// tagsは変更後のディレクトリのすべてのタグ情報で、externalはアプリの外で変更された場合にtrue
+#[derive(Clone, serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TagsChanged {
+    dir_path: String,
+    tags: HashMap<String, Vec<String>>,
+    external: bool,
+}
+
 // idとpathsを持つcommandのレスポンス用のstruct
 #[derive(Clone, serde::Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct ImagePaths {
     id: i32,
     paths: Vec<String>,
+    // pathsの並び順
+    sort_order: SortOrder,
+}
+
+// 逐次モードのdropで送るnew-images-chunkイベントのペイロード
+// offsetはImagePaths.pathsにおけるpathsの先頭の位置
+#[derive(Clone, serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ImagePathsChunk {
+    id: i32,
+    offset: usize,
+    paths: Vec<String>,
+}
+
+// 逐次モードのdropで走査完了時に送るnew-images-completeイベントのペイロード
+// reorderedがtrueの場合、チャンクで送った順からsort_orderに従って並び替えられているので
+// get_prev_image_pathsで再取得する必要がある
+#[derive(Clone, serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ImagePathsCompletion {
+    id: i32,
+    total: usize,
+    reordered: bool,
+}
+
+// ディレクトリの監視で画像の追加・削除を検知した際に送るimages-added・images-removedイベントのペイロード
+// 追加された画像はImagePaths.pathsの末尾に追加されている
+#[derive(Clone, serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ImagePathsDiff {
+    id: i32,
+    paths: Vec<String>,
+}
+
+// ディレクトリの監視で画像の名前変更を検知した際に送るimages-renamedイベントのペイロード
+// 名前変更された画像はImagePaths.pathsの同じ位置のままパスが置き換えられている
+#[derive(Clone, serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ImagePathsRenamed {
+    id: i32,
+    renames: Vec<ImagePathRename>,
+}
+
+#[derive(Clone, serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ImagePathRename {
+    from: String,
+    to: String,
 }
 
-// 直近返したIDと画像ファイルのパスを保持する
-static IMAGE_PATHS: OnceLock<Mutex<ImagePaths>> = OnceLock::new();
+// ビューアのセッション（ウィンドウ毎の直近返したIDと画像ファイルのパス）を保持する
+static SESSIONS: OnceLock<Mutex<Sessions>> = OnceLock::new();
+
+// 画像のタグの保存先（タグファイルまたはデータベース）
+static TAG_STORAGE: OnceLock<TagStorage> = OnceLock::new();
+
+// タグのあるディレクトリの一覧（アプリの設定ディレクトリに保存する）
+static TAG_DIR_INDEX: OnceLock<Arc<TagDirIndex>> = OnceLock::new();
 
-// 画像のタグ情報をメモリに保持する
-// Directory(String) > FileName(String) > Tags(Vec<String>) のマップ
-type ImageTagsMap = HashMap<String, HashMap<String, Vec<String>>>;
-static IMAGE_TAGS: OnceLock<Mutex<ImageTagsMap>> = OnceLock::new();
+// サムネイルのキャッシュ（アプリのキャッシュディレクトリに保存する）
+static THUMBNAIL_CACHE: OnceLock<ThumbnailCache> = OnceLock::new();
 
-const TAG_FILE_NAME: &str = "IMAGE_TAG";
-const TAG_TEMP_FILE_NAME: &str = "IMAGE_TAG_TEMP";
+// 振り分け先の設定（アプリの設定ディレクトリに保存する）
+static SORT_BIN_STORE: OnceLock<SortBinStore> = OnceLock::new();
 
-// メイン画面への画像ファイルのドロップを処理するTauriコマンド
+// タグの変更を全ウィンドウに通知するためのハンドル（テストでは設定されず、通知しない）
+static APP_HANDLE: OnceLock<tauri::AppHandle> = OnceLock::new();
+
+// メイン画面またはビューアへの画像ファイルのドロップを処理するTauriコマンド
+// ビューアへのドロップはそのビューアのセッション、メイン画面へのドロップは最後に開いたセッションを
+// 更新する。new_sessionがtrueの場合は新しいビューアウィンドウ（セッション）を開く
+// chunk_sizeを指定した場合は逐次モードとなり、走査しながらchunk_size件ごとに
+// new-images-chunkイベントを送り、完了時にnew-images-completeイベントを送る
+// 走査中に同じセッションへの次のドロップがあった場合、走査中の処理は中断される
+// 画像一覧の確定後は元になったディレクトリを監視し、変更があればimages-added・images-removed・
+// images-renamedイベントで差分を送る
 // NOTE: Windows でのマルチウィンドウの問題対処のためasync関数として定義
 // https://qiita.com/kemoshumai/items/f0bfff31684a157ab9f3
 // 上記記事は2.0Beta版だが正式版にもKnown Issueとして記載されている
 // https://docs.rs/tauri/2.2.0/tauri/webview/struct.WebviewWindowBuilder.html
+#[allow(clippy::too_many_arguments)]
 #[tauri::command(async)]
-async fn drop(app: tauri::AppHandle, paths: Vec<String>) -> Result<(), String> {
-    let webview = app.get_webview_window(VIEWER_LABEL);
-    if webview.is_none() {
+async fn drop(
+    app: tauri::AppHandle,
+    window: tauri::WebviewWindow,
+    paths: Vec<String>,
+    options: Option<ScanOptions>,
+    sort_order: Option<SortOrder>,
+    chunk_size: Option<usize>,
+    new_session: Option<bool>,
+) -> Result<(), CommandError> {
+    archive::check_supported(&paths)?;
+    let window_label = open_viewer_session(&app, window.label(), new_session.unwrap_or(false));
+
+    let sort_order = sort_order.unwrap_or_default();
+    let options = options.unwrap_or_default();
+
+    if let Some(chunk_size) = chunk_size {
+        let scanner = Scanner::new(options, image_format::is_image_file)
+            .map_err(|e| CommandError::new(ErrorKind::InvalidInput, e))?;
+
+        // 空のリストで新しいIDを発行してから走査を始める
+        let image_paths = update_session_image_paths(&window_label, Vec::new(), sort_order)?;
+        emit_to_window(&app, &window_label, "new-images", Some(image_paths.clone()));
+
+        tauri::async_runtime::spawn_blocking(move || {
+            scan_in_chunks(
+                app,
+                window_label,
+                scanner,
+                paths,
+                image_paths.id,
+                chunk_size.max(1),
+            );
+        });
+        return Ok(());
+    }
+
+    let mut image_files = extract_image_files(paths.clone(), &options)
+        .map_err(|e| CommandError::new(ErrorKind::InvalidInput, e))?;
+    sort::sort_image_paths(&mut image_files, sort_order);
+
+    let image_paths = update_session_image_paths(&window_label, image_files, sort_order)?;
+    let scanner = Scanner::new(options, image_format::is_image_file)
+        .map_err(|e| CommandError::new(ErrorKind::InvalidInput, e))?;
+    watch_session_dirs(&app, &window_label, image_paths.id, &paths, scanner);
+    emit_to_window(&app, &window_label, "new-images", Some(image_paths));
+    Ok(())
+}
+
+// dropの対象となるセッションを決めて、そのビューアウィンドウのラベルを返す
+// 対象のセッションがなければ作成し、ウィンドウが開いていなければ開く
+fn open_viewer_session(app: &tauri::AppHandle, caller_label: &str, new_session: bool) -> String {
+    let window_label = {
+        let mut sessions = must_lock_sessions();
+        if let Some(session) = sessions.get_by_label(caller_label) {
+            session.window_label.clone()
+        } else if let Some(session) = sessions.latest_mut().filter(|_| !new_session) {
+            session.window_label.clone()
+        } else {
+            sessions.create().window_label.clone()
+        }
+    };
+
+    if app.get_webview_window(&window_label).is_none() {
         let webview = tauri::WebviewWindowBuilder::new(
-            &app,
-            VIEWER_LABEL,
+            app,
+            &window_label,
             tauri::WebviewUrl::App(VIEWER_PAGE.to_string().into()),
         )
         .build()
         .expect("failed to build webview");
 
+        // ウィンドウが閉じられたらセッションを破棄する
+        let closed_label = window_label.clone();
+        webview.on_window_event(move |event| {
+            if let tauri::WindowEvent::Destroyed = event {
+                must_lock_sessions().remove_by_label(&closed_label);
+            }
+        });
+
         webview.show().expect("failed to show webview");
     }
+    window_label
+}
 
-    let image_files = extract_image_files(paths);
+// セッションの画像一覧を新しいIDで置き換えて、そのImagePathsを返す
+// 置き換える前の画像一覧のディレクトリの監視は終了し、削除の履歴は破棄する
+fn update_session_image_paths(
+    window_label: &str,
+    paths: Vec<String>,
+    sort_order: SortOrder,
+) -> Result<ImagePaths, String> {
+    let mut sessions = must_lock_sessions();
+    let id = sessions.next_image_paths_id();
+    let session = sessions
+        .get_by_label_mut(window_label)
+        .ok_or_else(|| format!("session for {window_label} is already closed"))?;
+    session.image_paths = ImagePaths {
+        id,
+        paths,
+        sort_order,
+    };
+    session.watcher = None;
+    session.delete_journal.clear();
+    Ok(session.image_paths.clone())
+}
 
-    // Mutexでロックを取りつつIMAGE_PATHSを更新
-    let mut image_paths = IMAGE_PATHS
-        .get()
-        .expect("failed to get IMAGE_PATHS_MUTEX")
-        .lock()
-        .expect("failed to lock IMAGE_PATHS_MUTEX");
-    image_paths.id += 1;
-    image_paths.paths = image_files.clone();
+// 画像ファイルを走査しながらchunk_size件ごとにセッションに追加してnew-images-chunkイベントを送る
+// 走査完了後はsort_orderに従って並び替え、new-images-completeイベントを送ってディレクトリの監視を始める
+// セッションのImagePathsのIDがidから変わった（次のドロップがあった）場合や
+// ウィンドウが閉じられた場合はその時点で中断する
+fn scan_in_chunks<F: Fn(&Path) -> bool + Send + 'static>(
+    app: tauri::AppHandle,
+    window_label: String,
+    scanner: Scanner<F>,
+    paths: Vec<String>,
+    id: i32,
+    chunk_size: usize,
+) {
+    let is_current = || must_lock_sessions().current_image_paths_id(&window_label) == Some(id);
+
+    let mut chunk = Vec::with_capacity(chunk_size);
+    let flow = scanner.scan(paths.clone(), |path| {
+        if !is_current() {
+            return ControlFlow::Break(());
+        }
+        chunk.push(path);
+        if chunk.len() < chunk_size {
+            return ControlFlow::Continue(());
+        }
+        flush_image_paths_chunk(&app, &window_label, id, &mut chunk)
+    });
+    if flow.is_break() || flush_image_paths_chunk(&app, &window_label, id, &mut chunk).is_break() {
+        return;
+    }
 
-    app.emit("new-images", Some(image_paths.clone()))
-        .expect("failed to emit new-images event");
-    Ok(())
+    // EXIFの読み込み等で時間がかかる場合があるので、ロックを外して並び替える
+    let Some((mut sorted_paths, sort_order)) = must_lock_sessions()
+        .get_by_label(&window_label)
+        .map(|session| {
+            (
+                session.image_paths.paths.clone(),
+                session.image_paths.sort_order,
+            )
+        })
+    else {
+        return;
+    };
+    sort::sort_image_paths(&mut sorted_paths, sort_order);
+
+    let completion = {
+        let mut sessions = must_lock_sessions();
+        let Some(image_paths) = sessions
+            .get_by_label_mut(&window_label)
+            .map(|session| &mut session.image_paths)
+            .filter(|image_paths| image_paths.id == id)
+        else {
+            return;
+        };
+        let reordered = image_paths.paths != sorted_paths;
+        image_paths.paths = sorted_paths;
+        ImagePathsCompletion {
+            id,
+            total: image_paths.paths.len(),
+            reordered,
+        }
+    };
+    watch_session_dirs(&app, &window_label, id, &paths, scanner);
+    emit_to_window(&app, &window_label, "new-images-complete", completion);
+}
+
+// 溜まったチャンクをセッションに追加してnew-images-chunkイベントを送る
+// セッションのImagePathsのIDがidから変わっている場合は何もせずにBreakを返す
+fn flush_image_paths_chunk(
+    app: &tauri::AppHandle,
+    window_label: &str,
+    id: i32,
+    chunk: &mut Vec<String>,
+) -> ControlFlow<()> {
+    let paths = std::mem::take(chunk);
+    let offset = {
+        let mut sessions = must_lock_sessions();
+        let Some(image_paths) = sessions
+            .get_by_label_mut(window_label)
+            .map(|session| &mut session.image_paths)
+            .filter(|image_paths| image_paths.id == id)
+        else {
+            return ControlFlow::Break(());
+        };
+        if paths.is_empty() {
+            return ControlFlow::Continue(());
+        }
+        let offset = image_paths.paths.len();
+        image_paths.paths.extend(paths.iter().cloned());
+        offset
+    };
+    emit_to_window(
+        app,
+        window_label,
+        "new-images-chunk",
+        ImagePathsChunk { id, offset, paths },
+    );
+    ControlFlow::Continue(())
+}
+
+// セッションの画像一覧（ImagePathsのIDがidのもの）の元になったディレクトリの監視を始める
+// ドロップされたフォルダと各画像の親ディレクトリの直下を監視し、追加されたファイルはscannerで判定する
+// 監視を開始できなかった場合も、画像一覧の表示には影響しないので監視なしで続ける
+fn watch_session_dirs<F: Fn(&Path) -> bool + Send + 'static>(
+    app: &tauri::AppHandle,
+    window_label: &str,
+    id: i32,
+    dropped_paths: &[String],
+    scanner: Scanner<F>,
+) {
+    let mut sessions = must_lock_sessions();
+    let Some(session) = sessions
+        .get_by_label_mut(window_label)
+        .filter(|session| session.image_paths.id == id)
+    else {
+        return;
+    };
+    let dirs = watcher::watched_dirs(dropped_paths, &session.image_paths.paths);
+    let app = app.clone();
+    let label = window_label.to_string();
+    session.watcher = DirectoryWatcher::watch(&dirs, move |changes| {
+        apply_watched_changes(&app, &label, id, &scanner, changes)
+    })
+    .ok();
 }
 
-// パス文字列の配列を受け取って拡張子名から画像ファイルを抽出して返す関数
-// ただし、フォルダの場合は一階層だけ中身を見て画像ファイルを抽出する
-fn extract_image_files(paths: Vec<String>) -> Vec<String> {
-    let mut image_files = Vec::new();
-    let image_exts = ["png", "jpeg", "jpg", "gif", "webp"];
+// 監視で検知した変更をセッションの画像一覧に反映して、差分をイベントで送る
+// セッションの画像一覧がidから置き換わっている場合は何もしない
+fn apply_watched_changes<F: Fn(&Path) -> bool>(
+    app: &tauri::AppHandle,
+    window_label: &str,
+    id: i32,
+    scanner: &Scanner<F>,
+    changes: Vec<FileChange>,
+) {
+    // アーカイブの展開等で時間がかかる場合があるので、追加されたファイルの走査はロックを外して行う
+    let listed: HashMap<PathBuf, Vec<String>> = changes
+        .iter()
+        .filter_map(|change| match change {
+            FileChange::Created(path) | FileChange::Renamed { to: path, .. } => Some(path),
+            FileChange::Removed(_) => None,
+        })
+        .map(|path| (path.clone(), list_watched_images(scanner, path)))
+        .collect();
 
-    let is_image = |path: &str| -> bool {
-        let path_lower = path.to_lowercase();
-        image_exts.iter().any(|ext| path_lower.ends_with(ext))
+    let applied = {
+        let mut sessions = must_lock_sessions();
+        let Some(image_paths) = sessions
+            .get_by_label_mut(window_label)
+            .map(|session| &mut session.image_paths)
+            .filter(|image_paths| image_paths.id == id)
+        else {
+            return;
+        };
+        watcher::apply_changes(&mut image_paths.paths, changes, |path| {
+            listed.get(path).cloned().unwrap_or_default()
+        })
     };
 
-    for path in paths {
-        if is_image(&path) {
-            image_files.push(path);
-        } else {
-            let dir = std::fs::read_dir(path);
-            if dir.is_err() {
-                // ディレクトリでなければスキップ
-                continue;
+    if !applied.removed.is_empty() {
+        emit_to_window(
+            app,
+            window_label,
+            "images-removed",
+            ImagePathsDiff {
+                id,
+                paths: applied.removed,
+            },
+        );
+    }
+    if !applied.renamed.is_empty() {
+        let renames = applied
+            .renamed
+            .into_iter()
+            .map(|(from, to)| ImagePathRename { from, to })
+            .collect();
+        emit_to_window(
+            app,
+            window_label,
+            "images-renamed",
+            ImagePathsRenamed { id, renames },
+        );
+    }
+    if !applied.added.is_empty() {
+        emit_to_window(
+            app,
+            window_label,
+            "images-added",
+            ImagePathsDiff {
+                id,
+                paths: applied.added,
+            },
+        );
+    }
+}
+
+// 監視で見つかったファイルから画像一覧に追加する画像のパスを返す
+// フォルダは監視の対象外（直下のみを監視する）なので走査しない
+fn list_watched_images<F: Fn(&Path) -> bool>(scanner: &Scanner<F>, path: &Path) -> Vec<String> {
+    if !path.is_file() || scanner.is_filtered_out(path) {
+        return Vec::new();
+    }
+    let Some(path) = path.to_str() else {
+        return Vec::new();
+    };
+    let mut images = Vec::new();
+    let _ = scanner.scan(vec![path.to_string()], |image| {
+        images.push(image);
+        ControlFlow::Continue(())
+    });
+    images
+}
+
+// パス文字列の配列を受け取って画像ファイルを抽出して返す関数
+// フォルダの場合はオプションに従って再帰的に中身を見て画像ファイルを抽出する
+// アーカイブの場合は中の画像ファイルを "{アーカイブのパス}!/{エントリ名}" のパスで返す
+fn extract_image_files(paths: Vec<String>, options: &ScanOptions) -> Result<Vec<String>, String> {
+    scan::scan_image_files(paths, options, image_format::is_image_file)
+}
+
+// 呼び出し元のウィンドウのセッションで直近返したImagePaths を再び返すTauriコマンド
+// セッションがない場合は空のImagePathsを返す
+// NOTE: drop時に新規ウィンドウ作成+emitではlistenが間に合わない場合があるので
+// 新規ウィンドウ側から再取得するために利用する
+#[tauri::command]
+fn get_prev_image_paths(window: tauri::WebviewWindow) -> ImagePaths {
+    must_lock_sessions()
+        .get_by_label(window.label())
+        .map(|session| session.image_paths.clone())
+        .unwrap_or_else(|| ImagePaths {
+            id: 0,
+            paths: Vec::new(),
+            sort_order: SortOrder::default(),
+        })
+}
+
+// 渡されたパスのファイルをゴミ箱に移動するTauriコマンド
+// セキュリティ: 呼び出し元のウィンドウのセッションが管理している画像パスのみ削除を許可
+#[tauri::command]
+fn delete_file(window: tauri::WebviewWindow, path: String) -> Result<(), CommandError> {
+    delete_session_file(window.label(), path)
+}
+
+fn delete_session_file(window_label: &str, path: String) -> Result<(), CommandError> {
+    // まず、渡されたパスがセッションが管理している画像パスに含まれているかチェック
+    authorize_session_path(window_label, &path).map_err(|e| CommandError {
+        message: "unauthorized file deletion: path not in managed image list".to_string(),
+        ..e
+    })?;
+
+    file_ops::trash_image_file(&path)?;
+    record_session_deletions(window_label, &[&path]);
+    Ok(())
+}
+
+// delete_files・move_files・batch_renameの各ファイルの結果
+// （migrate_tags・register_tag_dirsでは各ディレクトリの結果）
+#[derive(serde::Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct FileOperationResult {
+    path: String,
+    // 移動・名前変更後のパス（move_files・batch_renameで成功した場合のみ）
+    destination: Option<String>,
+    // 失敗した場合のエラーメッセージ
+    // 移動・名前変更した後にタグを付け替えられなかった場合は、destinationとともに設定する
+    error: Option<String>,
+}
+
+// 渡されたパスのファイルをまとめてゴミ箱に移動するTauriコマンド
+// セキュリティ: 1つでもセッションが管理していないパスが含まれる場合は、何も削除せずにエラーを返す
+// 戻り値は渡された順の各ファイルの結果で、一部のファイルの失敗では中断しない
+#[tauri::command]
+fn delete_files(
+    window: tauri::WebviewWindow,
+    paths: Vec<String>,
+) -> Result<Vec<FileOperationResult>, CommandError> {
+    delete_session_files(window.label(), paths)
+}
+
+fn delete_session_files(
+    window_label: &str,
+    paths: Vec<String>,
+) -> Result<Vec<FileOperationResult>, CommandError> {
+    authorize_session_paths(window_label, &paths)?;
+
+    let results: Vec<FileOperationResult> = paths
+        .into_iter()
+        .map(|path| {
+            let error = file_ops::trash_image_file(&path).err().map(String::from);
+            FileOperationResult {
+                path,
+                destination: None,
+                error,
             }
-            for entry in dir.unwrap() {
-                let entry = entry.unwrap();
-                let path = entry.path();
-                if path.is_file() && is_image(path.to_str().unwrap()) {
-                    image_files.push(path.to_str().unwrap().to_string());
+        })
+        .collect();
+
+    let deleted: Vec<&str> = results
+        .iter()
+        .filter(|result| result.error.is_none())
+        .map(|result| result.path.as_str())
+        .collect();
+    record_session_deletions(window_label, &deleted);
+    Ok(results)
+}
+
+// 渡されたパスのファイルをまとめて指定されたフォルダに移動するTauriコマンド
+// 移動先に同名のファイルがあるものは上書きせずに失敗とする
+// 移動した画像のタグは移動先のタグファイルに付け替え、画像一覧の同じ位置のまま移動後のパスに置き換える
+// セキュリティ・戻り値はdelete_filesと同様
+#[tauri::command]
+fn move_files(
+    window: tauri::WebviewWindow,
+    paths: Vec<String>,
+    dest_dir: String,
+) -> Result<Vec<FileOperationResult>, CommandError> {
+    move_session_files(window.label(), paths, &dest_dir)
+}
+
+fn move_session_files(
+    window_label: &str,
+    paths: Vec<String>,
+    dest_dir: &str,
+) -> Result<Vec<FileOperationResult>, CommandError> {
+    let dest_dir = validate_directory_path(dest_dir)?;
+    authorize_session_paths(window_label, &paths)?;
+
+    let results: Vec<FileOperationResult> = paths
+        .into_iter()
+        .map(|path| {
+            // 移動後は元のパスでタグを引けなくなるので、先にタグのキーを求めておく
+            let tag_key = validate_and_parse_image_path(&path).ok();
+            match file_ops::move_image_file(&path, Path::new(&dest_dir)) {
+                Ok(destination) => {
+                    // タグを付け替えられなかった場合も移動は済んでいるので、移動後のパスとともにエラーを返す
+                    let error = tag_key.and_then(|(src_dir, src_name)| {
+                        let dest_name = Path::new(&destination).file_name()?.to_str()?;
+                        transfer_tags_entry(&src_dir, &src_name, &dest_dir, dest_name, true)
+                            .err()
+                            .map(Into::into)
+                    });
+                    FileOperationResult {
+                        path,
+                        destination: Some(destination),
+                        error,
+                    }
                 }
+                Err(e) => FileOperationResult {
+                    path,
+                    destination: None,
+                    error: Some(e.into()),
+                },
             }
+        })
+        .collect();
+
+    let moved: Vec<(&str, &str)> = results
+        .iter()
+        .filter_map(|result| Some((result.path.as_str(), result.destination.as_deref()?)))
+        .collect();
+    replace_session_paths(window_label, &moved);
+    Ok(results)
+}
+
+// 移動した画像を画像一覧の同じ位置のまま移動後のパスに置き換える
+// 移動先も画像一覧に含まれている場合は、重複しないように移動前のパスを取り除く
+fn replace_session_paths(window_label: &str, moved: &[(&str, &str)]) {
+    let mut sessions = must_lock_sessions();
+    let Some(session) = sessions.get_by_label_mut(window_label) else {
+        return;
+    };
+    let image_paths = &mut session.image_paths.paths;
+    for (from, to) in moved {
+        if image_paths.iter().any(|p| p == to) {
+            undo::remove_image(image_paths, from);
+        } else if let Some(path) = image_paths.iter_mut().find(|p| p == from) {
+            *path = to.to_string();
         }
     }
-    image_files
 }
 
-// 直近返したImagePaths を再び返すTauriコマンド
-// NOTE: drop時に新規ウィンドウ作成+emitではlistenが間に合わない場合があるので
-// 新規ウィンドウ側から再取得するために利用する
+// 画像を同じフォルダ内で名前変更するTauriコマンド
+// 画像のタグは新しい名前に付け替え、画像一覧のパスも同じ位置のまま置き換える
+// 戻り値は名前変更後のパス
+// セキュリティ: 呼び出し元のウィンドウのセッションが管理している画像のみ許可
 #[tauri::command]
-fn get_prev_image_paths() -> ImagePaths {
-    IMAGE_PATHS
-        .get()
-        .expect("failed to get IMAGE_PATHS_MUTEX")
-        .lock()
-        .expect("failed to lock IMAGE_PATHS_MUTEX")
-        .clone()
+fn rename_file(
+    window: tauri::WebviewWindow,
+    path: String,
+    new_name: String,
+) -> Result<String, CommandError> {
+    rename_session_file(window.label(), path, &new_name)
 }
 
-// 渡されたパスのファイルをゴミ箱に移動するTauriコマンド
-// セキュリティ: アプリが管理している画像パスのみ削除を許可
+fn rename_session_file(
+    window_label: &str,
+    path: String,
+    new_name: &str,
+) -> Result<String, CommandError> {
+    authorize_session_path(window_label, &path)?;
+    file_ops::check_image_file(&path)?;
+    // 名前変更後は元のパスでタグを引けなくなるので、先にタグのキーを求めておく
+    let (dir_path, old_name) = validate_and_parse_image_path(&path)?;
+
+    let dest = file_ops::rename_image_file(&path, new_name)?;
+    replace_session_paths(window_label, &[(&path, &dest)]);
+    if old_name != new_name {
+        rename_tags_entries(&dir_path, &[(old_name, new_name.to_string())])?;
+    }
+    Ok(dest)
+}
+
+// 画像をまとめてテンプレートに従った名前に変更するTauriコマンド
+// テンプレートでは {name} {ext} {seq:04} {exif_date:%Y%m%d} {tags:_} が使える（連番はpathsの順）
+// dry_runがtrueの場合は名前変更せずに、名前変更後のパスと衝突などのエラーを返す（プレビュー用）
+// エラーになる画像が1つでもある場合は、何も名前変更せずにエラーを返す
+// 名前変更した画像のタグは新しい名前に付け替え、画像一覧のパスも同じ位置のまま置き換える
+// タグを付け替えられなかった画像は、変更後のパスとともにその結果にエラーを記録する
+// セキュリティ: delete_filesと同様
 #[tauri::command]
-fn delete_file(path: String) -> Result<(), String> {
-    // まず、渡されたパスがアプリが管理している画像パスに含まれているかチェック
-    let image_paths = IMAGE_PATHS
-        .get()
-        .expect("failed to get IMAGE_PATHS_MUTEX")
-        .lock()
-        .expect("failed to lock IMAGE_PATHS_MUTEX");
+fn batch_rename(
+    window: tauri::WebviewWindow,
+    paths: Vec<String>,
+    template: String,
+    dry_run: bool,
+) -> Result<Vec<FileOperationResult>, CommandError> {
+    batch_rename_session_files(window.label(), paths, &template, dry_run)
+}
 
-    if !image_paths.paths.contains(&path) {
-        return Err("unauthorized file deletion: path not in managed image list".to_string());
+fn batch_rename_session_files(
+    window_label: &str,
+    paths: Vec<String>,
+    template: &str,
+    dry_run: bool,
+) -> Result<Vec<FileOperationResult>, CommandError> {
+    authorize_session_paths(window_label, &paths)?;
+    if paths.iter().collect::<HashSet<_>>().len() != paths.len() {
+        return Err(CommandError::new(
+            ErrorKind::InvalidInput,
+            "paths must not contain duplicates",
+        ));
     }
+    let template = RenameTemplate::parse(template)
+        .map_err(|e| CommandError::new(ErrorKind::InvalidInput, e))?;
 
-    let path_obj = std::path::Path::new(&path);
-    if path_obj.is_file() {
-        let trash = trash::delete(path_obj);
-        if trash.is_ok() {
-            Ok(())
-        } else {
-            Err(trash.err().unwrap().to_string())
+    let mut dir_tags_cache = HashMap::new();
+    let rendered: Vec<Result<RenderedFileName, String>> = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| render_new_file_name(&template, i + 1, path, &mut dir_tags_cache))
+        .collect();
+    let names: Vec<(&Path, Result<String, String>)> = paths
+        .iter()
+        .zip(&rendered)
+        .map(|(path, rendered)| {
+            let new_name = rendered.as_ref().map(|(new_name, _)| new_name.clone());
+            (Path::new(path.as_str()), new_name.map_err(|e| e.clone()))
+        })
+        .collect();
+    let planned = batch_rename::plan_renames(&names);
+
+    let mut results: Vec<FileOperationResult> = paths
+        .iter()
+        .zip(&planned)
+        .map(|(path, dest)| FileOperationResult {
+            path: path.clone(),
+            destination: dest
+                .as_ref()
+                .ok()
+                .map(|dest| dest.to_string_lossy().to_string()),
+            error: dest.as_ref().err().cloned(),
+        })
+        .collect();
+    if dry_run {
+        return Ok(results);
+    }
+    let error_count = results
+        .iter()
+        .filter(|result| result.error.is_some())
+        .count();
+    if error_count > 0 {
+        return Err(CommandError::new(
+            ErrorKind::InvalidInput,
+            format!("{error_count} images cannot be renamed"),
+        ));
+    }
+
+    // 名前が変わらない画像は何もしない
+    let renames: Vec<(usize, &Path, &Path)> = planned
+        .iter()
+        .enumerate()
+        .filter_map(|(i, dest)| {
+            let src = Path::new(paths[i].as_str());
+            let dest = dest.as_deref().ok()?;
+            (src != dest).then_some((i, src, dest))
+        })
+        .collect();
+    let pairs: Vec<(&Path, &Path)> = renames.iter().map(|&(_, src, dest)| (src, dest)).collect();
+    let applied = batch_rename::apply_renames(&pairs);
+
+    let mut renamed_paths = HashMap::new();
+    // ディレクトリ毎の（変更前の名前, 変更後の名前）と、その画像の結果の位置
+    let mut tag_renames: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    let mut tag_rename_indices: HashMap<String, Vec<usize>> = HashMap::new();
+    for (&(i, _, _), applied) in renames.iter().zip(applied) {
+        let result = &mut results[i];
+        if let Err(e) = applied {
+            result.destination = None;
+            result.error = Some(e);
+            continue;
+        }
+        if let Some(destination) = &result.destination {
+            renamed_paths.insert(result.path.clone(), destination.clone());
+        }
+        if let Ok((new_name, (dir_path, file_name))) = &rendered[i] {
+            tag_renames
+                .entry(dir_path.clone())
+                .or_default()
+                .push((file_name.clone(), new_name.clone()));
+            tag_rename_indices
+                .entry(dir_path.clone())
+                .or_default()
+                .push(i);
+        }
+    }
+
+    rename_session_paths(window_label, &renamed_paths);
+    // 名前変更は済んでいるので、タグを付け替えられなかったディレクトリがあっても残りのディレクトリを続け、
+    // そのディレクトリの画像の結果にエラーを記録する
+    for (dir_path, renames) in tag_renames {
+        if let Err(e) = rename_tags_entries(&dir_path, &renames) {
+            let message = String::from(e);
+            for &i in &tag_rename_indices[&dir_path] {
+                results[i].error = Some(message.clone());
+            }
+        }
+    }
+    Ok(results)
+}
+
+// テンプレートから作った新しいファイル名と、名前変更前のタグのキー（ディレクトリ, ファイル名）
+type RenderedFileName = (String, (String, String));
+
+// テンプレートから画像の新しいファイル名を作り、タグのキーと合わせて返す
+// 名前変更後は元のパスでタグを引けなくなるので、タグのキーは名前変更前に求めておく
+// タグはディレクトリ毎にdir_tags_cacheに読み込んで使い回す
+fn render_new_file_name(
+    template: &RenameTemplate,
+    seq: usize,
+    path: &str,
+    dir_tags_cache: &mut HashMap<String, HashMap<String, Vec<String>>>,
+) -> Result<RenderedFileName, String> {
+    let path_obj = file_ops::check_image_file(path)?;
+    let (dir_path, file_name) = validate_and_parse_image_path(path)?;
+    let tags = if template.uses_tags() {
+        if !dir_tags_cache.contains_key(&dir_path) {
+            let dir_tags = load_tags_in_dir(dir_path.clone())?;
+            dir_tags_cache.insert(dir_path.clone(), dir_tags);
+        }
+        dir_tags_cache[&dir_path]
+            .get(&file_name)
+            .cloned()
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    let capture_date = if template.uses_exif_date() {
+        metadata::read_capture_date(path_obj)
+    } else {
+        None
+    };
+    let new_name = template.render(&RenameContext {
+        seq,
+        path: path_obj,
+        capture_date: capture_date.as_deref(),
+        tags: &tags,
+    })?;
+    Ok((new_name, (dir_path, file_name)))
+}
+
+// 名前変更した画像を画像一覧の同じ位置のまま新しいパスに置き換える
+// 入れ替えにも対応するため、すべての画像を同時に置き換える
+fn rename_session_paths(window_label: &str, renamed: &HashMap<String, String>) {
+    let mut sessions = must_lock_sessions();
+    let Some(session) = sessions.get_by_label_mut(window_label) else {
+        return;
+    };
+    for path in &mut session.image_paths.paths {
+        if let Some(to) = renamed.get(path) {
+            *path = to.clone();
+        }
+    }
+}
+
+// 保存されている振り分け先の一覧を返すTauriコマンド
+#[tauri::command]
+fn get_sort_bins() -> Result<Vec<SortBin>, CommandError> {
+    must_get_sort_bin_store().load()
+}
+
+// 振り分け先の一覧を保存するTauriコマンド
+// 名前が空・重複している場合や、フォルダが絶対パスでない場合はエラーを返す
+#[tauri::command]
+fn set_sort_bins(bins: Vec<SortBin>) -> Result<(), CommandError> {
+    must_get_sort_bin_store().save(&bins)
+}
+
+// 画像を名前で指定した振り分け先のフォルダに移動またはコピーするTauriコマンド
+// 同名のファイルがある場合は振り分け先の設定（連番を付ける・上書き・スキップ）に従う
+// 画像のタグは振り分け先のタグファイルに引き継ぎ、移動の場合は画像一覧のパスも置き換える
+// 戻り値は振り分け後のパスで、スキップした場合は None
+// セキュリティ: 呼び出し元のウィンドウのセッションが管理している画像のみ許可
+#[tauri::command]
+fn send_to_sort_bin(
+    window: tauri::WebviewWindow,
+    path: String,
+    bin_name: String,
+    mode: Option<TransferMode>,
+) -> Result<Option<String>, CommandError> {
+    send_session_file_to_sort_bin(
+        must_get_sort_bin_store(),
+        window.label(),
+        path,
+        &bin_name,
+        mode.unwrap_or_default(),
+    )
+}
+
+fn send_session_file_to_sort_bin(
+    store: &SortBinStore,
+    window_label: &str,
+    path: String,
+    bin_name: &str,
+    mode: TransferMode,
+) -> Result<Option<String>, CommandError> {
+    authorize_session_path(window_label, &path)?;
+    let path_obj = file_ops::check_image_file(&path)?;
+    let bin = store.find(bin_name)?;
+    let dest_dir = validate_directory_path(&bin.dir)?;
+    // 移動後は元のパスでタグを引けなくなるので、先にタグのキーを求めておく
+    let (src_dir, src_name) = validate_and_parse_image_path(&path)?;
+
+    let Some(dest) = sort_bin::transfer_file(path_obj, Path::new(&dest_dir), mode, bin.collision)?
+    else {
+        return Ok(None);
+    };
+    let dest_str = dest
+        .to_str()
+        .ok_or_else(|| {
+            CommandError::new(
+                ErrorKind::InvalidInput,
+                "Failed to convert destination path to string",
+            )
+        })?
+        .to_string();
+    let dest_name = dest
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| CommandError::new(ErrorKind::InvalidInput, "Failed to get file name"))?;
+
+    if mode == TransferMode::Move {
+        replace_session_paths(window_label, &[(&path, &dest_str)]);
+    }
+
+    transfer_tags_entry(
+        &src_dir,
+        &src_name,
+        &dest_dir,
+        dest_name,
+        mode == TransferMode::Move,
+    )?;
+    Ok(Some(dest_str))
+}
+
+// 削除した画像をセッションの画像一覧から取り除き、削除の取り消し用に元の位置を記録する
+fn record_session_deletions(window_label: &str, paths: &[&str]) {
+    let mut sessions = must_lock_sessions();
+    let Some(session) = sessions.get_by_label_mut(window_label) else {
+        return;
+    };
+    for path in paths {
+        if let Some(index) = undo::remove_image(&mut session.image_paths.paths, path) {
+            session.delete_journal.record(path.to_string(), index);
+        }
+    }
+}
+
+// 削除を取り消してゴミ箱から復元するTauriコマンド
+// 呼び出し元のセッションで最後に削除したものから順にcount件（省略時は1件）を復元し、
+// 画像一覧の削除前の位置に戻す。戻り値は復元した画像と画像一覧での位置（復元した順）
+// 途中で復元に失敗した場合はそこで止めて、それまでに復元した画像を返す
+// （1件も復元できなかった場合はエラー。失敗した画像は履歴から取り除く）
+#[tauri::command]
+fn undo_delete(
+    window: tauri::WebviewWindow,
+    count: Option<usize>,
+) -> Result<Vec<DeletedImage>, CommandError> {
+    undo_session_delete(window.label(), count.unwrap_or(1))
+}
+
+fn undo_session_delete(
+    window_label: &str,
+    count: usize,
+) -> Result<Vec<DeletedImage>, CommandError> {
+    let mut restored = Vec::new();
+    for _ in 0..count {
+        let Some(image) = must_lock_sessions()
+            .get_by_label_mut(window_label)
+            .and_then(|session| session.delete_journal.pop())
+        else {
+            break;
+        };
+
+        // ゴミ箱の一覧の取得に時間がかかる場合があるので、ロックを外して復元する
+        if let Err(e) = undo::restore_from_trash(&image.path) {
+            if restored.is_empty() {
+                return Err(e);
+            }
+            break;
         }
+
+        let mut sessions = must_lock_sessions();
+        let Some(session) = sessions.get_by_label_mut(window_label) else {
+            break;
+        };
+        let index = undo::reinsert_image(&mut session.image_paths.paths, &image);
+        restored.push(DeletedImage {
+            path: image.path,
+            index,
+        });
+    }
+    Ok(restored)
+}
+
+// セキュリティ: 渡されたパスが呼び出し元のウィンドウのセッションが管理している画像パスか検証する
+fn authorize_session_path(window_label: &str, path: &str) -> Result<(), CommandError> {
+    let sessions = must_lock_sessions();
+    let is_managed = sessions
+        .get_by_label(window_label)
+        .is_some_and(|session| session.image_paths.paths.iter().any(|p| p == path));
+    if is_managed {
+        Ok(())
     } else {
-        Err("not a file".to_string())
+        Err(CommandError::new(
+            ErrorKind::Unauthorized,
+            "unauthorized path: path not in managed image list",
+        )
+        .with_path(path))
+    }
+}
+
+// セキュリティ: 渡されたパスがすべて呼び出し元のウィンドウのセッションが管理している画像パスか検証する
+fn authorize_session_paths(window_label: &str, paths: &[String]) -> Result<(), CommandError> {
+    let sessions = must_lock_sessions();
+    let managed: HashSet<&str> = sessions
+        .get_by_label(window_label)
+        .map(|session| {
+            session
+                .image_paths
+                .paths
+                .iter()
+                .map(String::as_str)
+                .collect()
+        })
+        .unwrap_or_default();
+    match paths.iter().find(|path| !managed.contains(path.as_str())) {
+        Some(path) => Err(CommandError::new(
+            ErrorKind::Unauthorized,
+            format!("unauthorized path: {path} is not in managed image list"),
+        )
+        .with_path(path)),
+        None => Ok(()),
     }
 }
 
@@ -140,63 +1023,283 @@ fn delete_file(path: String) -> Result<(), String> {
 #[serde(rename_all = "camelCase")]
 struct FileInfo {
     size: u64,
+    // ファイルに保存されている幅・高さ
     width: u32,
     height: u32,
+    // EXIFのOrientationを適用した表示上の幅・高さ
+    display_width: u32,
+    display_height: u32,
+    // EXIFのOrientation（1〜8、タグがない場合は1）
+    orientation: u16,
+    format: ImageFormat,
 }
 
 #[tauri::command]
-fn get_file_info(file_path: String) -> Result<FileInfo, String> {
-    let path = Path::new(&file_path);
-
-    // ファイルの存在確認
-    if !path.exists() {
-        return Err(format!("{file_path} does not exist"));
+fn get_file_info(file_path: String) -> Result<FileInfo, CommandError> {
+    if let Some((archive_path, entry_name)) = archive::split_entry_path(&file_path) {
+        return get_archive_entry_info(Path::new(archive_path), entry_name)
+            .map_err(|e| e.with_path(file_path.as_str()));
     }
 
-    if !path.is_file() {
-        return Err(format!("{file_path} is not a file"));
-    }
+    let path = Path::new(&file_path);
+    check_file_exists(&file_path)?;
 
     // ファイルサイズを取得
-    let metadata =
-        std::fs::metadata(path).map_err(|e| format!("Failed to get file metadata: {e}"))?;
+    let metadata = std::fs::metadata(path).map_err(|e| {
+        CommandError::io(format!("Failed to get file metadata: {e}"), &e).with_path(&file_path)
+    })?;
     let file_size = metadata.len();
 
     // 画像の寸法を取得
-    let (width, height) = match imagesize::size(path) {
-        Ok(size) => (size.width as u32, size.height as u32),
-        Err(e) => return Err(format!("Failed to get image dimensions: {e}")),
-    };
+    let (width, height) = image_format::image_dimensions(path).map_err(|e| {
+        CommandError::new(
+            ErrorKind::InvalidData,
+            format!("Failed to get image dimensions: {e}"),
+        )
+        .with_path(&file_path)
+    })?;
+
+    // 画像形式を取得
+    let format = image_format::detect_image_format(path)
+        .ok_or_else(|| unsupported_format_error(&file_path))?;
+
+    // EXIFのOrientationから表示上の寸法を求める
+    let orientation = metadata::read_orientation(path);
+    let (display_width, display_height) = metadata::display_dimensions(width, height, orientation);
 
     Ok(FileInfo {
         size: file_size,
         width,
         height,
+        display_width,
+        display_height,
+        orientation,
+        format,
     })
 }
 
+// アーカイブ内のエントリのファイル情報を返す
+// サイズは展開後のバイト数とする
+fn get_archive_entry_info(archive_path: &Path, entry_name: &str) -> Result<FileInfo, CommandError> {
+    check_file_exists(&archive_path.to_string_lossy())?;
+
+    let data = archive::read_entry(archive_path, entry_name)
+        .map_err(|e| CommandError::new(ErrorKind::Io, e))?;
+    let format = image_format::detect_image_format_from_bytes(entry_name, &data)
+        .ok_or_else(|| CommandError::new(ErrorKind::UnsupportedFormat, UNSUPPORTED_FORMAT))?;
+    let (width, height) =
+        image_format::image_dimensions_from_bytes(format, &data).map_err(|e| {
+            CommandError::new(
+                ErrorKind::InvalidData,
+                format!("Failed to get image dimensions: {e}"),
+            )
+        })?;
+    let orientation = metadata::read_orientation_from_bytes(format, &data);
+    let (display_width, display_height) = metadata::display_dimensions(width, height, orientation);
+
+    Ok(FileInfo {
+        size: data.len() as u64,
+        width,
+        height,
+        display_width,
+        display_height,
+        orientation,
+        format,
+    })
+}
+
+// 画像のメタデータ（EXIF・XMP・IPTC）を取得するTauriコマンド
+// 取得できなかったフィールドは null になる
+#[tauri::command(async)]
+fn get_image_metadata(file_path: String) -> Result<metadata::ImageMetadata, CommandError> {
+    if let Some((archive_path, entry_name)) = archive::split_entry_path(&file_path) {
+        return get_archive_entry_metadata(Path::new(archive_path), entry_name)
+            .map_err(|e| e.with_path(file_path.as_str()));
+    }
+
+    let path = Path::new(&file_path);
+    check_file_exists(&file_path)?;
+
+    if !image_format::is_image_file(path) {
+        return Err(unsupported_format_error(&file_path));
+    }
+
+    Ok(metadata::read_image_metadata(path))
+}
+
+// アーカイブ内のエントリのメタデータを返す
+fn get_archive_entry_metadata(
+    archive_path: &Path,
+    entry_name: &str,
+) -> Result<metadata::ImageMetadata, CommandError> {
+    check_file_exists(&archive_path.to_string_lossy())?;
+
+    let data = archive::read_entry(archive_path, entry_name)
+        .map_err(|e| CommandError::new(ErrorKind::Io, e))?;
+    let format = image_format::detect_image_format_from_bytes(entry_name, &data)
+        .ok_or_else(|| CommandError::new(ErrorKind::UnsupportedFormat, UNSUPPORTED_FORMAT))?;
+    Ok(metadata::read_image_metadata_from_bytes(format, &data))
+}
+
+// サムネイルを生成・キャッシュして、そのファイルのパスを返すTauriコマンド
+// フロントエンドからはアセットプロトコル経由で読み込む
+// max_edgeはサムネイルの長辺のピクセル数
+#[tauri::command(async)]
+fn get_thumbnail(path: String, max_edge: Option<u32>) -> Result<String, CommandError> {
+    let path_obj = Path::new(&path);
+    check_file_exists(&path)?;
+
+    let format = image_format::detect_image_format(path_obj)
+        .ok_or_else(|| unsupported_format_error(&path))?;
+
+    // SVGは縮小しても画質が落ちないので、元のファイルをそのまま使う
+    if format == ImageFormat::Svg {
+        return Ok(path);
+    }
+
+    let thumbnail_path = THUMBNAIL_CACHE
+        .get()
+        .expect("failed to get THUMBNAIL_CACHE")
+        .get_thumbnail(path_obj, max_edge.unwrap_or(thumbnail::DEFAULT_MAX_EDGE))
+        .map_err(|e| CommandError::new(ErrorKind::InvalidData, e).with_path(path.as_str()))?;
+
+    thumbnail_path
+        .to_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| {
+            CommandError::new(
+                ErrorKind::Other,
+                "Failed to convert thumbnail path to string",
+            )
+        })
+}
+
+// Webviewで表示できない形式（TIFF・JPEG XL等）の画像をPNGに変換して返すURIスキームのプロトコル
+// URIのパスはパーセントエンコードされた画像ファイルのパス
+// アーカイブ内のエントリのパスの場合は、ディスクに書き出さずに展開して返す
+fn handle_image_protocol(
+    request: tauri::http::Request<Vec<u8>>,
+    responder: tauri::UriSchemeResponder,
+) {
+    use tauri::http::{header, Response};
+
+    let uri_path = request.uri().path().to_string();
+    // 変換に時間がかかる場合があるので、別スレッドで処理する
+    tauri::async_runtime::spawn_blocking(move || {
+        let response = match load_protocol_image(&uri_path) {
+            Ok((data, mime_type)) => Response::builder()
+                .status(tauri::http::StatusCode::OK)
+                .header(header::CONTENT_TYPE, mime_type)
+                .body(data),
+            Err((status, message)) => Response::builder()
+                .status(status)
+                .header(header::CONTENT_TYPE, "text/plain")
+                .body(message.into_bytes()),
+        };
+        responder.respond(response.expect("failed to build image protocol response"));
+    });
+}
+
+fn load_protocol_image(
+    uri_path: &str,
+) -> Result<(Vec<u8>, &'static str), (tauri::http::StatusCode, String)> {
+    use tauri::http::StatusCode;
+
+    let path = transcode::decode_uri_path(uri_path).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let path_str = path.to_string_lossy();
+    if let Some((archive_path, entry_name)) = archive::split_entry_path(&path_str) {
+        let archive_path = Path::new(archive_path);
+        if !archive_path.is_file() {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("{} is not a file", archive_path.display()),
+            ));
+        }
+        return transcode::load_archive_entry_for_webview(archive_path, entry_name)
+            .map_err(|e| (StatusCode::UNSUPPORTED_MEDIA_TYPE, e));
+    }
+    if !path.is_file() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("{} is not a file", path.display()),
+        ));
+    }
+    transcode::load_for_webview(&path).map_err(|e| (StatusCode::UNSUPPORTED_MEDIA_TYPE, e))
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    IMAGE_PATHS
-        .set(Mutex::new(ImagePaths {
-            id: 0,
-            paths: Vec::new(),
-        }))
-        .expect("failed to set IMAGE_PATHS_MUTEX");
-
-    IMAGE_TAGS
-        .set(Mutex::new(HashMap::new()))
-        .expect("failed to set IMAGE_TAGS_MUTEX");
+    SESSIONS
+        .set(Mutex::new(Sessions::default()))
+        .expect("failed to set SESSIONS_MUTEX");
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .register_asynchronous_uri_scheme_protocol(
+            transcode::URI_SCHEME,
+            |_ctx, request, responder| handle_image_protocol(request, responder),
+        )
+        .setup(|app| {
+            let thumbnail_dir = app
+                .path()
+                .app_cache_dir()?
+                .join(thumbnail::THUMBNAIL_DIR_NAME);
+            let _ = THUMBNAIL_CACHE.set(ThumbnailCache::new(
+                thumbnail_dir,
+                thumbnail::DEFAULT_CACHE_MAX_BYTES,
+            ));
+            let sort_bins_path = app
+                .path()
+                .app_config_dir()?
+                .join(sort_bin::SORT_BINS_FILE_NAME);
+            let _ = SORT_BIN_STORE.set(SortBinStore::new(sort_bins_path));
+            let tag_storage_path = app
+                .path()
+                .app_config_dir()?
+                .join(tag_store::TAG_STORAGE_FILE_NAME);
+            let tag_database_path = app
+                .path()
+                .app_data_dir()?
+                .join(tag_store::TAG_DATABASE_FILE_NAME);
+            let tag_index_path = app
+                .path()
+                .app_config_dir()?
+                .join(tag_index::TAG_INDEX_FILE_NAME);
+            let tag_dir_index = Arc::new(TagDirIndex::new(Some(tag_index_path)));
+            let _ = TAG_STORAGE.set(TagStorage::new(
+                Some(tag_storage_path),
+                Some(tag_database_path),
+                Some(tag_dir_index.clone()),
+            ));
+            let _ = TAG_DIR_INDEX.set(tag_dir_index);
+            let _ = APP_HANDLE.set(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             drop,
             get_prev_image_paths,
             delete_file,
+            undo_delete,
+            delete_files,
+            move_files,
+            rename_file,
+            batch_rename,
+            get_sort_bins,
+            set_sort_bins,
+            send_to_sort_bin,
             load_tags_in_dir,
+            reload_tags_in_dir,
+            get_tag_backend,
+            set_tag_backend,
+            migrate_tags,
+            search_by_tags,
+            get_tag_dirs,
+            register_tag_dirs,
+            unregister_tag_dirs,
             save_tags,
             get_file_info,
+            get_image_metadata,
+            get_thumbnail,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -204,73 +1307,195 @@ pub fn run() {
 
 // --- タグ関連 --- //
 
-// NOTE: 既知の問題
-// 複数のウィンドウで同じディレクトリを開いている場合、
-// タグファイルのロックや（最初に読み込んだ後の）同期はしていないので
-// 後からタグを更新した方の情報で上書きされてしまう
+// タグはディレクトリ毎のタグファイルか、アプリのデータベースに保存する（tag_storeを参照）
+// 複数のウィンドウ・複数のアプリで同じディレクトリのタグを編集しても上書きし合わないように、
+// 保存するときはロックしてから保存先を読み直し、その編集だけを反映して書き込む
+// 書き込んだ後はtags-changedイベントで全ウィンドウにディレクトリのタグ情報を送る
+// エディタや同期ツール、他のアプリなどで外から変更された場合は、次にタグ情報を取得するときに
+// 読み直して、同じくtags-changedイベントで送る
 
 // 指定されたディレクトリのタグ情報をロード・返却するTauriコマンド
 #[tauri::command]
-fn load_tags_in_dir(dir_path: String) -> Result<HashMap<String, Vec<String>>, String> {
+fn load_tags_in_dir(dir_path: String) -> Result<HashMap<String, Vec<String>>, CommandError> {
     // パス検証: パストラバーサル攻撃を防ぐ
     let validated_dir_path = validate_directory_path(&dir_path)?;
+    get_dir_tags(&validated_dir_path, false)
+}
 
-    let mut tags_map = IMAGE_TAGS
-        .get()
-        .expect("failed to get IMAGE_TAGS_MUTEX")
-        .lock()
-        .expect("failed to lock IMAGE_TAGS_MUTEX");
+// 指定されたディレクトリのタグファイルを（変更されていなくても）読み直して返却するTauriコマンド
+#[tauri::command]
+fn reload_tags_in_dir(dir_path: String) -> Result<HashMap<String, Vec<String>>, CommandError> {
+    let validated_dir_path = validate_directory_path(&dir_path)?;
+    get_dir_tags(&validated_dir_path, true)
+}
 
-    if !tags_map.contains_key(&validated_dir_path) {
-        let tag_map = parse_tags_file(&validated_dir_path)?;
-        tags_map.insert(validated_dir_path.clone(), tag_map);
+// ディレクトリのタグ情報を現在の保存先から返す
+// 前回取得した後にアプリの外で変更されていたり、名前変更・移動された画像にタグを付け直したりした場合は、
+// 全ウィンドウに通知する
+// forceがtrueの場合は、キャッシュがあっても読み直す
+fn get_dir_tags(dir_path: &str, force: bool) -> Result<HashMap<String, Vec<String>>, CommandError> {
+    let loaded = must_get_tag_storage().active()?.load_dir(dir_path, force)?;
+    if loaded.external || loaded.relinked {
+        notify_tags_changed(dir_path, loaded.tags.clone(), loaded.external);
     }
-
-    let result = tags_map
-        .get(&validated_dir_path)
-        .cloned()
-        .unwrap_or_default();
-    Ok(result)
+    for (relinked_dir, tags) in loaded.relinked_dirs {
+        notify_tags_changed(&relinked_dir, tags, false);
+    }
+    register_tag_dir(dir_path, &loaded.tags);
+    Ok(loaded.tags)
 }
 
-// 指定されたディレクトリのタグ情報を読み取って HashMap<String, Vec<String>> を返す
-fn parse_tags_file(dir_path: &str) -> Result<HashMap<String, Vec<String>>, String> {
-    let (tag_file_name, _) =
-        get_tag_file_names(dir_path.to_string()).expect("failed to get tag file names");
+// タグのあるディレクトリを、タグで検索するディレクトリの一覧に追加する
+// 一覧を保存できなくても、タグの読み込み・保存は失敗させない
+fn register_tag_dir(dir_path: &str, tags: &HashMap<String, Vec<String>>) {
+    if !tags.is_empty() {
+        let _ = must_get_tag_dir_index().register(dir_path);
+    }
+}
 
-    if !std::path::Path::new(&tag_file_name).exists() {
-        return Ok(HashMap::new());
+// タグのあるディレクトリの一覧から、条件に一致するタグの付いた画像を検索するTauriコマンド
+// 結果は画像のパスの一覧で、dropに渡せば新しい画像リストとして開ける
+// 読み込めないディレクトリ（外したドライブなど）は一覧に残したまま飛ばす
+#[tauri::command]
+fn search_by_tags(query: TagQuery) -> Result<Vec<String>, CommandError> {
+    if query.tags.is_empty() {
+        return Err(CommandError::new(
+            ErrorKind::InvalidInput,
+            "No tags to search for",
+        ));
+    }
+    for tag in &query.tags {
+        validate_tag(tag)?;
     }
 
-    let file = std::fs::File::open(tag_file_name.clone())
-        .unwrap_or_else(|_| panic!("failed to open tag file: {}", tag_file_name.clone()));
-    let reader = std::io::BufReader::new(file);
-    let mut result = HashMap::new();
-    for line in reader.lines() {
-        let line = line.expect("failed to read line");
-        let (file_name, tags) = parse_tag_line(&line);
-        result.insert(file_name, tags);
+    let mut paths = Vec::new();
+    for dir_path in must_get_tag_dir_index().dirs() {
+        let Ok(validated_dir_path) = validate_directory_path(&dir_path) else {
+            continue;
+        };
+        let Ok(dir_tags) = get_dir_tags(&validated_dir_path, false) else {
+            continue;
+        };
+        paths.extend(tag_index::search_dir(
+            &validated_dir_path,
+            &dir_tags,
+            &query,
+        ));
     }
+    sort::sort_image_paths(&mut paths, SortOrder::default());
+    Ok(paths)
+}
 
-    Ok(result)
+// タグで検索するディレクトリの一覧を返すTauriコマンド
+#[tauri::command]
+fn get_tag_dirs() -> Vec<String> {
+    must_get_tag_dir_index().dirs()
 }
 
-// タグファイルの一行分の文字列をパースしてファイル名とタグのペアを返す
-// 行の形式は: "file_name\ttag1,tag2,tag3"
-fn parse_tag_line(line: &str) -> (String, Vec<String>) {
-    let mut parts = line.split('\t');
-    let file_name = parts.next().unwrap_or("").to_string();
-    let tags = parts
-        .next()
-        .unwrap_or("")
-        .split(',')
-        .map(|s| s.to_string())
-        .collect();
-    (file_name, tags)
+// まだ開いていないディレクトリのタグも検索できるように、一覧に追加するTauriコマンド
+// タグのないディレクトリも追加する
+// ディレクトリ毎の結果を返す
+#[tauri::command]
+fn register_tag_dirs(dir_paths: Vec<String>) -> Vec<FileOperationResult> {
+    dir_paths
+        .into_iter()
+        .map(|dir_path| {
+            let result = validate_directory_path(&dir_path).and_then(|validated_dir_path| {
+                get_dir_tags(&validated_dir_path, false)?;
+                must_get_tag_dir_index().register(&validated_dir_path)
+            });
+            FileOperationResult {
+                path: dir_path,
+                destination: None,
+                error: result.err().map(String::from),
+            }
+        })
+        .collect()
+}
+
+// タグで検索するディレクトリの一覧から削除するTauriコマンド（タグ自体は消さない）
+// 再びタグを読み込んだ・保存した場合は、また一覧に追加される
+#[tauri::command]
+fn unregister_tag_dirs(dir_paths: Vec<String>) -> Result<(), CommandError> {
+    must_get_tag_dir_index().unregister(&dir_paths)
+}
+
+// 現在のタグの保存先を返すTauriコマンド
+#[tauri::command]
+fn get_tag_backend() -> TagBackend {
+    must_get_tag_storage().backend()
+}
+
+// タグの保存先を変更するTauriコマンド
+// 保存済みのタグは移行しないので、必要ならmigrate_tagsで移行する
+#[tauri::command]
+fn set_tag_backend(backend: TagBackend) -> Result<(), CommandError> {
+    must_get_tag_storage().set_backend(backend)
+}
+
+// 指定されたディレクトリのタグをfromの保存先からtoの保存先にコピーするTauriコマンド
+// 移行元のタグは消さず、移行先にだけあるタグも残す（両方にある画像は移行元のタグで上書きする）
+// ディレクトリ毎の結果を返す
+#[tauri::command]
+fn migrate_tags(
+    from: TagBackend,
+    to: TagBackend,
+    dir_paths: Vec<String>,
+) -> Result<Vec<FileOperationResult>, CommandError> {
+    if from == to {
+        return Err(CommandError::new(
+            ErrorKind::InvalidInput,
+            "Source and destination tag backends are the same",
+        ));
+    }
+    let storage = must_get_tag_storage();
+    let source = storage.store(from)?;
+    let target = storage.store(to)?;
+    let notify = storage.backend() == to;
+
+    Ok(dir_paths
+        .into_iter()
+        .map(|dir_path| {
+            let result = validate_directory_path(&dir_path).and_then(|validated_dir_path| {
+                let modified =
+                    migrate_dir_tags(source.as_ref(), target.as_ref(), &validated_dir_path)?;
+                if notify && modified.changed {
+                    notify_tags_changed(&validated_dir_path, modified.tags, false);
+                }
+                Ok(())
+            });
+            FileOperationResult {
+                path: dir_path,
+                destination: None,
+                error: result.err().map(String::from),
+            }
+        })
+        .collect())
+}
+
+fn migrate_dir_tags(
+    source: &dyn TagStore,
+    target: &dyn TagStore,
+    dir_path: &str,
+) -> Result<ModifiedDirTags, CommandError> {
+    let loaded = source.load_dir(dir_path, true)?;
+    target.modify_dir(
+        dir_path,
+        Box::new(move |dir_tags| {
+            let mut changed = false;
+            for (file_name, tags) in loaded.tags {
+                if dir_tags.get(&file_name) != Some(&tags) {
+                    dir_tags.insert(file_name, tags);
+                    changed = true;
+                }
+            }
+            changed
+        }),
+    )
 }
 
 // セキュリティ: タグの入力値検証
-fn validate_tag(tag: &str) -> Result<(), String> {
+fn validate_tag(tag: &str) -> Result<(), CommandError> {
     // 空のタグは許可
     if tag.is_empty() {
         return Ok(());
@@ -278,120 +1503,203 @@ fn validate_tag(tag: &str) -> Result<(), String> {
 
     // 長さ制限: 最大100文字
     if tag.len() > 100 {
-        return Err("Tag too long (maximum 100 characters)".to_string());
+        return Err(CommandError::new(
+            ErrorKind::InvalidInput,
+            "Tag too long (maximum 100 characters)",
+        ));
     }
 
     // 禁止文字チェック: タブ文字、改行文字、制御文字
     if tag.contains('\t') || tag.contains('\n') || tag.contains('\r') {
-        return Err("Tag contains invalid characters (tab, newline)".to_string());
+        return Err(CommandError::new(
+            ErrorKind::InvalidInput,
+            "Tag contains invalid characters (tab, newline)",
+        ));
     }
 
     // 制御文字チェック
     if tag.chars().any(|c| c.is_control()) {
-        return Err("Tag contains control characters".to_string());
+        return Err(CommandError::new(
+            ErrorKind::InvalidInput,
+            "Tag contains control characters",
+        ));
     }
 
     Ok(())
 }
 
-// セキュリティ: パストラバーサル攻撃防止のためのパス検証
-fn validate_and_parse_image_path(img_path: &str) -> Result<(String, String), String> {
-    let path = Path::new(img_path);
+const UNSUPPORTED_FORMAT: &str = "File is not a supported image format";
 
-    // ファイルの存在確認
-    if !path.exists() {
-        return Err(format!("{img_path} does not exist"));
-    }
+fn unsupported_format_error(path: &str) -> CommandError {
+    CommandError::new(ErrorKind::UnsupportedFormat, UNSUPPORTED_FORMAT).with_path(path)
+}
 
-    if !path.is_file() {
-        return Err(format!("{img_path} is not a file"));
+// パスが存在するファイルか検証する
+fn check_file_exists(path: &str) -> Result<(), CommandError> {
+    let path_obj = Path::new(path);
+    if !path_obj.exists() {
+        return Err(
+            CommandError::new(ErrorKind::NotFound, format!("{path} does not exist"))
+                .with_path(path),
+        );
     }
+    if !path_obj.is_file() {
+        return Err(
+            CommandError::new(ErrorKind::NotAFile, format!("{path} is not a file")).with_path(path),
+        );
+    }
+    Ok(())
+}
 
-    // パスの正規化（シンボリックリンクを解決し、. や .. を処理）
-    let canonical_path = path
-        .canonicalize()
-        .map_err(|e| format!("Failed to canonicalize path {img_path}: {e}"))?;
-
-    // ディレクトリとファイル名を取得
+// パスを正規化（シンボリックリンクを解決し、. や .. を処理）して、親ディレクトリとファイル名を返す
+fn canonicalize_file_path(path: &str) -> Result<(String, String), CommandError> {
+    let canonical_path = Path::new(path).canonicalize().map_err(|e| {
+        CommandError::io(format!("Failed to canonicalize path {path}: {e}"), &e).with_path(path)
+    })?;
+    let invalid_path = |message: &str| {
+        CommandError::new(ErrorKind::InvalidInput, message.to_string()).with_path(path)
+    };
     let dir_path = canonical_path
         .parent()
-        .ok_or_else(|| "Failed to get parent directory".to_string())?
+        .ok_or_else(|| invalid_path("Failed to get parent directory"))?
         .to_str()
-        .ok_or_else(|| "Failed to convert directory path to string".to_string())?
+        .ok_or_else(|| invalid_path("Failed to convert directory path to string"))?
         .to_string();
-
     let file_name = canonical_path
         .file_name()
-        .ok_or_else(|| "Failed to get file name".to_string())?
+        .ok_or_else(|| invalid_path("Failed to get file name"))?
         .to_str()
-        .ok_or_else(|| "Failed to convert file name to string".to_string())?
+        .ok_or_else(|| invalid_path("Failed to convert file name to string"))?
         .to_string();
+    Ok((dir_path, file_name))
+}
+
+// セキュリティ: パストラバーサル攻撃防止のためのパス検証
+// アーカイブ内のエントリの場合は、アーカイブのあるディレクトリと
+// "{アーカイブのファイル名}!/{エントリ名}" をタグのキーとして返す
+fn validate_and_parse_image_path(img_path: &str) -> Result<(String, String), CommandError> {
+    if let Some((archive_path, entry_name)) = archive::split_entry_path(img_path) {
+        return validate_and_parse_archive_entry_path(archive_path, entry_name)
+            .map_err(|e| e.with_path(img_path));
+    }
+
+    // ファイルの存在確認
+    check_file_exists(img_path)?;
+
+    // ディレクトリとファイル名を取得
+    let (dir_path, file_name) = canonicalize_file_path(img_path)?;
 
     // ファイル名の検証: 相対パス成分がないかチェック
     if file_name.contains("..") || file_name.contains("/") || file_name.contains("\\") {
-        return Err("Invalid file name".to_string());
+        return Err(
+            CommandError::new(ErrorKind::InvalidInput, "Invalid file name").with_path(img_path),
+        );
     }
 
-    // 画像ファイル拡張子の検証
-    let image_exts = ["png", "jpeg", "jpg", "gif", "webp"];
-    let path_str = canonical_path
-        .to_str()
-        .ok_or_else(|| "Failed to convert path to string".to_string())?;
-
-    if !image_exts
-        .iter()
-        .any(|ext| path_str.to_lowercase().ends_with(ext))
-    {
-        return Err("File is not a supported image format".to_string());
+    // 画像ファイル形式の検証
+    if !image_format::is_image_file(&Path::new(&dir_path).join(&file_name)) {
+        return Err(unsupported_format_error(img_path));
     }
 
     Ok((dir_path, file_name))
 }
 
+fn validate_and_parse_archive_entry_path(
+    archive_path: &str,
+    entry_name: &str,
+) -> Result<(String, String), CommandError> {
+    // アーカイブ自体はファイルと同じように検証する（画像形式の検証はエントリに対して行う）
+    check_file_exists(archive_path)?;
+    let (dir_path, archive_name) = canonicalize_file_path(archive_path)?;
+
+    // エントリ名の検証: タグファイルの区切り文字を含むものは保存できない
+    if entry_name.contains(['\t', '\n', '\r']) {
+        return Err(CommandError::new(
+            ErrorKind::InvalidInput,
+            "Invalid entry name",
+        ));
+    }
+    let entries = archive::list_entries(&Path::new(&dir_path).join(&archive_name))
+        .map_err(|e| CommandError::new(ErrorKind::Io, e))?;
+    if !entries.iter().any(|name| name == entry_name) {
+        return Err(CommandError::new(
+            ErrorKind::NotFound,
+            format!("{entry_name} does not exist in {archive_path}"),
+        ));
+    }
+
+    // 画像ファイル形式の検証（エントリは展開せずに拡張子で判定する）
+    let is_image_entry = Path::new(entry_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(ImageFormat::from_extension)
+        .is_some();
+    if !is_image_entry {
+        return Err(CommandError::new(
+            ErrorKind::UnsupportedFormat,
+            UNSUPPORTED_FORMAT,
+        ));
+    }
+
+    Ok((dir_path, archive::entry_path(&archive_name, entry_name)))
+}
+
 // セキュリティ: ディレクトリパスの検証
-fn validate_directory_path(dir_path: &str) -> Result<String, String> {
+fn validate_directory_path(dir_path: &str) -> Result<String, CommandError> {
     let path = Path::new(dir_path);
 
     // ディレクトリの存在確認
     if !path.exists() {
-        return Err(format!("{dir_path} does not exist"));
+        return Err(
+            CommandError::new(ErrorKind::NotFound, format!("{dir_path} does not exist"))
+                .with_path(dir_path),
+        );
     }
 
     if !path.is_dir() {
-        return Err(format!("{dir_path} is not a directory"));
+        return Err(CommandError::new(
+            ErrorKind::NotADirectory,
+            format!("{dir_path} is not a directory"),
+        )
+        .with_path(dir_path));
     }
 
     // パスの正規化（シンボリックリンクを解決し、. や .. を処理）
-    let canonical_path = path
-        .canonicalize()
-        .map_err(|e| format!("Failed to canonicalize directory path {dir_path}: {e}"))?;
+    let canonical_path = path.canonicalize().map_err(|e| {
+        CommandError::io(
+            format!("Failed to canonicalize directory path {dir_path}: {e}"),
+            &e,
+        )
+        .with_path(dir_path)
+    })?;
 
     let validated_path = canonical_path
         .to_str()
-        .ok_or_else(|| "Failed to convert directory path to string".to_string())?
+        .ok_or_else(|| {
+            CommandError::new(
+                ErrorKind::InvalidInput,
+                "Failed to convert directory path to string",
+            )
+            .with_path(dir_path)
+        })?
         .to_string();
 
     Ok(validated_path)
 }
 
-// 指定されたディレクトリのタグファイル名と一時ファイル名を取得する
-fn get_tag_file_names(dir_path: String) -> Result<(String, String), String> {
-    let dir = Path::new(&dir_path);
-    if dir.is_dir() {
-        let tag_file_name = dir.join(TAG_FILE_NAME);
-        let tag_backup_file_name = dir.join(TAG_TEMP_FILE_NAME);
-        Ok((
-            tag_file_name.to_str().unwrap().to_string(),
-            tag_backup_file_name.to_str().unwrap().to_string(),
-        ))
-    } else {
-        Err(format!("{dir_path} is not exist or not a directory"))
-    }
-}
-
 // 指定された画像ファイル（フルパス）のタグ情報を保存するtauriコマンド
+// セキュリティ: 呼び出し元のウィンドウのセッションが管理している画像のみ保存を許可
 #[tauri::command]
-fn save_tags(img_path: String, tags: Vec<String>) -> Result<(), String> {
+fn save_tags(
+    window: tauri::WebviewWindow,
+    img_path: String,
+    tags: Vec<String>,
+) -> Result<(), CommandError> {
+    authorize_session_path(window.label(), &img_path)?;
+    save_image_tags(img_path, tags)
+}
+
+fn save_image_tags(img_path: String, tags: Vec<String>) -> Result<(), CommandError> {
     // 入力値検証: タグの検証
     for tag in &tags {
         validate_tag(tag)?;
@@ -400,49 +1708,141 @@ fn save_tags(img_path: String, tags: Vec<String>) -> Result<(), String> {
     // パス検証: パストラバーサル攻撃を防ぐ
     let (dir_path, file_name) = validate_and_parse_image_path(&img_path)?;
 
-    let (tag_file_name, tag_backup_file_name) =
-        get_tag_file_names(dir_path.to_string()).expect("failed to get tag file names");
+    update_tags_entry(&dir_path, &file_name, Some(tags))
+}
 
-    // タグ情報をIMAGE_TAGSに保存する
-    let mut tags_map = must_lock_image_tags();
-    tags_map
-        .entry(dir_path.clone())
-        .or_insert_with(HashMap::new)
-        .insert(file_name.clone(), tags.clone());
+// ディレクトリのタグ情報のfile_nameのエントリを更新（tagsがNoneの場合は削除）してタグファイルに書き込む
+fn update_tags_entry(
+    dir_path: &str,
+    file_name: &str,
+    tags: Option<Vec<String>>,
+) -> Result<(), CommandError> {
+    modify_dir_tags(dir_path, |dir_tags| match tags {
+        Some(tags) => {
+            dir_tags.insert(file_name.to_string(), tags);
+            true
+        }
+        None => dir_tags.remove(file_name).is_some(),
+    })
+}
 
-    // 一時ファイルに書き込む
-    let mut temp_file =
-        std::fs::File::create(tag_backup_file_name.clone()).expect("failed to create temp file");
-    for (file_name, tags) in tags_map.get(&dir_path).unwrap() {
-        let tags_str = tags.join(",");
-        let line = format!("{file_name}\t{tags_str}\n");
-        temp_file
-            .write_all(line.as_bytes())
-            .expect("failed to write to temp file");
+// 別のディレクトリに移動・コピーした画像のタグを移動先のエントリにコピーする
+// remove_sourceがtrue（移動した場合）は、移動元のエントリを取り除く
+// 上書きした場合に古いタグが残らないように、タグがない場合も移動先のエントリを更新する
+fn transfer_tags_entry(
+    src_dir: &str,
+    src_name: &str,
+    dest_dir: &str,
+    dest_name: &str,
+    remove_source: bool,
+) -> Result<(), CommandError> {
+    let tags = load_tags_in_dir(src_dir.to_string())?.remove(src_name);
+    let has_tags = tags.is_some();
+    update_tags_entry(dest_dir, dest_name, tags)?;
+    if remove_source && has_tags {
+        update_tags_entry(src_dir, src_name, None)?;
     }
+    Ok(())
+}
 
-    // 一時ファイルをリネーム
-    std::fs::rename(tag_backup_file_name.clone(), tag_file_name.clone())
-        .expect("failed to rename temp file");
+// 名前変更した画像のタグのエントリを（変更前の名前, 変更後の名前）の通りに付け替えてタグファイルに書き込む
+// 付け替えは1回の書き込みで行うので、途中で失敗しても両方のエントリが残ったり消えたりしない
+// 入れ替えにも対応するため、変更前の名前のエントリをすべて取り出してから変更後の名前で入れ直す
+fn rename_tags_entries(dir_path: &str, renames: &[(String, String)]) -> Result<(), CommandError> {
+    modify_dir_tags(dir_path, |dir_tags| {
+        let moved: Vec<(&String, Option<Vec<String>>)> = renames
+            .iter()
+            .map(|(from_name, to_name)| (to_name, dir_tags.remove(from_name)))
+            .collect();
+        let mut changed = false;
+        for (to_name, tags) in moved {
+            match tags {
+                Some(tags) => {
+                    dir_tags.insert(to_name.clone(), tags);
+                    changed = true;
+                }
+                // 以前に同名だったファイルのタグが残っていても、新しい名前の画像には引き継がない
+                None => changed |= dir_tags.remove(to_name).is_some(),
+            }
+        }
+        changed
+    })
+}
 
+// ディレクトリのタグ情報をmodifyで変更して、変更があった（modifyがtrueを返した）場合は現在の保存先に保存する
+// 他のウィンドウ・アプリが保存したタグを消さないように、保存先から読み直したものを変更する
+// 保存できなかった場合は、読み込み済みのタグも変更前のままにしてエラーを返す
+fn modify_dir_tags(
+    dir_path: &str,
+    modify: impl FnOnce(&mut HashMap<String, Vec<String>>) -> bool,
+) -> Result<(), CommandError> {
+    let modified = must_get_tag_storage()
+        .active()?
+        .modify_dir(dir_path, Box::new(modify))?;
+    register_tag_dir(dir_path, &modified.tags);
+    if modified.changed || modified.external {
+        notify_tags_changed(dir_path, modified.tags, !modified.changed);
+    }
     Ok(())
 }
 
-fn must_lock_image_tags<'a>() -> MutexGuard<'a, ImageTagsMap> {
-    IMAGE_TAGS
+// ディレクトリのタグ情報が変わったことを全ウィンドウに通知する
+fn notify_tags_changed(dir_path: &str, tags: HashMap<String, Vec<String>>, external: bool) {
+    if let Some(app) = APP_HANDLE.get() {
+        if let Err(e) = app.emit(
+            "tags-changed",
+            TagsChanged {
+                dir_path: dir_path.to_string(),
+                tags,
+                external,
+            },
+        ) {
+            eprintln!("Failed to emit tags-changed event: {e}");
+        }
+    }
+}
+
+// ウィンドウにイベントを送る
+// 送れなかった場合（ウィンドウが閉じられた等）は、ログに残して処理を続ける
+fn emit_to_window<S: serde::Serialize + Clone>(
+    app: &tauri::AppHandle,
+    window_label: &str,
+    event: &str,
+    payload: S,
+) {
+    if let Err(e) = app.emit_to(window_label, event, payload) {
+        eprintln!("Failed to emit {event} event: {e}");
+    }
+}
+
+fn must_lock_sessions<'a>() -> MutexGuard<'a, Sessions> {
+    SESSIONS
         .get()
-        .expect("failed to get IMAGE_TAGS_MUTEX")
+        .expect("failed to get SESSIONS_MUTEX")
         .lock()
-        .expect("failed to lock IMAGE_TAGS_MUTEX")
+        .expect("failed to lock SESSIONS_MUTEX")
+}
+
+fn must_get_sort_bin_store() -> &'static SortBinStore {
+    SORT_BIN_STORE.get().expect("failed to get SORT_BIN_STORE")
+}
+
+fn must_get_tag_storage() -> &'static TagStorage {
+    TAG_STORAGE.get().expect("failed to get TAG_STORAGE")
+}
+
+fn must_get_tag_dir_index() -> &'static TagDirIndex {
+    TAG_DIR_INDEX.get().expect("failed to get TAG_DIR_INDEX")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::image_format::test_files::png;
 
-    /// テスト用のIMAGE_TAGS初期化ヘルパー関数
+    /// テスト用のTAG_STORAGE初期化ヘルパー関数
     ///
-    /// CI環境などで複数テストが並行実行される際に、グローバルなOnceLock<IMAGE_TAGS>への
+    /// CI環境などで複数テストが並行実行される際に、グローバルなOnceLock<TAG_STORAGE>への
     /// 重複初期化を防ぐためのスレッドセーフな初期化処理。
     ///
     /// std::sync::Onceを使用することで：
@@ -453,7 +1853,8 @@ mod tests {
         use std::sync::Once;
         static INIT: Once = Once::new();
         INIT.call_once(|| {
-            let _ = IMAGE_TAGS.set(Mutex::new(HashMap::new()));
+            let _ = TAG_STORAGE.set(TagStorage::new(None, None, None));
+            let _ = TAG_DIR_INDEX.set(Arc::new(TagDirIndex::new(None)));
         });
     }
 
@@ -467,7 +1868,7 @@ mod tests {
             "test.jpeg".to_string(),
         ];
 
-        let result = extract_image_files(paths.clone());
+        let result = extract_image_files(paths.clone(), &ScanOptions::default()).unwrap();
 
         assert_eq!(result.len(), 5);
         assert_eq!(result, paths);
@@ -482,7 +1883,7 @@ mod tests {
             "test".to_string(),
         ];
 
-        let result = extract_image_files(paths);
+        let result = extract_image_files(paths, &ScanOptions::default()).unwrap();
 
         assert_eq!(result.len(), 0);
     }
@@ -497,7 +1898,7 @@ mod tests {
             "image3.gif".to_string(),
         ];
 
-        let result = extract_image_files(paths);
+        let result = extract_image_files(paths, &ScanOptions::default()).unwrap();
 
         assert_eq!(result.len(), 3);
         assert!(result.contains(&"image1.jpg".to_string()));
@@ -515,7 +1916,7 @@ mod tests {
             "test.GIF".to_string(),
         ];
 
-        let result = extract_image_files(paths);
+        let result = extract_image_files(paths, &ScanOptions::default()).unwrap();
 
         // 大文字拡張子もサポート
         assert_eq!(result.len(), 3);
@@ -525,7 +1926,7 @@ mod tests {
     fn test_extract_image_files_empty_input() {
         let paths = vec![];
 
-        let result = extract_image_files(paths);
+        let result = extract_image_files(paths, &ScanOptions::default()).unwrap();
 
         assert_eq!(result.len(), 0);
     }
@@ -534,7 +1935,7 @@ mod tests {
     fn test_extract_image_files_with_nonexistent_directory() {
         let paths = vec!["nonexistent_directory".to_string(), "image.jpg".to_string()];
 
-        let result = extract_image_files(paths);
+        let result = extract_image_files(paths, &ScanOptions::default()).unwrap();
 
         // 存在しないディレクトリはスキップされ、有効な画像ファイルのみ残る
         assert_eq!(result.len(), 1);
@@ -543,20 +1944,30 @@ mod tests {
 
     #[test]
     fn test_is_image_helper_function() {
-        let is_image = |path: &str| -> bool {
-            let image_exts = ["png", "jpeg", "jpg", "gif", "webp"];
-            image_exts.iter().any(|ext| path.ends_with(ext))
-        };
-
-        assert!(is_image("test.jpg"));
-        assert!(is_image("test.png"));
-        assert!(is_image("test.gif"));
-        assert!(is_image("test.webp"));
-        assert!(is_image("test.jpeg"));
-        assert!(!is_image("test.txt"));
-        assert!(!is_image("test"));
-        // TODO: 大文字拡張子サポート後はtrueになる予定
-        assert!(!is_image("test.JPG"));
+        let temp_dir = tempfile::tempdir().unwrap();
+        let jpeg = temp_dir.path().join("photo.JPG");
+        std::fs::write(&jpeg, raw::test_files::jpeg(2, 2)).unwrap();
+        let text = temp_dir.path().join("notes.txt");
+        std::fs::write(&text, "text").unwrap();
+
+        // dropで画像を抽出する際の判定
+        assert!(image_format::is_image_file(&jpeg));
+        assert!(!image_format::is_image_file(&text));
+        assert!(!image_format::is_image_file(temp_dir.path()));
+        for name in [
+            "test.png",
+            "test.gif",
+            "test.webp",
+            "test.jpeg",
+            "test.AVIF",
+        ] {
+            assert!(image_format::is_image_file(Path::new(name)), "{name}");
+        }
+        assert!(!image_format::is_image_file(Path::new("test")));
+        assert_eq!(
+            image_format::detect_image_format(&jpeg),
+            Some(ImageFormat::Jpeg)
+        );
     }
 
     // タグ機能のテスト
@@ -566,68 +1977,16 @@ mod tests {
         use tempfile::TempDir;
 
         fn setup_test_dir() -> TempDir {
-            let temp_dir = TempDir::new().expect("Failed to create temp dir");
-            temp_dir
-        }
-
-        #[test]
-        fn test_parse_tag_line() {
-            let line = "test.jpg\ttag1,tag2,tag3";
-            let (file_name, tags) = parse_tag_line(line);
-
-            assert_eq!(file_name, "test.jpg");
-            assert_eq!(tags, vec!["tag1", "tag2", "tag3"]);
-        }
-
-        #[test]
-        fn test_parse_tag_line_empty_tags() {
-            let line = "test.jpg\t";
-            let (file_name, tags) = parse_tag_line(line);
-
-            assert_eq!(file_name, "test.jpg");
-            assert_eq!(tags, vec![""]);
-        }
-
-        #[test]
-        fn test_parse_tag_line_no_tabs() {
-            let line = "test.jpg";
-            let (file_name, tags) = parse_tag_line(line);
-
-            assert_eq!(file_name, "test.jpg");
-            assert_eq!(tags, vec![""]);
-        }
-
-        #[test]
-        fn test_get_tag_file_names() {
-            let temp_dir = setup_test_dir();
-            let dir_path = temp_dir.path().to_str().unwrap().to_string();
-
-            let result = get_tag_file_names(dir_path.clone());
-
-            assert!(result.is_ok());
-            let (tag_file, temp_file) = result.unwrap();
-            assert!(tag_file.contains("IMAGE_TAG"));
-            assert!(temp_file.contains("IMAGE_TAG_TEMP"));
-        }
-
-        #[test]
-        fn test_get_tag_file_names_invalid_dir() {
-            let dir_path = "/nonexistent/directory".to_string();
-
-            let result = get_tag_file_names(dir_path);
-
-            assert!(result.is_err());
-            assert!(result
-                .unwrap_err()
-                .contains("is not exist or not a directory"));
+            TempDir::new().expect("Failed to create temp dir")
         }
 
         #[test]
         fn test_parse_tags_file_empty_dir() {
+            ensure_image_tags_initialized();
             let temp_dir = setup_test_dir();
             let dir_path = temp_dir.path().to_str().unwrap();
 
-            let result = parse_tags_file(dir_path);
+            let result = get_dir_tags(dir_path, true);
 
             // 空のディレクトリでは空のHashMapが返される
             assert!(result.is_ok());
@@ -636,6 +1995,7 @@ mod tests {
 
         #[test]
         fn test_parse_tags_file_with_content() {
+            ensure_image_tags_initialized();
             let temp_dir = setup_test_dir();
             let dir_path = temp_dir.path().to_str().unwrap();
             let tag_file_path = temp_dir.path().join("IMAGE_TAG");
@@ -644,7 +2004,7 @@ mod tests {
             let content = "image1.jpg\ttag1,tag2\nimage2.png\ttag3,tag4,tag5\n";
             fs::write(&tag_file_path, content).expect("Failed to write test file");
 
-            let result = parse_tags_file(dir_path);
+            let result = get_dir_tags(dir_path, true);
 
             assert!(result.is_ok());
             let tags_map = result.unwrap();
@@ -664,7 +2024,7 @@ mod tests {
             let content = "photo.jpg\tnature,landscape\nvideo.mp4\ttime,family\n";
             fs::write(&tag_file_path, content).expect("Failed to write test file");
 
-            // IMAGE_TAGSを初期化
+            // TAG_STORAGEを初期化
             ensure_image_tags_initialized();
 
             let result = load_tags_in_dir(dir_path);
@@ -682,15 +2042,15 @@ mod tests {
             let test_file = temp_dir.path().join("test.jpg");
 
             // テスト用の画像ファイルを作成
-            fs::write(&test_file, "fake image content").expect("Failed to create test file");
+            fs::write(&test_file, png(0)).expect("Failed to create test file");
 
             let img_path = test_file.to_str().unwrap().to_string();
             let tags = vec!["nature".to_string(), "sunset".to_string()];
 
-            // IMAGE_TAGSを初期化
+            // TAG_STORAGEを初期化
             ensure_image_tags_initialized();
 
-            let result = save_tags(img_path, tags.clone());
+            let result = save_image_tags(img_path, tags.clone());
 
             assert!(result.is_ok());
 
@@ -704,18 +2064,204 @@ mod tests {
             assert!(content.contains("nature,sunset"));
         }
 
+        #[test]
+        fn test_save_tags_returns_error_when_tag_file_cannot_be_written() {
+            let temp_dir = setup_test_dir();
+            let test_file = temp_dir.path().join("test.jpg");
+            fs::write(&test_file, png(0)).expect("Failed to create test file");
+            let tag_file_path = temp_dir.path().join("IMAGE_TAG");
+            fs::write(&tag_file_path, "test.jpg\tcat\n").expect("Failed to write test file");
+            // 一時ファイルを作れないようにする
+            fs::create_dir(temp_dir.path().join("IMAGE_TAG_TEMP")).unwrap();
+
+            ensure_image_tags_initialized();
+            let img_path = test_file.to_str().unwrap().to_string();
+            let result = save_image_tags(img_path, vec!["dog".to_string()]);
+
+            assert!(result.is_err());
+            assert_eq!(
+                fs::read_to_string(&tag_file_path).unwrap(),
+                "test.jpg\tcat\n"
+            );
+            // 読み込み済みのタグも変更前のまま
+            let dir = temp_dir.path().canonicalize().unwrap();
+            let tags_map = load_tags_in_dir(dir.to_str().unwrap().to_string()).unwrap();
+            assert_eq!(tags_map["test.jpg"], vec!["cat"]);
+        }
+
+        #[test]
+        fn test_save_tags_keeps_tags_written_by_another_instance() {
+            let temp_dir = setup_test_dir();
+            let a = temp_dir.path().join("a.jpg");
+            let b = temp_dir.path().join("b.jpg");
+            fs::write(&a, png(0)).expect("Failed to create test file");
+            fs::write(&b, png(0)).expect("Failed to create test file");
+
+            ensure_image_tags_initialized();
+            save_image_tags(a.to_str().unwrap().to_string(), vec!["cat".to_string()]).unwrap();
+            // 読み込み済みの後に、別のアプリがタグファイルを書き換える
+            let tag_file_path = temp_dir.path().join("IMAGE_TAG");
+            tag_file::write(
+                &tag_file_path,
+                &temp_dir.path().join("IMAGE_TAG_TEMP"),
+                &HashMap::from([
+                    ("a.jpg".to_string(), vec!["cat".to_string()]),
+                    ("c.jpg".to_string(), vec!["bird".to_string()]),
+                ]),
+                &HashMap::new(),
+            )
+            .unwrap();
+
+            save_image_tags(b.to_str().unwrap().to_string(), vec!["dog".to_string()]).unwrap();
+
+            let saved = tag_file::read_stamped(&tag_file_path).unwrap().tags;
+            assert_eq!(saved.len(), 3);
+            assert_eq!(saved["c.jpg"], vec!["bird"]);
+            assert_eq!(saved["b.jpg"], vec!["dog"]);
+            let dir = temp_dir.path().canonicalize().unwrap();
+            let tags_map = load_tags_in_dir(dir.to_str().unwrap().to_string()).unwrap();
+            assert_eq!(tags_map, saved);
+        }
+
+        #[test]
+        fn test_search_by_tags_across_dirs() {
+            let temp_dir = setup_test_dir();
+            let root = temp_dir.path().canonicalize().unwrap();
+            let (a_dir, b_dir, c_dir) = (root.join("a"), root.join("b"), root.join("c"));
+            for dir in [&a_dir, &b_dir, &c_dir] {
+                fs::create_dir(dir).unwrap();
+            }
+            let path = |dir: &Path, name: &str| dir.join(name).to_str().unwrap().to_string();
+            for image in [path(&a_dir, "1.jpg"), path(&b_dir, "2.jpg")] {
+                fs::write(&image, png(0)).unwrap();
+            }
+            // 他のディレクトリにタグを保存する（アプリの外で付けたタグ）
+            fs::write(c_dir.join("3.jpg"), png(0)).unwrap();
+            fs::write(c_dir.join(tag_store::TAG_FILE_NAME), "3.jpg\tsearch-cat\n").unwrap();
+
+            ensure_image_tags_initialized();
+            let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+            save_image_tags(path(&a_dir, "1.jpg"), tags(&["search-cat", "search-red"])).unwrap();
+            save_image_tags(path(&b_dir, "2.jpg"), tags(&["search-dog", "search-red"])).unwrap();
+            let search = |query_tags: &[&str], match_all: bool| {
+                search_by_tags(TagQuery {
+                    tags: tags(query_tags),
+                    match_all,
+                })
+                .unwrap()
+            };
+
+            assert_eq!(
+                search(&["search-red"], false),
+                vec![path(&a_dir, "1.jpg"), path(&b_dir, "2.jpg")]
+            );
+            assert_eq!(
+                search(&["search-cat", "search-red"], true),
+                vec![path(&a_dir, "1.jpg")]
+            );
+            // 開いていないディレクトリは、登録するまで検索しない
+            assert_eq!(search(&["search-cat"], false), vec![path(&a_dir, "1.jpg")]);
+            let results = register_tag_dirs(vec![
+                c_dir.to_str().unwrap().to_string(),
+                root.join("missing").to_str().unwrap().to_string(),
+            ]);
+            assert_eq!(results[0].error, None);
+            assert!(results[1].error.is_some());
+            assert_eq!(
+                search(&["search-cat"], false),
+                vec![path(&a_dir, "1.jpg"), path(&c_dir, "3.jpg")]
+            );
+            assert!(get_tag_dirs().contains(&c_dir.to_str().unwrap().to_string()));
+
+            unregister_tag_dirs(vec![a_dir.to_str().unwrap().to_string()]).unwrap();
+            assert_eq!(search(&["search-cat"], false), vec![path(&c_dir, "3.jpg")]);
+            assert_eq!(
+                search_by_tags(TagQuery::default()).unwrap_err().kind,
+                ErrorKind::InvalidInput
+            );
+        }
+
+        #[test]
+        fn test_migrate_dir_tags_between_backends() {
+            let temp_dir = setup_test_dir();
+            let images_dir = temp_dir.path().join("images");
+            fs::create_dir(&images_dir).unwrap();
+            let dir_path = images_dir.canonicalize().unwrap();
+            let dir_path = dir_path.to_str().unwrap();
+            tag_file::write(
+                &images_dir.join(tag_store::TAG_FILE_NAME),
+                &images_dir.join(tag_store::TAG_TEMP_FILE_NAME),
+                &HashMap::from([
+                    ("a.jpg".to_string(), vec!["cat".to_string()]),
+                    ("b.jpg".to_string(), vec!["dog".to_string()]),
+                ]),
+                &HashMap::new(),
+            )
+            .unwrap();
+            let storage = TagStorage::new(None, Some(temp_dir.path().join("tags.sqlite3")), None);
+            let file_store = storage.store(TagBackend::File).unwrap();
+            let database = storage.store(TagBackend::Database).unwrap();
+            database
+                .modify_dir(
+                    dir_path,
+                    Box::new(|dir_tags| {
+                        dir_tags.insert("a.jpg".to_string(), vec!["old".to_string()]);
+                        dir_tags.insert("c.jpg".to_string(), vec!["bird".to_string()]);
+                        true
+                    }),
+                )
+                .unwrap();
+
+            let modified =
+                migrate_dir_tags(file_store.as_ref(), database.as_ref(), dir_path).unwrap();
+
+            assert!(modified.changed);
+            let migrated = database.load_dir(dir_path, true).unwrap().tags;
+            assert_eq!(migrated.len(), 3);
+            assert_eq!(migrated["a.jpg"], vec!["cat"]);
+            assert_eq!(migrated["c.jpg"], vec!["bird"]);
+            // 移行元は変更しない
+            assert_eq!(file_store.load_dir(dir_path, true).unwrap().tags.len(), 2);
+            // もう一度移行しても変更はない
+            let modified =
+                migrate_dir_tags(file_store.as_ref(), database.as_ref(), dir_path).unwrap();
+            assert!(!modified.changed);
+        }
+
+        #[test]
+        fn test_save_tags_migrates_legacy_file_and_keeps_commas() {
+            let temp_dir = setup_test_dir();
+            let test_file = temp_dir.path().join("test.jpg");
+            fs::write(&test_file, png(0)).expect("Failed to create test file");
+            let tag_file_path = temp_dir.path().join("IMAGE_TAG");
+            // 古い形式（ヘッダ行なし）のタグファイル
+            fs::write(&tag_file_path, "other.jpg\tcat,dog\n").expect("Failed to write test file");
+
+            ensure_image_tags_initialized();
+            let tags = vec!["red, blue".to_string(), "green".to_string()];
+            save_image_tags(test_file.to_str().unwrap().to_string(), tags.clone()).unwrap();
+
+            // 現在の形式で書き直され、既存のタグも残っている
+            let content = fs::read_to_string(&tag_file_path).expect("Failed to read tag file");
+            assert!(content.starts_with("#IMAGE_TAG version="));
+            let dir = temp_dir.path().canonicalize().unwrap();
+            let tags_map = get_dir_tags(dir.to_str().unwrap(), true).unwrap();
+            assert_eq!(tags_map["test.jpg"], tags);
+            assert_eq!(tags_map["other.jpg"], vec!["cat", "dog"]);
+        }
+
         #[test]
         fn test_save_tags_nonexistent_file() {
             let img_path = "/nonexistent/path/test.jpg".to_string();
             let tags = vec!["tag1".to_string()];
 
-            // IMAGE_TAGSを初期化
+            // TAG_STORAGEを初期化
             ensure_image_tags_initialized();
 
-            let result = save_tags(img_path, tags);
+            let result = save_image_tags(img_path, tags);
 
             assert!(result.is_err());
-            assert!(result.unwrap_err().contains("does not exist"));
+            assert!(result.unwrap_err().message.contains("does not exist"));
         }
 
         #[test]
@@ -728,7 +2274,7 @@ mod tests {
             let content = "image1.jpg\ttag1\n";
             fs::write(&tag_file_path, content).expect("Failed to write test file");
 
-            // IMAGE_TAGSを初期化
+            // TAG_STORAGEを初期化
             ensure_image_tags_initialized();
 
             // 最初の読み込み
@@ -736,15 +2282,18 @@ mod tests {
             assert!(result1.is_ok());
             assert_eq!(result1.unwrap()["image1.jpg"], vec!["tag1"]);
 
-            // ファイルを変更（ただしキャッシュは更新されない想定）
+            // アプリの外でファイルを変更
             let new_content = "image1.jpg\ttag1,tag2\n";
             fs::write(&tag_file_path, new_content).expect("Failed to write test file");
 
-            // 2回目の読み込み（キャッシュから取得される）
-            let result2 = load_tags_in_dir(dir_path);
-            assert!(result2.is_ok());
-            // キャッシュされた値が返される
-            assert_eq!(result2.unwrap()["image1.jpg"], vec!["tag1"]);
+            // 2回目の読み込み（変更を検知して読み直される）
+            let result2 = load_tags_in_dir(dir_path.clone());
+            assert_eq!(result2.unwrap()["image1.jpg"], vec!["tag1", "tag2"]);
+
+            // タグファイルが消された場合も読み直される
+            fs::remove_file(&tag_file_path).unwrap();
+            assert!(load_tags_in_dir(dir_path.clone()).unwrap().is_empty());
+            assert!(reload_tags_in_dir(dir_path).unwrap().is_empty());
         }
 
         // セキュリティテスト: タグバリデーション
@@ -775,41 +2324,41 @@ mod tests {
             let test_file = temp_dir.path().join("test.jpg");
 
             // テスト用の画像ファイルを作成
-            fs::write(&test_file, "fake image content").expect("Failed to create test file");
+            fs::write(&test_file, png(0)).expect("Failed to create test file");
 
             let img_path = test_file.to_str().unwrap().to_string();
 
-            // IMAGE_TAGSを初期化
+            // TAG_STORAGEを初期化
             ensure_image_tags_initialized();
 
             // 無効なタグで保存試行: 長すぎるタグ
             let long_tag = "a".repeat(101);
-            let result = save_tags(img_path.clone(), vec![long_tag]);
+            let result = save_image_tags(img_path.clone(), vec![long_tag]);
             assert!(result.is_err());
-            assert!(result.unwrap_err().contains("Tag too long"));
+            assert!(result.unwrap_err().message.contains("Tag too long"));
 
             // 無効なタグで保存試行: タブ文字
-            let result = save_tags(img_path.clone(), vec!["tag\twith\ttab".to_string()]);
+            let result = save_image_tags(img_path.clone(), vec!["tag\twith\ttab".to_string()]);
             assert!(result.is_err());
-            assert!(result.unwrap_err().contains("invalid characters"));
+            assert!(result.unwrap_err().message.contains("invalid characters"));
 
             // 無効なタグで保存試行: 制御文字
-            let result = save_tags(img_path, vec!["tag\x00control".to_string()]);
+            let result = save_image_tags(img_path, vec!["tag\x00control".to_string()]);
             assert!(result.is_err());
-            assert!(result.unwrap_err().contains("control characters"));
+            assert!(result.unwrap_err().message.contains("control characters"));
         }
 
         #[test]
         fn test_path_validation_security() {
             let temp_dir = setup_test_dir();
 
-            // IMAGE_TAGSを初期化
+            // TAG_STORAGEを初期化
             ensure_image_tags_initialized();
 
             // 存在しないディレクトリ
             let result = load_tags_in_dir("/nonexistent/directory".to_string());
             assert!(result.is_err());
-            assert!(result.unwrap_err().contains("does not exist"));
+            assert!(result.unwrap_err().message.contains("does not exist"));
 
             // ファイルをディレクトリとして指定
             let test_file = temp_dir.path().join("test.txt");
@@ -817,7 +2366,35 @@ mod tests {
 
             let result = load_tags_in_dir(test_file.to_str().unwrap().to_string());
             assert!(result.is_err());
-            assert!(result.unwrap_err().contains("is not a directory"));
+            assert!(result.unwrap_err().message.contains("is not a directory"));
+        }
+
+        #[test]
+        fn test_save_tags_for_archive_entry() {
+            let temp_dir = setup_test_dir();
+            let zip = temp_dir.path().join("comic.cbz");
+            archive::test_files::write_zip(&zip, &[("ch1/001.jpg", &png(0)[..])]);
+            let zip_path = zip.to_str().unwrap();
+
+            // TAG_STORAGEを初期化
+            ensure_image_tags_initialized();
+
+            let result = save_image_tags(
+                archive::entry_path(zip_path, "ch1/001.jpg"),
+                vec!["cover".to_string()],
+            );
+            assert!(result.is_ok());
+
+            // アーカイブのあるディレクトリのタグファイルに "アーカイブ名!/エントリ名" で保存される
+            let tags_map = load_tags_in_dir(temp_dir.path().to_str().unwrap().to_string()).unwrap();
+            assert_eq!(tags_map["comic.cbz!/ch1/001.jpg"], vec!["cover"]);
+            let content = fs::read_to_string(temp_dir.path().join("IMAGE_TAG")).unwrap();
+            assert!(content.contains("comic.cbz!/ch1/001.jpg\tcover"));
+
+            // 存在しないエントリ
+            let result = save_image_tags(archive::entry_path(zip_path, "ch1/002.jpg"), vec![]);
+            assert!(result.is_err());
+            assert!(result.unwrap_err().message.contains("does not exist"));
         }
 
         #[test]
@@ -827,18 +2404,18 @@ mod tests {
             let test_file2 = temp_dir.path().join("photo2.png");
 
             // テスト用ファイルを作成
-            fs::write(&test_file1, "fake content").expect("Failed to create test file");
-            fs::write(&test_file2, "fake content").expect("Failed to create test file");
+            fs::write(&test_file1, png(0)).expect("Failed to create test file");
+            fs::write(&test_file2, png(1)).expect("Failed to create test file");
 
-            // IMAGE_TAGSを初期化
+            // TAG_STORAGEを初期化
             ensure_image_tags_initialized();
 
             // 複数のファイルにタグを保存
-            let _ = save_tags(
+            let _ = save_image_tags(
                 test_file1.to_str().unwrap().to_string(),
                 vec!["nature".to_string()],
             );
-            let _ = save_tags(
+            let _ = save_image_tags(
                 test_file2.to_str().unwrap().to_string(),
                 vec!["portrait".to_string()],
             );
@@ -866,8 +2443,7 @@ mod tests {
         const TEST_IMAGE_SIZE: u64 = 69; // 以下のPNGデータのバイト数
 
         fn setup_test_dir() -> TempDir {
-            let temp_dir = TempDir::new().expect("Failed to create temp dir");
-            temp_dir
+            TempDir::new().expect("Failed to create temp dir")
         }
 
         fn create_test_image(path: &std::path::Path) {
@@ -890,7 +2466,7 @@ mod tests {
                 0x49, 0x45, 0x4E, 0x44, // IEND chunk type
                 0xAE, 0x42, 0x60, 0x82, // CRC
             ];
-            fs::write(path, &png_data).expect("Failed to create test image");
+            fs::write(path, png_data).expect("Failed to create test image");
         }
 
         #[test]
@@ -907,6 +2483,9 @@ mod tests {
             assert_eq!(file_info.size, TEST_IMAGE_SIZE);
             assert_eq!(file_info.width, TEST_IMAGE_WIDTH);
             assert_eq!(file_info.height, TEST_IMAGE_HEIGHT);
+            assert_eq!(file_info.display_width, TEST_IMAGE_WIDTH);
+            assert_eq!(file_info.display_height, TEST_IMAGE_HEIGHT);
+            assert_eq!(file_info.orientation, 1);
         }
 
         #[test]
@@ -914,7 +2493,7 @@ mod tests {
             let result = get_file_info("/nonexistent/file.png".to_string());
 
             assert!(result.is_err());
-            assert!(result.unwrap_err().contains("does not exist"));
+            assert!(result.unwrap_err().message.contains("does not exist"));
         }
 
         #[test]
@@ -922,10 +2501,11 @@ mod tests {
             let temp_dir = setup_test_dir();
             let dir_path = temp_dir.path().to_str().unwrap().to_string();
 
-            let result = get_file_info(dir_path);
+            let error = get_file_info(dir_path.clone()).unwrap_err();
 
-            assert!(result.is_err());
-            assert!(result.unwrap_err().contains("is not a file"));
+            assert_eq!(error.kind, ErrorKind::NotAFile);
+            assert_eq!(error.path, Some(dir_path));
+            assert!(error.message.contains("is not a file"));
         }
 
         #[test]
@@ -941,6 +2521,7 @@ mod tests {
             assert!(result.is_err());
             assert!(result
                 .unwrap_err()
+                .message
                 .contains("Failed to get image dimensions"));
         }
 
@@ -991,6 +2572,12 @@ mod tests {
                     ext
                 );
                 assert_eq!(file_info.size, TEST_IMAGE_SIZE, "Size mismatch for {}", ext);
+                assert_eq!(
+                    file_info.format,
+                    ImageFormat::Png,
+                    "Format mismatch for {}",
+                    ext
+                );
             }
         }
 
@@ -1001,6 +2588,10 @@ mod tests {
                 size: TEST_IMAGE_SIZE,
                 width: TEST_IMAGE_WIDTH,
                 height: TEST_IMAGE_HEIGHT,
+                display_width: TEST_IMAGE_HEIGHT,
+                display_height: TEST_IMAGE_WIDTH,
+                orientation: 6,
+                format: ImageFormat::Png,
             };
 
             let serialized = serde_json::to_string(&file_info);
@@ -1010,54 +2601,154 @@ mod tests {
             assert!(json_str.contains(&format!("\"size\":{}", TEST_IMAGE_SIZE)));
             assert!(json_str.contains(&format!("\"width\":{}", TEST_IMAGE_WIDTH)));
             assert!(json_str.contains(&format!("\"height\":{}", TEST_IMAGE_HEIGHT)));
+            assert!(json_str.contains("\"format\":\"png\""));
+            assert!(json_str.contains(&format!("\"displayWidth\":{}", TEST_IMAGE_HEIGHT)));
+            assert!(json_str.contains("\"orientation\":6"));
+        }
+
+        #[test]
+        fn test_get_file_info_raw_uses_preview_dimensions() {
+            let temp_dir = setup_test_dir();
+            let test_file = temp_dir.path().join("IMG_0001.CR2");
+            let preview = raw::test_files::jpeg(64, 48);
+            fs::write(&test_file, raw::test_files::tiff_based_raw(&preview)).unwrap();
+
+            let file_info = get_file_info(test_file.to_str().unwrap().to_string()).unwrap();
+
+            assert_eq!((file_info.width, file_info.height), (64, 48));
+            assert_eq!(file_info.format, ImageFormat::Raw);
+        }
+
+        #[test]
+        fn test_get_file_info_archive_entry() {
+            let temp_dir = setup_test_dir();
+            let test_file = temp_dir.path().join("test.png");
+            create_test_image(&test_file);
+            let zip = temp_dir.path().join("pack.zip");
+            archive::test_files::write_zip(
+                &zip,
+                &[("images/test.png", &fs::read(&test_file).unwrap())],
+            );
+            let entry_path = archive::entry_path(zip.to_str().unwrap(), "images/test.png");
+
+            let file_info = get_file_info(entry_path).unwrap();
+
+            assert_eq!(file_info.size, TEST_IMAGE_SIZE);
+            assert_eq!(
+                (file_info.width, file_info.height),
+                (TEST_IMAGE_WIDTH, TEST_IMAGE_HEIGHT)
+            );
+            assert_eq!(file_info.format, ImageFormat::Png);
+
+            let missing = archive::entry_path(zip.to_str().unwrap(), "images/missing.png");
+            assert!(get_file_info(missing).is_err());
+        }
+
+        #[test]
+        fn test_get_file_info_applies_exif_orientation() {
+            use metadata::test_images::{jpeg_with_metadata, short_field};
+
+            let temp_dir = setup_test_dir();
+            let test_file = temp_dir.path().join("portrait.jpg");
+            let data =
+                jpeg_with_metadata(&[short_field(exif::Tag::Orientation, 6)], None, 400, 300);
+            fs::write(&test_file, data).unwrap();
+
+            let file_info = get_file_info(test_file.to_str().unwrap().to_string()).unwrap();
+
+            assert_eq!((file_info.width, file_info.height), (400, 300));
+            assert_eq!(
+                (file_info.display_width, file_info.display_height),
+                (300, 400)
+            );
+            assert_eq!(file_info.orientation, 6);
+            assert_eq!(file_info.format, ImageFormat::Jpeg);
+        }
+
+        #[test]
+        fn test_get_image_metadata_archive_entry() {
+            use metadata::test_images::{ascii_field, jpeg_with_iptc, jpeg_with_metadata};
+
+            let temp_dir = setup_test_dir();
+            let data = jpeg_with_iptc(
+                &jpeg_with_metadata(&[ascii_field(exif::Tag::Model, "EOS R5")], None, 4, 3),
+                &[((2, 25), "sunset"), ((2, 120), "Evening")],
+            );
+            let zip = temp_dir.path().join("pack.zip");
+            archive::test_files::write_zip(&zip, &[("photos/a.jpg", &data)]);
+            let entry_path = archive::entry_path(zip.to_str().unwrap(), "photos/a.jpg");
+
+            let metadata = get_image_metadata(entry_path).unwrap();
+
+            assert_eq!(metadata.camera_model.as_deref(), Some("EOS R5"));
+            assert_eq!(metadata.keywords, Some(vec!["sunset".to_string()]));
+            assert_eq!(metadata.caption.as_deref(), Some("Evening"));
+
+            let missing = archive::entry_path(zip.to_str().unwrap(), "photos/missing.jpg");
+            let error = get_image_metadata(missing.clone()).unwrap_err();
+            assert_eq!(error.path.as_deref(), Some(missing.as_str()));
         }
     }
 
+    /// テスト用のセッションを作成し、指定されたパスを管理対象として登録する
+    ///
+    /// SESSIONSの初期化はTAG_STORAGEと同様にスレッドセーフに一度だけ行い、
+    /// セッションはテスト毎に作成するため、並行実行されるテスト同士で干渉しない。
+    /// 作成したセッションのウィンドウラベルを返す。
+    fn create_test_session(paths: Vec<String>) -> String {
+        use std::sync::Once;
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            let _ = SESSIONS.set(Mutex::new(Sessions::default()));
+        });
+
+        let mut sessions = must_lock_sessions();
+        let session = sessions.create();
+        session.image_paths.paths = paths;
+        session.window_label.clone()
+    }
+
+    /// テスト用の画像ファイルをdirにnamesの名前で作成し、そのパスを返す。
+    /// 内容（フィンガープリント）が重ならないように、画像毎に異なる色のPNG画像にする。
+    fn create_test_images(dir: &Path, names: &[&str]) -> Vec<String> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let path = dir.join(name);
+                std::fs::write(&path, png(i as u8)).unwrap();
+                path.to_str().unwrap().to_string()
+            })
+            .collect()
+    }
+
     #[test]
     fn test_delete_file_security_unauthorized_path() {
-        // IMAGE_PATHS静的変数を初期化
-        IMAGE_PATHS.get_or_init(|| {
-            Mutex::new(ImagePaths {
-                id: 0,
-                paths: vec!["managed_file.jpg".to_string()],
-            })
-        });
+        let window_label = create_test_session(vec!["managed_file.jpg".to_string()]);
 
         // 管理されていないパスの削除を試行した場合、エラーが返されることを確認
-        let result = delete_file("unauthorized_path.jpg".to_string());
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("unauthorized file deletion"));
+        let error =
+            delete_session_file(&window_label, "unauthorized_path.jpg".to_string()).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Unauthorized);
+        assert_eq!(error.path.as_deref(), Some("unauthorized_path.jpg"));
+        assert!(error.message.contains("unauthorized file deletion"));
     }
 
     #[test]
+    #[allow(clippy::unnecessary_unwrap)]
     fn test_delete_file_security_authorized_path() {
         // テスト用の一時ファイルを作成（中身は何でも良い）
         let temp_dir = tempfile::tempdir().unwrap();
         let test_file = temp_dir.path().join("test.png");
-        std::fs::write(&test_file, b"dummy content").unwrap();
+        std::fs::write(&test_file, png(0)).unwrap();
 
         let test_path = test_file.to_str().unwrap().to_string();
 
-        // IMAGE_PATHS静的変数を初期化してテストパスを追加
-        IMAGE_PATHS.get_or_init(|| {
-            Mutex::new(ImagePaths {
-                id: 0,
-                paths: vec![],
-            })
-        });
-
-        // 既存のイメージパスリストにテストパスを追加
-        {
-            let mut image_paths = IMAGE_PATHS
-                .get()
-                .expect("failed to get IMAGE_PATHS_MUTEX")
-                .lock()
-                .expect("failed to lock IMAGE_PATHS_MUTEX");
-            image_paths.paths.push(test_path.clone());
-        }
+        // セッションを作成してテストパスを追加
+        let window_label = create_test_session(vec![test_path.clone()]);
 
         // 管理されているパスの削除は成功すべき
-        let result = delete_file(test_path);
+        let result = delete_session_file(&window_label, test_path);
         if result.is_err() {
             eprintln!("Delete failed with error: {}", result.as_ref().unwrap_err());
         }
@@ -1066,4 +2757,458 @@ mod tests {
         // ファイルがゴミ箱に移動されたことを確認（存在しないことで確認）
         assert!(!test_file.exists());
     }
+
+    #[test]
+    fn test_undo_delete_restores_original_position() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let paths = create_test_images(temp_dir.path(), &["a.png", "b.png", "c.png"]);
+        let window_label = create_test_session(paths.clone());
+
+        delete_session_file(&window_label, paths[1].clone()).unwrap();
+        delete_session_file(&window_label, paths[0].clone()).unwrap();
+        assert_eq!(
+            must_lock_sessions()
+                .get_by_label(&window_label)
+                .unwrap()
+                .image_paths
+                .paths,
+            vec![paths[2].clone()]
+        );
+
+        let restored = undo_session_delete(&window_label, 5).unwrap();
+
+        assert_eq!(
+            restored,
+            vec![
+                DeletedImage {
+                    path: paths[0].clone(),
+                    index: 0
+                },
+                DeletedImage {
+                    path: paths[1].clone(),
+                    index: 1
+                },
+            ]
+        );
+        assert!(Path::new(&paths[0]).exists() && Path::new(&paths[1]).exists());
+        assert_eq!(
+            must_lock_sessions()
+                .get_by_label(&window_label)
+                .unwrap()
+                .image_paths
+                .paths,
+            paths
+        );
+        // 取り消す削除がなければ何も復元しない
+        assert_eq!(undo_session_delete(&window_label, 1), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_undo_delete_already_restored_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_file = temp_dir.path().join("test.png");
+        std::fs::write(&test_file, png(0)).unwrap();
+        let test_path = test_file.to_str().unwrap().to_string();
+        let window_label = create_test_session(vec![test_path.clone()]);
+
+        delete_session_file(&window_label, test_path).unwrap();
+        // 同じパスに新しいファイルが作られている場合は上書きせずにエラーにする
+        std::fs::write(&test_file, png(1)).unwrap();
+
+        let result = undo_session_delete(&window_label, 1);
+        assert!(result.is_err());
+        assert_eq!(std::fs::read(&test_file).unwrap(), png(1));
+    }
+
+    #[test]
+    fn test_delete_files_reports_each_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image = temp_dir.path().join("a.png");
+        let text = temp_dir.path().join("b.txt");
+        std::fs::write(&image, png(0)).unwrap();
+        std::fs::write(&text, b"dummy content").unwrap();
+        let image_path = image.to_str().unwrap().to_string();
+        let text_path = text.to_str().unwrap().to_string();
+        let window_label = create_test_session(vec![image_path.clone(), text_path.clone()]);
+
+        let results =
+            delete_session_files(&window_label, vec![image_path.clone(), text_path.clone()])
+                .unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].error, None);
+        assert!(results[1].error.is_some());
+        assert!(!image.exists() && text.exists());
+        // 削除できたものだけが画像一覧から取り除かれる
+        assert_eq!(
+            must_lock_sessions()
+                .get_by_label(&window_label)
+                .unwrap()
+                .image_paths
+                .paths,
+            vec![text_path]
+        );
+    }
+
+    #[test]
+    fn test_delete_files_rejects_whole_batch_with_unauthorized_path() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image = temp_dir.path().join("a.png");
+        std::fs::write(&image, png(0)).unwrap();
+        let image_path = image.to_str().unwrap().to_string();
+        let window_label = create_test_session(vec![image_path.clone()]);
+
+        let result =
+            delete_session_files(&window_label, vec![image_path, "/etc/passwd".to_string()]);
+
+        assert_eq!(result.unwrap_err().kind, ErrorKind::Unauthorized);
+        assert!(image.exists());
+    }
+
+    #[test]
+    fn test_move_files_updates_managed_list() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dest_dir = temp_dir.path().join("keep");
+        std::fs::create_dir(&dest_dir).unwrap();
+        std::fs::write(dest_dir.join("b.png"), png(1)).unwrap();
+        let paths = create_test_images(temp_dir.path(), &["a.png", "b.png", "c.png"]);
+        let window_label = create_test_session(paths.clone());
+
+        let results = move_session_files(
+            &window_label,
+            vec![paths[0].clone(), paths[1].clone()],
+            dest_dir.to_str().unwrap(),
+        )
+        .unwrap();
+
+        let moved = dest_dir.canonicalize().unwrap().join("a.png");
+        assert_eq!(results[0].destination.as_deref(), moved.to_str());
+        assert!(results[1]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("already exists"));
+        assert!(moved.exists() && Path::new(&paths[1]).exists());
+        assert_eq!(
+            must_lock_sessions()
+                .get_by_label(&window_label)
+                .unwrap()
+                .image_paths
+                .paths,
+            vec![
+                moved.to_str().unwrap().to_string(),
+                paths[1].clone(),
+                paths[2].clone()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_move_files_moves_tags() {
+        ensure_image_tags_initialized();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dest_dir = temp_dir.path().join("keep");
+        std::fs::create_dir(&dest_dir).unwrap();
+        let image = temp_dir.path().join("a.png");
+        std::fs::write(&image, png(0)).unwrap();
+        std::fs::write(
+            temp_dir.path().join(tag_store::TAG_FILE_NAME),
+            "a.png\tfavorite\nb.png\tother\n",
+        )
+        .unwrap();
+        let image_path = image.to_str().unwrap().to_string();
+        let window_label = create_test_session(vec![image_path.clone()]);
+
+        let results =
+            move_session_files(&window_label, vec![image_path], dest_dir.to_str().unwrap())
+                .unwrap();
+
+        assert_eq!(results[0].error, None);
+        // タグは移動先に引き継がれ、移動元からは取り除かれる
+        let dest_dir = dest_dir.canonicalize().unwrap();
+        let dest_tags = get_dir_tags(dest_dir.to_str().unwrap(), true).unwrap();
+        assert_eq!(dest_tags["a.png"], vec!["favorite"]);
+        let src_tags = get_dir_tags(
+            temp_dir.path().canonicalize().unwrap().to_str().unwrap(),
+            true,
+        )
+        .unwrap();
+        assert!(!src_tags.contains_key("a.png"));
+        assert_eq!(src_tags["b.png"], vec!["other"]);
+    }
+
+    #[test]
+    fn test_send_to_sort_bin_moves_image_with_tags() {
+        ensure_image_tags_initialized();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let keep_dir = temp_dir.path().join("keep");
+        std::fs::create_dir(&keep_dir).unwrap();
+        std::fs::write(keep_dir.join("a.png"), png(1)).unwrap();
+        std::fs::write(keep_dir.join(tag_store::TAG_FILE_NAME), "old.png\tkept\n").unwrap();
+        let image = temp_dir.path().join("a.png");
+        std::fs::write(&image, png(0)).unwrap();
+        std::fs::write(
+            temp_dir.path().join(tag_store::TAG_FILE_NAME),
+            "a.png\tfavorite\nb.png\tother\n",
+        )
+        .unwrap();
+        let image_path = image.to_str().unwrap().to_string();
+        let window_label = create_test_session(vec![image_path.clone()]);
+        let store = SortBinStore::new(temp_dir.path().join(sort_bin::SORT_BINS_FILE_NAME));
+        store
+            .save(&[SortBin {
+                name: "keep".to_string(),
+                dir: keep_dir.to_str().unwrap().to_string(),
+                collision: sort_bin::CollisionPolicy::Suffix,
+            }])
+            .unwrap();
+
+        let dest = send_session_file_to_sort_bin(
+            &store,
+            &window_label,
+            image_path,
+            "keep",
+            TransferMode::Move,
+        )
+        .unwrap()
+        .unwrap();
+
+        // 同名のファイルがあるので連番を付けて移動する
+        let keep_dir = keep_dir.canonicalize().unwrap();
+        assert_eq!(Path::new(&dest), keep_dir.join("a (1).png"));
+        assert!(!image.exists());
+        assert_eq!(
+            must_lock_sessions()
+                .get_by_label(&window_label)
+                .unwrap()
+                .image_paths
+                .paths,
+            vec![dest]
+        );
+        // タグは振り分け先に引き継がれ、移動元からは取り除かれる
+        let dest_tags = get_dir_tags(keep_dir.to_str().unwrap(), true).unwrap();
+        assert_eq!(dest_tags["a (1).png"], vec!["favorite"]);
+        assert_eq!(dest_tags["old.png"], vec!["kept"]);
+        let src_tags = get_dir_tags(
+            temp_dir.path().canonicalize().unwrap().to_str().unwrap(),
+            true,
+        )
+        .unwrap();
+        assert!(!src_tags.contains_key("a.png"));
+        assert_eq!(src_tags["b.png"], vec!["other"]);
+    }
+
+    #[test]
+    fn test_rename_file_renames_tag_entry() {
+        ensure_image_tags_initialized();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image = temp_dir.path().join("a.png");
+        std::fs::write(&image, png(0)).unwrap();
+        std::fs::write(
+            temp_dir.path().join(tag_store::TAG_FILE_NAME),
+            "a.png\tfavorite\nb.png\tother\n",
+        )
+        .unwrap();
+        let image_path = image.to_str().unwrap().to_string();
+        let window_label = create_test_session(vec![image_path.clone()]);
+
+        let dest = rename_session_file(&window_label, image_path, "c.png").unwrap();
+
+        assert_eq!(Path::new(&dest), temp_dir.path().join("c.png"));
+        assert!(!image.exists());
+        assert_eq!(
+            must_lock_sessions()
+                .get_by_label(&window_label)
+                .unwrap()
+                .image_paths
+                .paths,
+            vec![dest]
+        );
+        let dir = temp_dir.path().canonicalize().unwrap();
+        let tags = get_dir_tags(dir.to_str().unwrap(), true).unwrap();
+        assert!(!tags.contains_key("a.png"));
+        assert_eq!(tags["c.png"], vec!["favorite"]);
+        assert_eq!(tags["b.png"], vec!["other"]);
+    }
+
+    #[test]
+    fn test_batch_rename_applies_template_and_renames_tags() {
+        ensure_image_tags_initialized();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let paths = create_test_images(temp_dir.path(), &["a.png", "b.png"]);
+        std::fs::write(
+            temp_dir.path().join(tag_store::TAG_FILE_NAME),
+            "a.png\tcat\nb.png\tdog,sleep\n",
+        )
+        .unwrap();
+        let window_label = create_test_session(paths.clone());
+        let expected = [
+            temp_dir.path().join("001_cat.png"),
+            temp_dir.path().join("002_dog-sleep.png"),
+        ];
+
+        // プレビューでは名前変更しない
+        let preview = batch_rename_session_files(
+            &window_label,
+            paths.clone(),
+            "{seq:03}_{tags:-}.{ext}",
+            true,
+        )
+        .unwrap();
+        assert_eq!(preview[1].destination.as_deref(), expected[1].to_str());
+        assert!(Path::new(&paths[0]).exists());
+
+        let results = batch_rename_session_files(
+            &window_label,
+            paths.clone(),
+            "{seq:03}_{tags:-}.{ext}",
+            false,
+        )
+        .unwrap();
+
+        assert!(results.iter().all(|result| result.error.is_none()));
+        assert!(expected.iter().all(|path| path.exists()));
+        assert_eq!(
+            must_lock_sessions()
+                .get_by_label(&window_label)
+                .unwrap()
+                .image_paths
+                .paths,
+            expected
+                .iter()
+                .map(|path| path.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        );
+        let dir = temp_dir.path().canonicalize().unwrap();
+        let tags = get_dir_tags(dir.to_str().unwrap(), true).unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags["001_cat.png"], vec!["cat"]);
+        assert_eq!(tags["002_dog-sleep.png"], vec!["dog", "sleep"]);
+    }
+
+    #[test]
+    fn test_batch_rename_rejects_collisions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let paths = create_test_images(temp_dir.path(), &["a.png", "b.png"]);
+        let window_label = create_test_session(paths.clone());
+
+        let preview =
+            batch_rename_session_files(&window_label, paths.clone(), "same.{ext}", true).unwrap();
+        assert!(preview.iter().all(|result| result.error.is_some()));
+
+        let result = batch_rename_session_files(&window_label, paths.clone(), "same.{ext}", false);
+        assert!(result.is_err());
+        assert!(paths.iter().all(|path| Path::new(path).exists()));
+    }
+
+    #[test]
+    fn test_batch_rename_reports_tag_write_failure() {
+        ensure_image_tags_initialized();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dirs = [temp_dir.path().join("x"), temp_dir.path().join("y")];
+        let mut paths = Vec::new();
+        for dir in &dirs {
+            std::fs::create_dir(dir).unwrap();
+            std::fs::write(dir.join("a.png"), png(0)).unwrap();
+            std::fs::write(dir.join(tag_store::TAG_FILE_NAME), "a.png\tcat\n").unwrap();
+            paths.push(dir.join("a.png").to_str().unwrap().to_string());
+        }
+        // xのタグファイルの一時ファイルを作れないようにする
+        std::fs::create_dir(dirs[0].join(tag_store::TAG_TEMP_FILE_NAME)).unwrap();
+        let window_label = create_test_session(paths.clone());
+
+        let results =
+            batch_rename_session_files(&window_label, paths, "{seq:03}.{ext}", false).unwrap();
+
+        // 名前変更は済んでいるので、タグを付け替えられなかった画像も変更後のパスを返す
+        assert_eq!(
+            results[0].destination.as_deref(),
+            dirs[0].join("001.png").to_str()
+        );
+        assert!(results[0].error.is_some());
+        assert_eq!(results[1].error, None);
+        let tags = get_dir_tags(dirs[1].canonicalize().unwrap().to_str().unwrap(), true).unwrap();
+        assert_eq!(tags["002.png"], vec!["cat"]);
+    }
+
+    #[test]
+    fn test_rename_file_security_unmanaged_path() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image = temp_dir.path().join("a.png");
+        std::fs::write(&image, png(0)).unwrap();
+        let window_label = create_test_session(vec![]);
+
+        let result =
+            rename_session_file(&window_label, image.to_str().unwrap().to_string(), "c.png");
+
+        assert_eq!(result.unwrap_err().kind, ErrorKind::Unauthorized);
+        assert!(image.exists());
+    }
+
+    #[test]
+    fn test_send_to_sort_bin_unknown_bin() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image = temp_dir.path().join("a.png");
+        std::fs::write(&image, png(0)).unwrap();
+        let image_path = image.to_str().unwrap().to_string();
+        let window_label = create_test_session(vec![image_path.clone()]);
+        let store = SortBinStore::new(temp_dir.path().join(sort_bin::SORT_BINS_FILE_NAME));
+
+        let result = send_session_file_to_sort_bin(
+            &store,
+            &window_label,
+            image_path,
+            "keep",
+            TransferMode::Copy,
+        );
+
+        let error = result.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::InvalidInput);
+        assert!(error.message.contains("not configured"));
+        assert!(image.exists());
+    }
+
+    #[test]
+    fn test_delete_file_security_other_session() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let test_file = temp_dir.path().join("test.png");
+        std::fs::write(&test_file, png(0)).unwrap();
+        let test_path = test_file.to_str().unwrap().to_string();
+
+        // 別のセッションが管理しているパスは削除できない
+        create_test_session(vec![test_path.clone()]);
+        let window_label = create_test_session(vec![]);
+
+        let result = delete_session_file(&window_label, test_path);
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .message
+            .contains("unauthorized file deletion"));
+        assert!(test_file.exists());
+    }
+
+    #[test]
+    fn test_delete_file_archive_entry() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let zip = temp_dir.path().join("comic.cbz");
+        archive::test_files::write_zip(&zip, &[("001.png", &png(0)[..])]);
+        let entry_path = archive::entry_path(zip.to_str().unwrap(), "001.png");
+
+        // アーカイブ内のエントリは削除できない（アーカイブ自体も削除しない）
+        let window_label = create_test_session(vec![entry_path.clone()]);
+        let result = delete_session_file(&window_label, entry_path);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().message.contains("archive"));
+        assert!(zip.exists());
+    }
+
+    #[test]
+    fn test_authorize_session_path_unknown_window() {
+        create_test_session(vec!["managed_file.jpg".to_string()]);
+
+        let result = authorize_session_path("main", "managed_file.jpg");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().message.contains("unauthorized path"));
+    }
 }
@@ -0,0 +1,720 @@
+use std::io::Read;
+use std::path::Path;
+
+use crate::image_format::{self, ImageFormat};
+use crate::raw;
+
+// XMPパケットを探すためにファイル先頭から読み込む最大バイト数
+const XMP_SEARCH_LIMIT: u64 = 1024 * 1024;
+const XMP_PACKET_START: &str = "<x:xmpmeta";
+const XMP_PACKET_END: &str = "</x:xmpmeta>";
+
+// JPEGのAPP13セグメント（Photoshopの画像リソース）の識別子と、IPTC-IIMのリソースID
+const PHOTOSHOP_SIGNATURE: &[u8] = b"Photoshop 3.0\0";
+const IPTC_RESOURCE_ID: u16 = 0x0404;
+// IPTC-IIMのアプリケーションレコードのキーワード（2:25）とキャプション（2:120）
+const IPTC_KEYWORDS: (u8, u8) = (2, 25);
+const IPTC_CAPTION: (u8, u8) = (2, 120);
+
+// 画像のメタデータ
+// EXIF（JPEG/WebP/PNG/TIFF/HEIF）・XMP・IPTC（JPEG）から取得し、取得できなかったフィールドは None とする
+#[derive(serde::Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageMetadata {
+    // 撮影日時（"YYYY-MM-DDTHH:MM:SS"）
+    pub capture_date: Option<String>,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens_model: Option<String>,
+    // 露出時間（"1/250" や "2.5" のような表示用の文字列）
+    pub exposure_time: Option<String>,
+    pub f_number: Option<f64>,
+    pub iso: Option<u32>,
+    // 焦点距離（mm）
+    pub focal_length: Option<f64>,
+    // 35mm判換算の焦点距離（mm）
+    pub focal_length_in_35mm: Option<u32>,
+    pub gps: Option<GpsPosition>,
+    // EXIFのOrientation（1〜8）
+    pub orientation: Option<u16>,
+    // XMPのレーティング（xmp:Rating）
+    pub rating: Option<i32>,
+    // XMPのキーワード（dc:subject）、なければIPTCのキーワード
+    pub keywords: Option<Vec<String>>,
+    // XMPのキャプション（dc:description）、なければIPTCのキャプション
+    pub caption: Option<String>,
+}
+
+// GPSの位置情報
+// 緯度・経度は南緯・西経を負の値とした10進数の度、高度は海抜（m）
+#[derive(serde::Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+}
+
+// 画像のメタデータを読み込む
+// メタデータを持たない画像の場合は全てのフィールドが None になる
+pub fn read_image_metadata(path: &Path) -> ImageMetadata {
+    let head = read_head(path).unwrap_or_default();
+    build_metadata(read_exif(path), &head)
+}
+
+// メモリ上の画像データ（アーカイブ内のエントリ等）のメタデータを読み込む
+pub fn read_image_metadata_from_bytes(format: ImageFormat, data: &[u8]) -> ImageMetadata {
+    let head = &data[..data.len().min(XMP_SEARCH_LIMIT as usize)];
+    build_metadata(read_exif_from_bytes(format, data), head)
+}
+
+// EXIFとファイル先頭のデータ（XMP・IPTCを探す）からメタデータを組み立てる
+fn build_metadata(exif: Option<exif::Exif>, head: &[u8]) -> ImageMetadata {
+    let mut metadata = ImageMetadata::default();
+
+    if let Some(exif) = exif {
+        metadata.capture_date = get_capture_date(&exif);
+        metadata.camera_make = get_ascii(&exif, exif::Tag::Make);
+        metadata.camera_model = get_ascii(&exif, exif::Tag::Model);
+        metadata.lens_model = get_ascii(&exif, exif::Tag::LensModel);
+        metadata.exposure_time =
+            get_rational(&exif, exif::Tag::ExposureTime).map(format_exposure_time);
+        metadata.f_number = get_rational(&exif, exif::Tag::FNumber);
+        metadata.iso = get_uint(&exif, exif::Tag::PhotographicSensitivity);
+        metadata.focal_length = get_rational(&exif, exif::Tag::FocalLength);
+        metadata.focal_length_in_35mm = get_uint(&exif, exif::Tag::FocalLengthIn35mmFilm);
+        metadata.gps = get_gps_position(&exif);
+        metadata.orientation = get_uint(&exif, exif::Tag::Orientation).map(|v| v as u16);
+    }
+
+    if let Some(xmp) = find_xmp_packet(head) {
+        metadata.rating = get_xmp_value(&xmp, "xmp:Rating").and_then(|v| v.parse().ok());
+        metadata.keywords = get_xmp_list(&xmp, "dc:subject");
+        metadata.caption =
+            get_xmp_list(&xmp, "dc:description").and_then(|captions| captions.into_iter().next());
+    }
+
+    // 同じ内容がXMPにもあればXMPを優先する（XMPを書き込むアプリはIPTCを更新しないことがある）
+    let iptc = find_iptc_records(head);
+    if metadata.keywords.is_none() {
+        let keywords: Vec<String> = iptc
+            .iter()
+            .filter(|(dataset, _)| *dataset == IPTC_KEYWORDS)
+            .map(|(_, value)| value.clone())
+            .collect();
+        metadata.keywords = (!keywords.is_empty()).then_some(keywords);
+    }
+    if metadata.caption.is_none() {
+        metadata.caption = iptc
+            .into_iter()
+            .find(|(dataset, _)| *dataset == IPTC_CAPTION)
+            .map(|(_, value)| value);
+    }
+
+    metadata
+}
+
+// 撮影日時を返す
+// DateTimeOriginal がなければ DateTime（ファイルの変更日時）で代用する
+pub fn read_capture_date(path: &Path) -> Option<String> {
+    get_capture_date(&read_exif(path)?)
+}
+
+// EXIFのOrientation（1〜8）を返す
+// タグがない、または範囲外の値の場合は回転なし（1）とする
+pub fn read_orientation(path: &Path) -> u16 {
+    get_orientation(read_exif(path))
+}
+
+// メモリ上の画像データ（アーカイブ内のエントリ等）の撮影日時を返す
+pub fn read_capture_date_from_bytes(format: ImageFormat, data: &[u8]) -> Option<String> {
+    get_capture_date(&read_exif_from_bytes(format, data)?)
+}
+
+// メモリ上の画像データのEXIFのOrientationを返す
+pub fn read_orientation_from_bytes(format: ImageFormat, data: &[u8]) -> u16 {
+    get_orientation(read_exif_from_bytes(format, data))
+}
+
+fn get_orientation(exif: Option<exif::Exif>) -> u16 {
+    exif.and_then(|exif| get_uint(&exif, exif::Tag::Orientation))
+        .filter(|orientation| (1..=8).contains(orientation))
+        .map_or(1, |orientation| orientation as u16)
+}
+
+// 保存されている幅・高さとOrientationから、表示上の幅・高さを返す
+// Orientationが5〜8の場合は90度回転するため、幅と高さが入れ替わる
+pub fn display_dimensions(width: u32, height: u32, orientation: u16) -> (u32, u32) {
+    if (5..=8).contains(&orientation) {
+        (height, width)
+    } else {
+        (width, height)
+    }
+}
+
+// EXIFを読み込む
+// EXIFを持たない、または読み込めない場合は None を返す
+// TIFFベースでないRAW画像（CR3・RAF）は埋め込まれたプレビューのEXIFを読み込む
+fn read_exif(path: &Path) -> Option<exif::Exif> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut reader = std::io::BufReader::new(file);
+    exif::Reader::new()
+        .read_from_container(&mut reader)
+        .ok()
+        .or_else(|| {
+            if image_format::detect_image_format(path) != Some(ImageFormat::Raw) {
+                return None;
+            }
+            let preview = raw::extract_preview(path).ok()?;
+            exif::Reader::new()
+                .read_from_container(&mut std::io::Cursor::new(preview))
+                .ok()
+        })
+}
+
+// メモリ上の画像データからEXIFを読み込む
+// RAW画像の場合は read_exif と同様に埋め込まれたプレビューのEXIFを読み込む
+fn read_exif_from_bytes(format: ImageFormat, data: &[u8]) -> Option<exif::Exif> {
+    exif::Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(data))
+        .ok()
+        .or_else(|| {
+            if format != ImageFormat::Raw {
+                return None;
+            }
+            let preview = raw::extract_preview_from_bytes(data).ok()?;
+            exif::Reader::new()
+                .read_from_container(&mut std::io::Cursor::new(preview))
+                .ok()
+        })
+}
+
+fn get_capture_date(exif: &exif::Exif) -> Option<String> {
+    get_date_time(exif, exif::Tag::DateTimeOriginal)
+        .or_else(|| get_date_time(exif, exif::Tag::DateTime))
+}
+
+// EXIFの日時フィールドを "YYYY-MM-DDTHH:MM:SS" 形式の文字列に変換する
+// 文字列の大小比較がそのまま日時の前後関係になる
+fn get_date_time(exif: &exif::Exif, tag: exif::Tag) -> Option<String> {
+    let field = exif.get_field(tag, exif::In::PRIMARY)?;
+    let exif::Value::Ascii(ref values) = field.value else {
+        return None;
+    };
+    let date_time = exif::DateTime::from_ascii(values.first()?).ok()?;
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        date_time.year,
+        date_time.month,
+        date_time.day,
+        date_time.hour,
+        date_time.minute,
+        date_time.second
+    ))
+}
+
+// ASCIIのフィールドを文字列で返す（末尾のNUL・空白は除く）
+fn get_ascii(exif: &exif::Exif, tag: exif::Tag) -> Option<String> {
+    let field = exif.get_field(tag, exif::In::PRIMARY)?;
+    let exif::Value::Ascii(ref values) = field.value else {
+        return None;
+    };
+    let value = String::from_utf8_lossy(values.first()?)
+        .trim_end_matches(['\0', ' '])
+        .to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+fn get_uint(exif: &exif::Exif, tag: exif::Tag) -> Option<u32> {
+    exif.get_field(tag, exif::In::PRIMARY)?.value.get_uint(0)
+}
+
+fn get_rational(exif: &exif::Exif, tag: exif::Tag) -> Option<f64> {
+    get_rationals(exif, tag)?.first().copied()
+}
+
+// 符号なし・符号付きの有理数のフィールドを f64 の配列で返す
+// 分母が0の値を含む場合は None を返す
+fn get_rationals(exif: &exif::Exif, tag: exif::Tag) -> Option<Vec<f64>> {
+    let field = exif.get_field(tag, exif::In::PRIMARY)?;
+    let values: Vec<f64> = match field.value {
+        exif::Value::Rational(ref values) if values.iter().all(|v| v.denom != 0) => {
+            values.iter().map(|v| v.to_f64()).collect()
+        }
+        exif::Value::SRational(ref values) if values.iter().all(|v| v.denom != 0) => {
+            values.iter().map(|v| v.to_f64()).collect()
+        }
+        _ => return None,
+    };
+    (!values.is_empty()).then_some(values)
+}
+
+// 露出時間（秒）を表示用の文字列にする
+// 1秒未満は "1/250" のような分数、1秒以上は "2.5" のような小数にする
+fn format_exposure_time(seconds: f64) -> String {
+    if seconds > 0.0 && seconds < 1.0 {
+        format!("1/{}", (1.0 / seconds).round())
+    } else {
+        let formatted = format!("{seconds:.1}");
+        formatted.trim_end_matches(".0").to_string()
+    }
+}
+
+fn get_gps_position(exif: &exif::Exif) -> Option<GpsPosition> {
+    let latitude = get_gps_coordinate(exif, exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef)?;
+    let longitude = get_gps_coordinate(exif, exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef)?;
+    // GPSAltitudeRef が1の場合は海面下
+    let altitude = get_rational(exif, exif::Tag::GPSAltitude).map(|altitude| {
+        if get_uint(exif, exif::Tag::GPSAltitudeRef) == Some(1) {
+            -altitude
+        } else {
+            altitude
+        }
+    });
+    Some(GpsPosition {
+        latitude,
+        longitude,
+        altitude,
+    })
+}
+
+// 度・分・秒の3つの値と方角（N/S/E/W）から10進数の度を返す
+fn get_gps_coordinate(exif: &exif::Exif, tag: exif::Tag, ref_tag: exif::Tag) -> Option<f64> {
+    let values = get_rationals(exif, tag)?;
+    let degrees = values.first()?;
+    let minutes = values.get(1).unwrap_or(&0.0);
+    let seconds = values.get(2).unwrap_or(&0.0);
+    let coordinate = degrees + minutes / 60.0 + seconds / 3600.0;
+    match get_ascii(exif, ref_tag).as_deref() {
+        Some("S") | Some("W") => Some(-coordinate),
+        _ => Some(coordinate),
+    }
+}
+
+// XMP・IPTCを探すためにファイル先頭を読み込む
+fn read_head(path: &Path) -> Option<Vec<u8>> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut data = Vec::new();
+    file.take(XMP_SEARCH_LIMIT).read_to_end(&mut data).ok()?;
+    Some(data)
+}
+
+// ファイル内のXMPパケット（<x:xmpmeta>〜</x:xmpmeta>）を探して返す
+// JPEG・PNG・WebPなどのコンテナに関わらず、非圧縮で埋め込まれているものを対象とする
+fn find_xmp_packet(data: &[u8]) -> Option<String> {
+    let start = find_bytes(data, XMP_PACKET_START.as_bytes())?;
+    let end = find_bytes(&data[start..], XMP_PACKET_END.as_bytes())? + start;
+    let packet = &data[start..end + XMP_PACKET_END.len()];
+    Some(String::from_utf8_lossy(packet).into_owned())
+}
+
+// JPEGのAPP13セグメントのIPTC-IIMのデータセットを、((レコード, データセット), 値) の配列で返す
+// JPEG以外（TIFFのIPTCタグ等）には対応しない。値はUTF-8として読み込む
+fn find_iptc_records(data: &[u8]) -> Vec<((u8, u8), String)> {
+    let mut records = Vec::new();
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return records;
+    }
+    let mut offset = 2;
+    // SOS以降は画像データなので、その前のセグメントだけを見る
+    while let Some(&[0xFF, marker, len_hi, len_lo]) = data.get(offset..offset + 4) {
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let len = u16::from_be_bytes([len_hi, len_lo]) as usize;
+        let Some(payload) = data.get(offset + 4..offset + 2 + len) else {
+            break;
+        };
+        if marker == 0xED {
+            if let Some(resources) = payload.strip_prefix(PHOTOSHOP_SIGNATURE) {
+                records.extend(parse_iptc(find_photoshop_resource(
+                    resources,
+                    IPTC_RESOURCE_ID,
+                )));
+            }
+        }
+        offset += 2 + len;
+    }
+    records
+}
+
+// Photoshopの画像リソース（"8BIM"、ID、パスカル文字列の名前、サイズ、データ）から指定されたIDのデータを返す
+// 名前とデータは偶数バイトに揃えられている
+fn find_photoshop_resource(mut resources: &[u8], id: u16) -> &[u8] {
+    while let Some(rest) = resources.strip_prefix(b"8BIM") {
+        let Some(&[id_hi, id_lo, name_len]) = rest.get(..3) else {
+            break;
+        };
+        let name_size = (1 + name_len as usize).next_multiple_of(2);
+        let Some(size_bytes) = rest.get(2 + name_size..6 + name_size) else {
+            break;
+        };
+        let size = u32::from_be_bytes(size_bytes.try_into().expect("4 bytes")) as usize;
+        let data_start = 6 + name_size;
+        let Some(resource) = rest.get(data_start..data_start + size) else {
+            break;
+        };
+        if u16::from_be_bytes([id_hi, id_lo]) == id {
+            return resource;
+        }
+        resources = rest
+            .get(data_start + size.next_multiple_of(2)..)
+            .unwrap_or_default();
+    }
+    &[]
+}
+
+// IPTC-IIMのデータセット（0x1C、レコード、データセット、サイズ、値）を読み込む
+// 拡張サイズ（32KB以上）のデータセットがあれば、そこで読み込みを止める
+fn parse_iptc(mut data: &[u8]) -> Vec<((u8, u8), String)> {
+    let mut records = Vec::new();
+    while let Some(&[0x1C, record, dataset, size_hi, size_lo]) = data.get(..5) {
+        if size_hi & 0x80 != 0 {
+            break;
+        }
+        let size = u16::from_be_bytes([size_hi, size_lo]) as usize;
+        let Some(value) = data.get(5..5 + size) else {
+            break;
+        };
+        let value = String::from_utf8_lossy(value).trim().to_string();
+        if !value.is_empty() {
+            records.push(((record, dataset), value));
+        }
+        data = &data[5 + size..];
+    }
+    records
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// XMPの単一の値を返す
+// 属性（name="value"）と要素（<name>value</name>）のどちらの形式にも対応する
+fn get_xmp_value(xmp: &str, name: &str) -> Option<String> {
+    let attribute_prefix = format!("{name}=\"");
+    if let Some(start) = xmp.find(&attribute_prefix) {
+        let value_start = start + attribute_prefix.len();
+        let value_end = xmp[value_start..].find('"')? + value_start;
+        return Some(unescape_xml(&xmp[value_start..value_end]));
+    }
+    get_xmp_element_text(xmp, name).map(|text| unescape_xml(text.trim()))
+}
+
+// XMPの配列（<name><rdf:Bag><rdf:li>value</rdf:li>...</rdf:Bag></name>）の値を返す
+fn get_xmp_list(xmp: &str, name: &str) -> Option<Vec<String>> {
+    let content = get_xmp_element_text(xmp, name)?;
+    let values: Vec<String> = content
+        .split("<rdf:li")
+        .skip(1)
+        .filter_map(|item| {
+            let text_start = item.find('>')? + 1;
+            let text_end = item.find("</rdf:li>")?;
+            Some(unescape_xml(item.get(text_start..text_end)?.trim()))
+        })
+        .filter(|value| !value.is_empty())
+        .collect();
+    (!values.is_empty()).then_some(values)
+}
+
+// <name ...>〜</name> の内側の文字列を返す
+fn get_xmp_element_text<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    let open_tag = format!("<{name}");
+    let close_tag = format!("</{name}>");
+    let start = xmp.find(&open_tag)?;
+    let content_start = xmp[start..].find('>')? + start + 1;
+    let content_end = xmp[content_start..].find(&close_tag)? + content_start;
+    Some(&xmp[content_start..content_end])
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// テスト用のメタデータ付き画像データを作成するヘルパー
+#[cfg(test)]
+pub mod test_images {
+    use std::io::Cursor;
+
+    // 指定されたEXIFフィールドとXMPパケットを持つJPEGのデータを返す
+    // 画像データ自体は持たないが、SOF0セグメントで幅・高さを指定する
+    // 構造はJPEGとして正しいので、RAW画像のプレビューとしても使える
+    pub fn jpeg_with_metadata(
+        fields: &[exif::Field],
+        xmp: Option<&str>,
+        width: u16,
+        height: u16,
+    ) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8]; // SOI
+
+        if !fields.is_empty() {
+            let mut writer = exif::experimental::Writer::new();
+            for field in fields {
+                writer.push_field(field);
+            }
+            let mut tiff = Cursor::new(Vec::new());
+            writer
+                .write(&mut tiff, false)
+                .expect("Failed to write EXIF");
+            let mut payload = b"Exif\0\0".to_vec();
+            payload.extend(tiff.into_inner());
+            push_segment(&mut data, 0xE1, &payload);
+        }
+
+        if let Some(xmp) = xmp {
+            let mut payload = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+            payload.extend(xmp.as_bytes());
+            push_segment(&mut data, 0xE1, &payload);
+        }
+
+        // SOF0: 精度, 高さ, 幅, 成分数, 成分の情報
+        let mut sof = vec![8];
+        sof.extend(height.to_be_bytes());
+        sof.extend(width.to_be_bytes());
+        sof.extend([1, 1, 0x11, 0]);
+        push_segment(&mut data, 0xC0, &sof);
+
+        // SOS: 成分数, 成分の情報, スペクトル選択, 逐次近似
+        push_segment(&mut data, 0xDA, &[1, 1, 0, 0, 0x3F, 0]);
+        data.push(0); // 圧縮データ
+
+        data.extend([0xFF, 0xD9]); // EOI
+        data
+    }
+
+    // JPEGのデータのSOIの直後に、指定されたIPTC-IIMのデータセット（(レコード, データセット), 値）の
+    // APP13セグメントを追加して返す
+    // IPTCの前に名前付きの別の画像リソースを置き、名前とデータの偶数バイト揃えも読めるか確かめられるようにする
+    pub fn jpeg_with_iptc(jpeg: &[u8], records: &[((u8, u8), &str)]) -> Vec<u8> {
+        let mut iptc = Vec::new();
+        for &((record, dataset), value) in records {
+            iptc.extend([0x1C, record, dataset]);
+            iptc.extend((value.len() as u16).to_be_bytes());
+            iptc.extend(value.as_bytes());
+        }
+        let mut payload = b"Photoshop 3.0\0".to_vec();
+        // 名前 "ab"（長さ込みで3バイト→4バイト）、データ1バイト（→2バイト）のリソース
+        payload.extend(b"8BIM\x03\xED\x02ab\0\0\0\0\x01\x00\x00");
+        payload.extend(b"8BIM\x04\x04\0\0");
+        payload.extend((iptc.len() as u32).to_be_bytes());
+        payload.extend(&iptc);
+
+        let mut data = jpeg[..2].to_vec();
+        push_segment(&mut data, 0xED, &payload);
+        data.extend(&jpeg[2..]);
+        data
+    }
+
+    fn push_segment(data: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+        data.extend([0xFF, marker]);
+        data.extend(((payload.len() + 2) as u16).to_be_bytes());
+        data.extend(payload);
+    }
+
+    pub fn ascii_field(tag: exif::Tag, value: &str) -> exif::Field {
+        exif::Field {
+            tag,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Ascii(vec![value.as_bytes().to_vec()]),
+        }
+    }
+
+    pub fn short_field(tag: exif::Tag, value: u16) -> exif::Field {
+        exif::Field {
+            tag,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Short(vec![value]),
+        }
+    }
+
+    pub fn rational_field(tag: exif::Tag, values: &[(u32, u32)]) -> exif::Field {
+        exif::Field {
+            tag,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Rational(
+                values
+                    .iter()
+                    .map(|&(num, denom)| exif::Rational { num, denom })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_images::*;
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const TEST_XMP: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF><rdf:Description xmp:Rating="4"><dc:subject><rdf:Bag><rdf:li>sunset</rdf:li><rdf:li>Tom &amp; Jerry</rdf:li></rdf:Bag></dc:subject></rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+    fn write_test_image(dir: &TempDir, data: &[u8]) -> std::path::PathBuf {
+        let path = dir.path().join("photo.jpg");
+        fs::write(&path, data).expect("Failed to write test image");
+        path
+    }
+
+    #[test]
+    fn test_read_image_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        let fields = [
+            ascii_field(exif::Tag::Make, "Canon"),
+            ascii_field(exif::Tag::Model, "EOS R5"),
+            ascii_field(exif::Tag::LensModel, "RF24-70mm F2.8 L IS USM"),
+            ascii_field(exif::Tag::DateTimeOriginal, "2024:05:06 07:08:09"),
+            rational_field(exif::Tag::ExposureTime, &[(1, 250)]),
+            rational_field(exif::Tag::FNumber, &[(28, 10)]),
+            short_field(exif::Tag::PhotographicSensitivity, 400),
+            rational_field(exif::Tag::FocalLength, &[(50, 1)]),
+            short_field(exif::Tag::Orientation, 6),
+            ascii_field(exif::Tag::GPSLatitudeRef, "N"),
+            rational_field(exif::Tag::GPSLatitude, &[(35, 1), (30, 1), (0, 1)]),
+            ascii_field(exif::Tag::GPSLongitudeRef, "W"),
+            rational_field(exif::Tag::GPSLongitude, &[(139, 1), (45, 1), (0, 1)]),
+        ];
+        let path = write_test_image(
+            &temp_dir,
+            &jpeg_with_metadata(&fields, Some(TEST_XMP), 4, 3),
+        );
+
+        let metadata = read_image_metadata(&path);
+
+        assert_eq!(
+            metadata.capture_date.as_deref(),
+            Some("2024-05-06T07:08:09")
+        );
+        assert_eq!(metadata.camera_make.as_deref(), Some("Canon"));
+        assert_eq!(metadata.camera_model.as_deref(), Some("EOS R5"));
+        assert_eq!(
+            metadata.lens_model.as_deref(),
+            Some("RF24-70mm F2.8 L IS USM")
+        );
+        assert_eq!(metadata.exposure_time.as_deref(), Some("1/250"));
+        assert_eq!(metadata.f_number, Some(2.8));
+        assert_eq!(metadata.iso, Some(400));
+        assert_eq!(metadata.focal_length, Some(50.0));
+        assert_eq!(metadata.focal_length_in_35mm, None);
+        assert_eq!(metadata.orientation, Some(6));
+        assert_eq!(
+            metadata.gps,
+            Some(GpsPosition {
+                latitude: 35.5,
+                longitude: -139.75,
+                altitude: None,
+            })
+        );
+        assert_eq!(metadata.rating, Some(4));
+        assert_eq!(
+            metadata.keywords,
+            Some(vec!["sunset".to_string(), "Tom & Jerry".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_read_image_metadata_without_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = write_test_image(&temp_dir, &jpeg_with_metadata(&[], None, 4, 3));
+
+        assert_eq!(read_image_metadata(&path), ImageMetadata::default());
+        assert_eq!(read_capture_date(&path), None);
+    }
+
+    #[test]
+    fn test_read_iptc_keywords_and_caption() {
+        let data = jpeg_with_iptc(
+            &jpeg_with_metadata(&[], None, 1, 1),
+            &[
+                ((1, 90), "\x1b%G"),
+                ((2, 25), "sunset"),
+                ((2, 25), "海"),
+                ((2, 120), "Evening at the beach"),
+            ],
+        );
+
+        let metadata = read_image_metadata_from_bytes(ImageFormat::Jpeg, &data);
+
+        assert_eq!(
+            metadata.keywords,
+            Some(vec!["sunset".to_string(), "海".to_string()])
+        );
+        assert_eq!(metadata.caption.as_deref(), Some("Evening at the beach"));
+    }
+
+    #[test]
+    fn test_xmp_takes_precedence_over_iptc() {
+        let temp_dir = TempDir::new().unwrap();
+        let xmp = r#"<x:xmpmeta><dc:subject><rdf:Bag><rdf:li>xmp</rdf:li></rdf:Bag></dc:subject><dc:description><rdf:Alt><rdf:li xml:lang="x-default">XMP caption</rdf:li></rdf:Alt></dc:description></x:xmpmeta>"#;
+        let data = jpeg_with_iptc(
+            &jpeg_with_metadata(&[], Some(xmp), 1, 1),
+            &[((2, 25), "iptc"), ((2, 120), "IPTC caption")],
+        );
+        let path = write_test_image(&temp_dir, &data);
+
+        let metadata = read_image_metadata(&path);
+
+        assert_eq!(metadata.keywords, Some(vec!["xmp".to_string()]));
+        assert_eq!(metadata.caption.as_deref(), Some("XMP caption"));
+    }
+
+    #[test]
+    fn test_read_orientation() {
+        let temp_dir = TempDir::new().unwrap();
+        let rotated = write_test_image(
+            &temp_dir,
+            &jpeg_with_metadata(&[short_field(exif::Tag::Orientation, 6)], None, 4, 3),
+        );
+        assert_eq!(read_orientation(&rotated), 6);
+        assert_eq!(display_dimensions(4, 3, 6), (3, 4));
+
+        let invalid = write_test_image(
+            &temp_dir,
+            &jpeg_with_metadata(&[short_field(exif::Tag::Orientation, 9)], None, 4, 3),
+        );
+        assert_eq!(read_orientation(&invalid), 1);
+
+        let plain = write_test_image(&temp_dir, &jpeg_with_metadata(&[], None, 4, 3));
+        assert_eq!(read_orientation(&plain), 1);
+        assert_eq!(display_dimensions(4, 3, 1), (4, 3));
+        assert_eq!(display_dimensions(4, 3, 3), (4, 3));
+    }
+
+    #[test]
+    fn test_read_raw_metadata_from_preview() {
+        let temp_dir = TempDir::new().unwrap();
+        let preview = jpeg_with_metadata(&[ascii_field(exif::Tag::Model, "X-T5")], None, 4, 3);
+        let raf = temp_dir.path().join("DSCF0001.RAF");
+        let mut data = b"FUJIFILMCCD-RAW 0201".to_vec();
+        data.extend(&preview);
+        fs::write(&raf, data).unwrap();
+
+        assert_eq!(
+            read_image_metadata(&raf).camera_model.as_deref(),
+            Some("X-T5")
+        );
+    }
+
+    #[test]
+    fn test_format_exposure_time() {
+        assert_eq!(format_exposure_time(0.004), "1/250");
+        assert_eq!(format_exposure_time(0.5), "1/2");
+        assert_eq!(format_exposure_time(1.0), "1");
+        assert_eq!(format_exposure_time(2.5), "2.5");
+    }
+
+    #[test]
+    fn test_get_xmp_value_element_form() {
+        let xmp = "<x:xmpmeta><xmp:Rating> 5 </xmp:Rating></x:xmpmeta>";
+
+        assert_eq!(get_xmp_value(xmp, "xmp:Rating").as_deref(), Some("5"));
+        assert_eq!(get_xmp_value(xmp, "xmp:Label"), None);
+    }
+}
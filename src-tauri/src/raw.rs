@@ -0,0 +1,624 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+// カメラのRAW画像の拡張子
+const RAW_EXTENSIONS: [&str; 6] = ["cr2", "cr3", "nef", "arw", "dng", "raf"];
+
+// プレビューの候補が表示できるJPEGか確かめるために読む先頭のバイト数
+// （SOFまでにEXIF等のAPPセグメントがあるので、その最大長より大きくする）
+const PREVIEW_HEAD_SIZE: u64 = 128 * 1024;
+
+// 壊れたファイルで延々と読み続けないように、たどるIFD・ボックスの数の上限
+const MAX_IFDS: usize = 32;
+const MAX_IFD_ENTRIES: u16 = 1024;
+const MAX_BOXES: usize = 64;
+
+// CR3のプレビュー（PRVWボックス）を含むuuidボックスのUUID
+const CR3_PREVIEW_UUID: [u8; 16] = [
+    0xea, 0xf4, 0x2b, 0x5e, 0x1c, 0x98, 0x4b, 0x88, 0xb9, 0xfb, 0xb7, 0xdc, 0x40, 0x6e, 0x4d, 0x16,
+];
+
+// TIFFのタグ
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+
+// RAW画像の拡張子（大文字小文字は区別しない）かどうか
+pub fn is_raw_extension(ext: &str) -> bool {
+    RAW_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+}
+
+// RAWファイルに埋め込まれたプレビュー用のJPEGを返す
+// RAWファイルは数十MBあるので、形式毎の構造（TIFFのIFD・RAFのヘッダ・CR3のボックス）から
+// プレビューの位置を求めて、その範囲だけを読む
+// 構造からプレビューが見つからない形式では、ファイル全体からJPEGのストリームを探して
+// ブラウザで表示できるもののうち最も大きいもの（通常は撮影サイズに近いプレビュー）を返す
+pub fn extract_preview(path: &Path) -> Result<Vec<u8>, String> {
+    let mut file =
+        BufReader::new(File::open(path).map_err(|e| format!("Failed to read RAW file: {e}"))?);
+    if let Some(location) = locate_preview(&mut file) {
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(location.range.start))
+            .and_then(|_| {
+                (&mut file)
+                    .take(location.range.end - location.range.start)
+                    .read_to_end(&mut data)
+            })
+            .map_err(|e| format!("Failed to read RAW file: {e}"))?;
+        if let Some(range) = find_largest_jpeg(&data) {
+            data.truncate(range.end);
+            data.drain(..range.start);
+            return Ok(data);
+        }
+    }
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read RAW file: {e}"))?;
+    extract_preview_from_bytes(&data).map(|preview| preview.to_vec())
+}
+
+// メモリ上のRAW画像のデータ（アーカイブ内のエントリ等）から埋め込まれたプレビューを返す
+pub fn extract_preview_from_bytes(data: &[u8]) -> Result<&[u8], String> {
+    let located = locate_preview(&mut Cursor::new(data)).and_then(|location| {
+        let start = usize::try_from(location.range.start).ok()?;
+        let end = usize::try_from(location.range.end).ok()?;
+        let candidate = data.get(start..end)?;
+        find_largest_jpeg(candidate).map(|range| &candidate[range])
+    });
+    located
+        .or_else(|| find_largest_jpeg(data).map(|range| &data[range]))
+        .ok_or_else(|| "RAW file has no embedded preview".to_string())
+}
+
+// 埋め込まれたプレビューの幅・高さを返す
+// ビューアに表示されるのはプレビューなので、センサーの画素数ではなくプレビューの寸法とする
+// 寸法はJPEGのヘッダから分かるので、構造からプレビューが見つかった場合はその先頭だけを読む
+pub fn preview_dimensions(path: &Path) -> Result<(u32, u32), String> {
+    let mut file =
+        BufReader::new(File::open(path).map_err(|e| format!("Failed to read RAW file: {e}"))?);
+    if let Some(size) =
+        locate_preview(&mut file).and_then(|location| imagesize::blob_size(&location.head).ok())
+    {
+        return Ok((size.width as u32, size.height as u32));
+    }
+    let preview = extract_preview(path)?;
+    jpeg_dimensions(&preview)
+}
+
+// メモリ上のRAW画像のデータから埋め込まれたプレビューの幅・高さを返す
+pub fn preview_dimensions_from_bytes(data: &[u8]) -> Result<(u32, u32), String> {
+    jpeg_dimensions(extract_preview_from_bytes(data)?)
+}
+
+fn jpeg_dimensions(jpeg: &[u8]) -> Result<(u32, u32), String> {
+    imagesize::blob_size(jpeg)
+        .map(|size| (size.width as u32, size.height as u32))
+        .map_err(|e| e.to_string())
+}
+
+// 構造から見つけたプレビューのJPEGの位置と、その先頭部分
+struct PreviewLocation {
+    range: Range<u64>,
+    head: Vec<u8>,
+}
+
+// 形式毎の構造からプレビューのJPEGの候補を求め、表示できるもののうち最も大きいものを返す
+// RAWデータ自体もJPEG（ロスレス）として格納されている場合があるので、先頭を読んで確かめる
+fn locate_preview<R: Read + Seek>(reader: &mut R) -> Option<PreviewLocation> {
+    let file_size = reader.seek(SeekFrom::End(0)).ok()?;
+    let mut header = [0; 92];
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    let header_len = read_up_to(reader, &mut header).ok()?;
+    let header = &header[..header_len];
+
+    let mut candidates = if header.starts_with(b"FUJIFILMCCD-RAW ") {
+        raf_preview(header).into_iter().collect()
+    } else if header.get(4..8) == Some(&b"ftyp"[..]) {
+        cr3_preview(reader, file_size).into_iter().collect()
+    } else {
+        tiff_candidates(reader, 0)
+    };
+    candidates.retain(|range| range.start < range.end && range.end <= file_size);
+    candidates.sort_by_key(|range| std::cmp::Reverse(range.end - range.start));
+    candidates.dedup();
+
+    candidates.into_iter().find_map(|range| {
+        let mut head = vec![0; (range.end - range.start).min(PREVIEW_HEAD_SIZE) as usize];
+        reader.seek(SeekFrom::Start(range.start)).ok()?;
+        reader.read_exact(&mut head).ok()?;
+        is_displayable_jpeg_head(&head).then_some(PreviewLocation { range, head })
+    })
+}
+
+// RAFのヘッダにあるプレビューのJPEGの位置（84バイト目から、ビッグエンディアンのオフセットと長さ）
+fn raf_preview(header: &[u8]) -> Option<Range<u64>> {
+    let read_u32 = |pos: usize| {
+        header
+            .get(pos..pos + 4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64)
+    };
+    let offset = read_u32(84)?;
+    Some(offset..offset + read_u32(88)?)
+}
+
+// CR3（ISOベースメディアファイル形式）のプレビュー用のuuidボックス内のJPEGの位置
+// ボックスの中にはPRVWボックスのヘッダに続いてJPEGが入っているので、SOIの位置から始める
+fn cr3_preview<R: Read + Seek>(reader: &mut R, file_size: u64) -> Option<Range<u64>> {
+    let mut pos = 0;
+    for _ in 0..MAX_BOXES {
+        let mut header = [0; 16];
+        reader.seek(SeekFrom::Start(pos)).ok()?;
+        reader.read_exact(&mut header[..8]).ok()?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut content = pos + 8;
+        if size == 1 {
+            reader.read_exact(&mut header[8..16]).ok()?;
+            size = u64::from_be_bytes(header[8..16].try_into().ok()?);
+            content += 8;
+        } else if size == 0 {
+            size = file_size - pos;
+        }
+        let end = pos.checked_add(size)?;
+        if size < 8 || end > file_size {
+            return None;
+        }
+        if &header[4..8] == b"uuid" {
+            let mut uuid = [0; 16];
+            reader.read_exact(&mut uuid).ok()?;
+            if uuid == CR3_PREVIEW_UUID {
+                let mut head = [0; 256];
+                let head_len = read_up_to(reader, &mut head).ok()?;
+                let soi = find_bytes(&head[..head_len], &[0xFF, 0xD8, 0xFF])?;
+                return Some(content + 16 + soi as u64..end);
+            }
+        }
+        pos = end;
+    }
+    None
+}
+
+// TIFFベースのRAW（CR2・NEF・ARW・DNG等）のIFDとSubIFDをたどって、プレビューのJPEGの位置を集める
+// JPEGInterchangeFormat（サムネイル・プレビュー）と、JPEG圧縮の1ストリップの画像を候補にする
+fn tiff_candidates<R: Read + Seek>(reader: &mut R, base: u64) -> Vec<Range<u64>> {
+    let mut header = [0; 8];
+    if reader.seek(SeekFrom::Start(base)).is_err() || reader.read_exact(&mut header).is_err() {
+        return Vec::new();
+    }
+    let little_endian = match &header[..4] {
+        b"II*\0" => true,
+        b"MM\0*" => false,
+        _ => return Vec::new(),
+    };
+    let mut tiff = Tiff {
+        reader,
+        base,
+        little_endian,
+    };
+
+    let mut candidates = Vec::new();
+    let mut queue = vec![tiff.u32_at(4).unwrap_or(0)];
+    let mut visited = HashSet::new();
+    while let Some(ifd_offset) = queue.pop() {
+        if ifd_offset == 0 || visited.len() >= MAX_IFDS || !visited.insert(ifd_offset) {
+            continue;
+        }
+        let Some(ifd) = tiff.read_ifd(ifd_offset) else {
+            continue;
+        };
+        if let (Some(&offset), Some(&length)) = (
+            ifd.values(TAG_JPEG_OFFSET).first(),
+            ifd.values(TAG_JPEG_LENGTH).first(),
+        ) {
+            candidates.push(base + offset as u64..base + offset as u64 + length as u64);
+        }
+        let is_jpeg = matches!(ifd.values(TAG_COMPRESSION).first(), Some(6 | 7));
+        if let ([offset], [length], true) = (
+            ifd.values(TAG_STRIP_OFFSETS).as_slice(),
+            ifd.values(TAG_STRIP_BYTE_COUNTS).as_slice(),
+            is_jpeg,
+        ) {
+            candidates.push(base + *offset as u64..base + *offset as u64 + *length as u64);
+        }
+        queue.extend(ifd.values(TAG_SUB_IFDS));
+        queue.push(ifd.next);
+    }
+    candidates
+}
+
+// TIFFの読み込み（オフセットはTIFFヘッダの先頭から）
+struct Tiff<'a, R> {
+    reader: &'a mut R,
+    base: u64,
+    little_endian: bool,
+}
+
+// IFDのエントリ（タグと、SHORT・LONG型の値）と次のIFDのオフセット
+struct Ifd {
+    entries: Vec<(u16, Vec<u32>)>,
+    next: u32,
+}
+
+impl Ifd {
+    fn values(&self, tag: u16) -> Vec<u32> {
+        self.entries
+            .iter()
+            .find(|(entry_tag, _)| *entry_tag == tag)
+            .map(|(_, values)| values.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl<R: Read + Seek> Tiff<'_, R> {
+    fn read_at(&mut self, offset: u32, buf: &mut [u8]) -> Option<()> {
+        self.reader
+            .seek(SeekFrom::Start(self.base + offset as u64))
+            .ok()?;
+        self.reader.read_exact(buf).ok()
+    }
+
+    fn u16_from(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32_from(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32_at(&mut self, offset: u32) -> Option<u32> {
+        let mut buf = [0; 4];
+        self.read_at(offset, &mut buf)?;
+        Some(self.u32_from(&buf))
+    }
+
+    // プレビューの位置に関わるタグの値だけを読む（それ以外のタグは値を読まない）
+    fn read_ifd(&mut self, offset: u32) -> Option<Ifd> {
+        let mut count = [0; 2];
+        self.read_at(offset, &mut count)?;
+        let count = self.u16_from(&count).min(MAX_IFD_ENTRIES);
+        let mut raw_entries = vec![0; count as usize * 12 + 4];
+        self.read_at(offset + 2, &mut raw_entries)?;
+
+        let mut entries = Vec::new();
+        for entry in raw_entries.chunks_exact(12) {
+            let tag = self.u16_from(&entry[0..2]);
+            if ![
+                TAG_COMPRESSION,
+                TAG_STRIP_OFFSETS,
+                TAG_STRIP_BYTE_COUNTS,
+                TAG_SUB_IFDS,
+                TAG_JPEG_OFFSET,
+                TAG_JPEG_LENGTH,
+            ]
+            .contains(&tag)
+            {
+                continue;
+            }
+            // SHORT(3)・LONG(4)・IFD(13)型のみ
+            let value_size = match self.u16_from(&entry[2..4]) {
+                3 => 2,
+                4 | 13 => 4,
+                _ => continue,
+            };
+            let value_count = self.u32_from(&entry[4..8]).min(MAX_IFDS as u32) as usize;
+            let mut values = vec![0; value_size * value_count];
+            let values_len = values.len();
+            if values_len <= 4 {
+                values.copy_from_slice(&entry[8..8 + values_len]);
+            } else {
+                let values_offset = self.u32_from(&entry[8..12]);
+                self.read_at(values_offset, &mut values)?;
+            }
+            let values = values
+                .chunks_exact(value_size)
+                .map(|bytes| match value_size {
+                    2 => self.u16_from(bytes) as u32,
+                    _ => self.u32_from(bytes),
+                })
+                .collect();
+            entries.push((tag, values));
+        }
+        let next = self.u32_from(&raw_entries[raw_entries.len() - 4..]);
+        Some(Ifd { entries, next })
+    }
+}
+
+// 読めるだけ読む（ファイルが短い場合はそこまで）
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+// JPEGの先頭部分から、ブラウザで表示できる形式（ベースライン・プログレッシブ等）か判定する
+// SOFが先頭部分に含まれない場合も、候補として残すためにtrueとする（全体を読むときに確かめる）
+fn is_displayable_jpeg_head(head: &[u8]) -> bool {
+    if !head.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return false;
+    }
+    let mut pos = 2;
+    loop {
+        let (Some(&0xFF), Some(&marker)) = (head.get(pos), head.get(pos + 1)) else {
+            return true;
+        };
+        match marker {
+            0xC0..=0xC2 => return true,
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xDA | 0xD9 => return false,
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            0x01 | 0xD0..=0xD7 => {
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+        let (Some(&high), Some(&low)) = (head.get(pos + 2), head.get(pos + 3)) else {
+            return true;
+        };
+        pos += 2 + u16::from_be_bytes([high, low]) as usize;
+    }
+}
+
+fn find_largest_jpeg(data: &[u8]) -> Option<Range<usize>> {
+    let mut largest: Option<Range<usize>> = None;
+    let mut pos = 0;
+    while let Some(offset) = find_bytes(&data[pos..], &[0xFF, 0xD8, 0xFF]) {
+        let start = pos + offset;
+        match jpeg_end(data, start) {
+            Some(end) => {
+                if largest
+                    .as_ref()
+                    .is_none_or(|range| end - start > range.len())
+                {
+                    largest = Some(start..end);
+                }
+                // JPEGの内部（EXIFのサムネイル等）はより小さいので探さない
+                pos = end;
+            }
+            None => pos = start + 1,
+        }
+    }
+    largest
+}
+
+// startから始まるJPEGのストリームを解析して、その終端（EOIの直後）の位置を返す
+// ブラウザで表示できないもの（RAWデータ自体に使われるロスレスJPEG等）や壊れているものは None を返す
+fn jpeg_end(data: &[u8], start: usize) -> Option<usize> {
+    let mut pos = start + 2;
+    let mut displayable = false;
+    let mut scanned = false;
+    loop {
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        // マーカーの前の埋め草（連続する0xFF）を読み飛ばす
+        while *data.get(pos + 1)? == 0xFF {
+            pos += 1;
+        }
+        let marker = data[pos + 1];
+        pos += 2;
+
+        match marker {
+            // EOI
+            0xD9 => return scanned.then_some(pos),
+            // 長さを持たないマーカー（TEM・RST）
+            0x01 | 0xD0..=0xD7 => continue,
+            // ベースライン・拡張シーケンシャル・プログレッシブ
+            0xC0..=0xC2 => displayable = true,
+            // ロスレス・階層型・算術符号化
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return None,
+            _ => {}
+        }
+
+        let length = u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]) as usize;
+        if length < 2 {
+            return None;
+        }
+        pos += length;
+
+        // SOSの後は次のマーカーまで圧縮データが続く
+        if marker == 0xDA {
+            if !displayable {
+                return None;
+            }
+            scanned = true;
+            pos = skip_entropy_coded_data(data, pos)?;
+        }
+    }
+}
+
+// 圧縮データを読み飛ばし、次のマーカーの位置を返す
+// 圧縮データ中の0xFFの後には0x00（スタッフィング）かRSTマーカーが続く
+fn skip_entropy_coded_data(data: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let marker_pos = find_bytes(data.get(pos..)?, &[0xFF])? + pos;
+        match *data.get(marker_pos + 1)? {
+            0x00 | 0xD0..=0xD7 => pos = marker_pos + 2,
+            _ => return Some(marker_pos),
+        }
+    }
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+pub mod test_files {
+    use std::io::Cursor;
+
+    // 指定された幅・高さのJPEGのデータを返す
+    pub fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(width, height)
+            .write_to(&mut data, image::ImageFormat::Jpeg)
+            .expect("Failed to encode JPEG");
+        data.into_inner()
+    }
+
+    // TIFFベースのRAWファイルを模したデータを返す
+    // IFD0に小さいプレビュー、IFD1に大きいプレビュー、IFD1のSubIFDにより大きいロスレスJPEG（RAWデータ）を持たせる
+    pub fn tiff_based_raw(preview: &[u8]) -> Vec<u8> {
+        let thumbnail = jpeg(2, 2);
+        // SOI, SOF3（ロスレス）, SOS, 圧縮データ, EOI
+        let mut lossless = vec![
+            0xFF, 0xD8, 0xFF, 0xC3, 0x00, 0x0B, 0x08, 0x00, 0x01, 0x00, 0x01, 0x01, 0x01, 0x11,
+            0x00, 0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x01, 0x00, 0x00,
+        ];
+        lossless.extend(vec![0x55; preview.len() * 2]);
+        lossless.extend([0xFF, 0xD9]);
+
+        // IFD0（8バイト目から2エントリ）、IFD1（3エントリ）、SubIFD（3エントリ）の後にデータを置く
+        let (ifd0, ifd1, sub_ifd) = (8, 8 + 30, 8 + 30 + 42);
+        let thumbnail_offset = sub_ifd + 42 + 16;
+        let preview_offset = thumbnail_offset + thumbnail.len() as u32 + 16;
+        let lossless_offset = preview_offset + preview.len() as u32 + 16;
+
+        let mut data = b"II*\0".to_vec();
+        data.extend((ifd0 as u32).to_le_bytes());
+        let mut write_ifd = |entries: &[(u16, u16, u32)], next: u32| {
+            data.extend((entries.len() as u16).to_le_bytes());
+            for (tag, value_type, value) in entries {
+                data.extend(tag.to_le_bytes());
+                data.extend(value_type.to_le_bytes());
+                data.extend(1u32.to_le_bytes());
+                data.extend(value.to_le_bytes());
+            }
+            data.extend(next.to_le_bytes());
+        };
+        write_ifd(
+            &[
+                (0x0201, 4, thumbnail_offset),
+                (0x0202, 4, thumbnail.len() as u32),
+            ],
+            ifd1,
+        );
+        write_ifd(
+            &[
+                (0x014A, 4, sub_ifd),
+                (0x0201, 4, preview_offset),
+                (0x0202, 4, preview.len() as u32),
+            ],
+            0,
+        );
+        write_ifd(
+            &[
+                (0x0103, 3, 7),
+                (0x0111, 4, lossless_offset),
+                (0x0117, 4, lossless.len() as u32),
+            ],
+            0,
+        );
+        for part in [&thumbnail[..], preview, &lossless] {
+            data.extend([0x00; 16]);
+            data.extend(part);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_files::*;
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_extract_largest_displayable_preview() {
+        let temp_dir = TempDir::new().unwrap();
+        let preview = jpeg(64, 48);
+        let raw = temp_dir.path().join("IMG_0001.CR2");
+        fs::write(&raw, tiff_based_raw(&preview)).unwrap();
+
+        assert_eq!(extract_preview(&raw).unwrap(), preview);
+        assert_eq!(preview_dimensions(&raw), Ok((64, 48)));
+    }
+
+    #[test]
+    fn test_extract_preview_without_jpeg() {
+        let temp_dir = TempDir::new().unwrap();
+        let raw = temp_dir.path().join("DSC_0001.NEF");
+        fs::write(&raw, b"II*\0\x08\0\0\0\xFF\xD8\xFF\xC0\0").unwrap();
+
+        assert!(extract_preview(&raw).is_err());
+    }
+
+    #[test]
+    fn test_extract_preview_referenced_by_ifd() {
+        let temp_dir = TempDir::new().unwrap();
+        let preview = jpeg(64, 48);
+        // IFDから参照されていない、より大きいJPEGは使わない
+        let mut data = tiff_based_raw(&preview);
+        data.extend(jpeg(128, 96));
+        let raw = temp_dir.path().join("DSC_0001.NEF");
+        fs::write(&raw, &data).unwrap();
+
+        assert_eq!(extract_preview(&raw).unwrap(), preview);
+        assert_eq!(preview_dimensions(&raw), Ok((64, 48)));
+        assert_eq!(extract_preview_from_bytes(&data), Ok(&preview[..]));
+        assert_eq!(preview_dimensions_from_bytes(&data), Ok((64, 48)));
+    }
+
+    #[test]
+    fn test_extract_preview_from_raf_header() {
+        let preview = jpeg(32, 24);
+        let mut data = b"FUJIFILMCCD-RAW 0201FF383501".to_vec();
+        data.resize(84, 0);
+        data.extend(100u32.to_be_bytes());
+        data.extend((preview.len() as u32).to_be_bytes());
+        data.resize(100, 0);
+        data.extend(&preview);
+        data.extend(jpeg(64, 48));
+
+        assert_eq!(extract_preview_from_bytes(&data), Ok(&preview[..]));
+    }
+
+    #[test]
+    fn test_extract_preview_from_cr3_box() {
+        let preview = jpeg(32, 24);
+        let box_data = |box_type: &[u8], content: &[u8]| {
+            let mut data = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+            data.extend(box_type);
+            data.extend(content);
+            data
+        };
+        let mut prvw = CR3_PREVIEW_UUID.to_vec();
+        prvw.extend([0x00; 8]);
+        prvw.extend(box_data(b"PRVW", &[&[0x00; 16][..], &preview].concat()));
+        let mut data = box_data(b"ftyp", b"crx \0\0\0\x01");
+        data.extend(box_data(b"uuid", &prvw));
+        data.extend(box_data(b"mdat", &jpeg(64, 48)));
+
+        assert_eq!(extract_preview_from_bytes(&data), Ok(&preview[..]));
+    }
+
+    #[test]
+    fn test_is_raw_extension() {
+        assert!(is_raw_extension("CR3"));
+        assert!(is_raw_extension("raf"));
+        assert!(!is_raw_extension("tif"));
+    }
+}
@@ -0,0 +1,506 @@
+use std::collections::HashSet;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::archive;
+use crate::sort::natural_cmp;
+
+// 走査する深さのデフォルトの上限（ドロップされたフォルダ直下を1とする）
+const DEFAULT_MAX_DEPTH: usize = 16;
+
+// 常に除外するファイル・フォルダ名のパターン（指定された除外パターンに加えて適用する）
+// NAS (Synology) のサムネイルフォルダやOSのゴミ箱などを対象とする
+const DEFAULT_EXCLUDE_PATTERNS: [&str; 4] = [
+    "@eaDir",
+    ".thumbnails",
+    "$RECYCLE.BIN",
+    "System Volume Information",
+];
+
+// ドロップされたフォルダを走査する際のオプション
+// 指定されなかったフィールドはデフォルト値になる
+#[derive(Clone, serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScanOptions {
+    // 走査する最大の深さ。Noneの場合は無制限
+    pub max_depth: Option<usize>,
+    // 指定された場合、いずれかのパターンにマッチするファイルのみを対象にする
+    pub include_patterns: Vec<String>,
+    // いずれかのパターンにマッチするファイル・フォルダを除外する（DEFAULT_EXCLUDE_PATTERNSに追加する）
+    pub exclude_patterns: Vec<String>,
+    // "." で始まる隠しファイル・隠しフォルダを対象にするか
+    pub include_hidden: bool,
+    // シンボリックリンクを辿るか
+    pub follow_symlinks: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            include_patterns: Vec::new(),
+            exclude_patterns: Vec::new(),
+            include_hidden: false,
+            follow_symlinks: true,
+        }
+    }
+}
+
+// ScanOptionsのパターンをコンパイルしたフィルタ
+// パターンはファイル（フォルダ）名と、ドロップされたフォルダからの相対パスの両方に対して評価する
+struct ScanFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl ScanFilter {
+    fn new(options: &ScanOptions) -> Result<Self, String> {
+        let include = if options.include_patterns.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(&options.include_patterns)?)
+        };
+        let exclude_patterns: Vec<String> = DEFAULT_EXCLUDE_PATTERNS
+            .iter()
+            .map(|s| s.to_string())
+            .chain(options.exclude_patterns.iter().cloned())
+            .collect();
+        let exclude = build_glob_set(&exclude_patterns)?;
+        Ok(Self { include, exclude })
+    }
+
+    fn is_excluded(&self, name: &str, relative_path: &str) -> bool {
+        self.exclude.is_match(name) || self.exclude.is_match(relative_path)
+    }
+
+    fn is_included(&self, name: &str, relative_path: &str) -> bool {
+        match &self.include {
+            Some(include) => include.is_match(name) || include.is_match(relative_path),
+            None => true,
+        }
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob =
+            Glob::new(pattern).map_err(|e| format!("Invalid glob pattern {pattern}: {e}"))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Failed to build glob patterns: {e}"))
+}
+
+// パスの配列を受け取って画像ファイルを抽出して返す
+// 画像ファイルのパスはそのまま、フォルダの場合はオプションに従って再帰的に走査する
+// アーカイブはフォルダと同様に扱い、中の画像を "{アーカイブのパス}!/{エントリ名}" の仮想的なパスで返す
+// 結果はドロップされた順、フォルダ内はファイル名の自然順に並べる
+pub fn scan_image_files(
+    paths: Vec<String>,
+    options: &ScanOptions,
+    is_image: impl Fn(&Path) -> bool,
+) -> Result<Vec<String>, String> {
+    let scanner = Scanner::new(options.clone(), is_image)?;
+    let mut image_files = Vec::new();
+    let _ = scanner.scan(paths, |path| {
+        image_files.push(path);
+        ControlFlow::Continue(())
+    });
+    Ok(image_files)
+}
+
+// 画像ファイルの走査を行う
+// 見つかった画像ファイルを1件ずつコールバックに渡すため、走査の途中経過の利用や中断ができる
+pub struct Scanner<F: Fn(&Path) -> bool> {
+    options: ScanOptions,
+    filter: ScanFilter,
+    is_image: F,
+}
+
+impl<F: Fn(&Path) -> bool> Scanner<F> {
+    // オプションのパターンが不正な場合はエラーを返す
+    pub fn new(options: ScanOptions, is_image: F) -> Result<Self, String> {
+        let filter = ScanFilter::new(&options)?;
+        Ok(Self {
+            options,
+            filter,
+            is_image,
+        })
+    }
+
+    // 走査順（scan_image_filesの結果と同じ順）に画像ファイルのパスを on_image に渡す
+    // on_image が Break を返した場合は走査を中断して Break を返す
+    pub fn scan(
+        &self,
+        paths: Vec<String>,
+        mut on_image: impl FnMut(String) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let mut walker = Walker {
+            scanner: self,
+            on_image: &mut on_image,
+            visited: HashSet::new(),
+        };
+
+        for path in paths {
+            let path_obj = Path::new(&path);
+            if (self.is_image)(path_obj) {
+                (walker.on_image)(path)?;
+            } else if path_obj.is_dir() {
+                walker.walk_dir(path_obj, path_obj, 1)?;
+            } else if path_obj.is_file() && archive::is_archive_file(path_obj) {
+                walker.walk_archive(&path)?;
+            }
+            // ディレクトリでもアーカイブでもなければスキップ
+        }
+        ControlFlow::Continue(())
+    }
+
+    // フォルダの走査であればファイル名で対象外になるファイルか
+    // ディレクトリの監視で見つかったファイルなど、走査を経ずに追加するファイルの判定に使う
+    pub fn is_filtered_out(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            return true;
+        };
+        (!self.options.include_hidden && name.starts_with('.'))
+            || self.filter.is_excluded(name, name)
+            || (!archive::is_archive_file(path) && !self.filter.is_included(name, name))
+    }
+}
+
+struct Walker<'a, F: Fn(&Path) -> bool> {
+    scanner: &'a Scanner<F>,
+    on_image: &'a mut dyn FnMut(String) -> ControlFlow<()>,
+    // シンボリックリンクのループや重複走査を防ぐため、走査済みのフォルダを正規化したパスで記録する
+    visited: HashSet<PathBuf>,
+}
+
+impl<F: Fn(&Path) -> bool> Walker<'_, F> {
+    fn walk_dir(&mut self, root: &Path, dir: &Path, depth: usize) -> ControlFlow<()> {
+        let options = &self.scanner.options;
+        if options.max_depth.is_some_and(|max| depth > max) {
+            return ControlFlow::Continue(());
+        }
+        let Ok(canonical_dir) = dir.canonicalize() else {
+            return ControlFlow::Continue(());
+        };
+        if !self.visited.insert(canonical_dir) {
+            return ControlFlow::Continue(());
+        }
+
+        // 読み取れないフォルダ・エントリはスキップする
+        let Ok(read_dir) = std::fs::read_dir(dir) else {
+            return ControlFlow::Continue(());
+        };
+        let mut entries: Vec<_> = read_dir.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by(|a, b| {
+            natural_cmp(
+                &a.file_name().to_string_lossy(),
+                &b.file_name().to_string_lossy(),
+            )
+        });
+
+        for entry in entries {
+            let path = entry.path();
+            let Some(name) = entry.file_name().to_str().map(|s| s.to_string()) else {
+                continue;
+            };
+            if !options.include_hidden && name.starts_with('.') {
+                continue;
+            }
+            let relative_path = relative_path_string(root, &path);
+            if self.scanner.filter.is_excluded(&name, &relative_path) {
+                continue;
+            }
+
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let (is_dir, is_file) = if file_type.is_symlink() {
+                if !options.follow_symlinks {
+                    continue;
+                }
+                // リンク先が存在しない場合はスキップ
+                match std::fs::metadata(&path) {
+                    Ok(metadata) => (metadata.is_dir(), metadata.is_file()),
+                    Err(_) => continue,
+                }
+            } else {
+                (file_type.is_dir(), file_type.is_file())
+            };
+
+            if is_dir {
+                self.walk_dir(root, &path, depth + 1)?;
+            } else if is_file && archive::is_archive_file(&path) {
+                if let Some(path_str) = path.to_str() {
+                    self.walk_archive(path_str)?;
+                }
+            } else if is_file
+                && self.scanner.filter.is_included(&name, &relative_path)
+                && (self.scanner.is_image)(&path)
+            {
+                if let Some(path_str) = path.to_str() {
+                    (self.on_image)(path_str.to_string())?;
+                }
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    // アーカイブ内の画像のエントリを仮想的なパスで on_image に渡す
+    // パターンはエントリのファイル名とアーカイブ内のパスに対して評価する
+    // 読み取れないアーカイブはスキップする
+    fn walk_archive(&mut self, archive_path: &str) -> ControlFlow<()> {
+        let options = &self.scanner.options;
+        let filter = &self.scanner.filter;
+        let Ok(entries) = archive::list_entries(Path::new(archive_path)) else {
+            return ControlFlow::Continue(());
+        };
+
+        for entry in entries {
+            let mut components = entry.split('/');
+            if components.any(|name| {
+                (!options.include_hidden && name.starts_with('.'))
+                    || filter.is_excluded(name, &entry)
+            }) {
+                continue;
+            }
+            let name = entry.rsplit('/').next().unwrap_or(&entry);
+            if !filter.is_included(name, &entry) {
+                continue;
+            }
+            let path = archive::entry_path(archive_path, &entry);
+            if (self.scanner.is_image)(Path::new(&path)) {
+                (self.on_image)(path)?;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+// ドロップされたフォルダからの相対パスを "/" 区切りの文字列で返す
+fn relative_path_string(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn is_jpg(path: &Path) -> bool {
+        path.extension().is_some_and(|ext| ext == "jpg")
+    }
+
+    fn touch(dir: &Path, relative_path: &str) {
+        let path = dir.join(relative_path);
+        fs::create_dir_all(path.parent().unwrap()).expect("Failed to create dir");
+        fs::write(&path, "fake image content").expect("Failed to create test file");
+    }
+
+    fn scan(dir: &TempDir, options: &ScanOptions) -> Vec<String> {
+        let root = dir.path().to_str().unwrap().to_string();
+        scan_image_files(vec![root.clone()], options, is_jpg)
+            .expect("Failed to scan")
+            .into_iter()
+            .map(|path| relative_path_string(Path::new(&root), Path::new(&path)))
+            .collect()
+    }
+
+    #[test]
+    fn test_scan_recursive_sorted() {
+        let temp_dir = TempDir::new().unwrap();
+        touch(temp_dir.path(), "2024/02/b.jpg");
+        touch(temp_dir.path(), "2024/01/a10.jpg");
+        touch(temp_dir.path(), "2024/01/a2.jpg");
+        touch(temp_dir.path(), "2024/01/note.txt");
+        touch(temp_dir.path(), "top.jpg");
+
+        let result = scan(&temp_dir, &ScanOptions::default());
+
+        assert_eq!(
+            result,
+            vec![
+                "2024/01/a2.jpg",
+                "2024/01/a10.jpg",
+                "2024/02/b.jpg",
+                "top.jpg"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_max_depth() {
+        let temp_dir = TempDir::new().unwrap();
+        touch(temp_dir.path(), "a.jpg");
+        touch(temp_dir.path(), "sub/b.jpg");
+        touch(temp_dir.path(), "sub/sub/c.jpg");
+
+        let options = ScanOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        let result = scan(&temp_dir, &options);
+
+        assert_eq!(result, vec!["a.jpg", "sub/b.jpg"]);
+    }
+
+    #[test]
+    fn test_scan_default_excludes_and_hidden() {
+        let temp_dir = TempDir::new().unwrap();
+        touch(temp_dir.path(), "a.jpg");
+        touch(temp_dir.path(), "@eaDir/a.jpg/SYNOPHOTO_THUMB_XL.jpg");
+        touch(temp_dir.path(), ".hidden/b.jpg");
+        touch(temp_dir.path(), ".c.jpg");
+
+        let result = scan(&temp_dir, &ScanOptions::default());
+        assert_eq!(result, vec!["a.jpg"]);
+
+        let options = ScanOptions {
+            include_hidden: true,
+            ..Default::default()
+        };
+        let result = scan(&temp_dir, &options);
+        assert_eq!(result, vec![".c.jpg", ".hidden/b.jpg", "a.jpg"]);
+    }
+
+    #[test]
+    fn test_scan_include_and_exclude_patterns() {
+        let temp_dir = TempDir::new().unwrap();
+        touch(temp_dir.path(), "keep/IMG_0001.jpg");
+        touch(temp_dir.path(), "keep/DSC_0001.jpg");
+        touch(temp_dir.path(), "skip/IMG_0002.jpg");
+        // 除外パターンを指定してもデフォルトの除外は適用する
+        touch(temp_dir.path(), "@eaDir/IMG_0003.jpg");
+
+        let options = ScanOptions {
+            include_patterns: vec!["IMG_*".to_string()],
+            exclude_patterns: vec!["skip".to_string()],
+            ..Default::default()
+        };
+        let result = scan(&temp_dir, &options);
+
+        assert_eq!(result, vec!["keep/IMG_0001.jpg"]);
+    }
+
+    #[test]
+    fn test_scan_invalid_pattern() {
+        let options = ScanOptions {
+            exclude_patterns: vec!["[".to_string()],
+            ..Default::default()
+        };
+
+        let result = scan_image_files(vec![], &options, is_jpg);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Invalid glob pattern"));
+    }
+
+    #[test]
+    fn test_scanner_break() {
+        let temp_dir = TempDir::new().unwrap();
+        touch(temp_dir.path(), "a.jpg");
+        touch(temp_dir.path(), "sub/b.jpg");
+        touch(temp_dir.path(), "sub/c.jpg");
+        let root = temp_dir.path().to_str().unwrap().to_string();
+
+        let scanner = Scanner::new(ScanOptions::default(), is_jpg).unwrap();
+        let mut found = Vec::new();
+        let flow = scanner.scan(vec![root], |path| {
+            found.push(path);
+            if found.len() == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+
+        assert!(flow.is_break());
+        assert_eq!(found.len(), 2);
+        assert!(found[1].ends_with("b.jpg"));
+    }
+
+    #[test]
+    fn test_scanner_is_filtered_out() {
+        let options = ScanOptions {
+            include_patterns: vec!["IMG_*".to_string()],
+            ..Default::default()
+        };
+        let scanner = Scanner::new(options, is_jpg).unwrap();
+
+        assert!(!scanner.is_filtered_out(Path::new("/p/IMG_0001.jpg")));
+        assert!(scanner.is_filtered_out(Path::new("/p/DSC_0001.jpg")));
+        assert!(scanner.is_filtered_out(Path::new("/p/.IMG_0001.jpg")));
+        assert!(scanner.is_filtered_out(Path::new("/p/@eaDir")));
+        // アーカイブは中の画像にincludeを適用するので対象外にしない
+        assert!(!scanner.is_filtered_out(Path::new("/p/comic.cbz")));
+    }
+
+    #[test]
+    fn test_scan_archives() {
+        let temp_dir = TempDir::new().unwrap();
+        touch(temp_dir.path(), "a.jpg");
+        archive::test_files::write_zip(
+            &temp_dir.path().join("b.cbz"),
+            &[
+                ("10.jpg", b""),
+                ("2.jpg", b""),
+                ("notes.txt", b""),
+                (".hidden/3.jpg", b""),
+                ("skip/4.jpg", b""),
+            ],
+        );
+        touch(temp_dir.path(), "c.jpg");
+
+        let options = ScanOptions {
+            exclude_patterns: vec!["skip".to_string()],
+            ..Default::default()
+        };
+        let result = scan(&temp_dir, &options);
+        assert_eq!(
+            result,
+            vec!["a.jpg", "b.cbz!/2.jpg", "b.cbz!/10.jpg", "c.jpg"]
+        );
+
+        // ドロップされたアーカイブも展開する
+        let archive_path = temp_dir.path().join("b.cbz").to_str().unwrap().to_string();
+        let result =
+            scan_image_files(vec![archive_path.clone()], &ScanOptions::default(), is_jpg).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                archive::entry_path(&archive_path, "2.jpg"),
+                archive::entry_path(&archive_path, "10.jpg"),
+                archive::entry_path(&archive_path, "skip/4.jpg"),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_symlink_loop() {
+        let temp_dir = TempDir::new().unwrap();
+        touch(temp_dir.path(), "sub/a.jpg");
+        std::os::unix::fs::symlink(temp_dir.path(), temp_dir.path().join("sub/loop"))
+            .expect("Failed to create symlink");
+
+        let result = scan(&temp_dir, &ScanOptions::default());
+        assert_eq!(result, vec!["sub/a.jpg"]);
+
+        let options = ScanOptions {
+            follow_symlinks: false,
+            ..Default::default()
+        };
+        let result = scan(&temp_dir, &options);
+        assert_eq!(result, vec!["sub/a.jpg"]);
+    }
+}
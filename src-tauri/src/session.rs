@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+
+use crate::sort::SortOrder;
+use crate::undo::DeleteJournal;
+use crate::watcher::DirectoryWatcher;
+use crate::ImagePaths;
+
+// ビューアウィンドウのラベルの接頭辞
+// 各セッションのウィンドウのラベルは "viewer-{セッションID}" となる
+const VIEWER_LABEL_PREFIX: &str = "viewer";
+
+// ビューアのセッション
+// ビューアウィンドウ1つにつき1セッションで、それぞれが独立した画像一覧を持つ
+#[derive(Debug)]
+pub struct Session {
+    pub window_label: String,
+    pub image_paths: ImagePaths,
+    // 画像一覧の元になったディレクトリの監視（セッションを破棄すると監視も終了する）
+    pub watcher: Option<DirectoryWatcher>,
+    // 削除の取り消し用の履歴（画像一覧を置き換えると破棄する）
+    pub delete_journal: DeleteJournal,
+}
+
+// セッションIDをキーとしたセッションの一覧
+// セッションIDは作成順に採番し、ImagePathsのIDはセッションをまたいで一意になるように採番する
+#[derive(Debug, Default)]
+pub struct Sessions {
+    sessions: BTreeMap<u32, Session>,
+    last_session_id: u32,
+    last_image_paths_id: i32,
+}
+
+impl Sessions {
+    // 新しいセッションを作成して返す
+    pub fn create(&mut self) -> &mut Session {
+        self.last_session_id += 1;
+        let id = self.last_session_id;
+        self.sessions.entry(id).or_insert(Session {
+            window_label: format!("{VIEWER_LABEL_PREFIX}-{id}"),
+            image_paths: ImagePaths {
+                id: 0,
+                paths: Vec::new(),
+                sort_order: SortOrder::default(),
+            },
+            watcher: None,
+            delete_journal: DeleteJournal::default(),
+        })
+    }
+
+    // 最後に作成されたセッションを返す
+    pub fn latest_mut(&mut self) -> Option<&mut Session> {
+        self.sessions.values_mut().next_back()
+    }
+
+    pub fn get_by_label(&self, window_label: &str) -> Option<&Session> {
+        self.sessions
+            .values()
+            .find(|session| session.window_label == window_label)
+    }
+
+    pub fn get_by_label_mut(&mut self, window_label: &str) -> Option<&mut Session> {
+        self.sessions
+            .values_mut()
+            .find(|session| session.window_label == window_label)
+    }
+
+    pub fn remove_by_label(&mut self, window_label: &str) -> Option<Session> {
+        let id = self
+            .sessions
+            .iter()
+            .find(|(_, session)| session.window_label == window_label)
+            .map(|(id, _)| *id)?;
+        self.sessions.remove(&id)
+    }
+
+    // 新しいImagePathsのIDを発行する
+    pub fn next_image_paths_id(&mut self) -> i32 {
+        self.last_image_paths_id += 1;
+        self.last_image_paths_id
+    }
+
+    // 指定されたウィンドウのセッションの現在のImagePathsのIDを返す
+    // セッションが存在しない（ウィンドウが閉じられた）場合は None を返す
+    pub fn current_image_paths_id(&self, window_label: &str) -> Option<i32> {
+        self.get_by_label(window_label)
+            .map(|session| session.image_paths.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_and_lookup_sessions() {
+        let mut sessions = Sessions::default();
+        let first_label = sessions.create().window_label.clone();
+        let second_label = sessions.create().window_label.clone();
+
+        assert_eq!(first_label, "viewer-1");
+        assert_eq!(second_label, "viewer-2");
+        assert_eq!(sessions.latest_mut().unwrap().window_label, second_label);
+        assert!(sessions.get_by_label(&first_label).is_some());
+        assert!(sessions.get_by_label("main").is_none());
+
+        sessions.remove_by_label(&second_label);
+        assert_eq!(sessions.latest_mut().unwrap().window_label, first_label);
+        assert!(sessions.current_image_paths_id(&second_label).is_none());
+    }
+
+    #[test]
+    fn test_image_paths_id_is_unique_across_sessions() {
+        let mut sessions = Sessions::default();
+        let first = sessions.next_image_paths_id();
+        sessions.create();
+        let second = sessions.next_image_paths_id();
+
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,380 @@
+use std::cmp::Ordering;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::archive;
+use crate::image_format;
+use crate::metadata;
+
+// 画像一覧の並び順
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SortOrder {
+    // ファイルパスの自然順（"img2.png" < "img10.png"）
+    #[default]
+    Natural,
+    // 更新日時の古い順
+    ModifiedTime,
+    // 作成日時の古い順
+    CreatedTime,
+    // ファイルサイズの小さい順
+    FileSize,
+    // 画素数（幅×高さ）の小さい順
+    Dimensions,
+    // EXIFの撮影日時の古い順
+    ExifDate,
+}
+
+// 画像パスの配列を指定された順に並べ替える
+// 値を取得できなかったファイルは末尾に置き、同じ値のファイル同士は自然順に並べる
+pub fn sort_image_paths(paths: &mut [String], order: SortOrder) {
+    paths.sort_by(|a, b| natural_path_cmp(a, b));
+
+    // sort_by_cached_key は安定ソートなので、同値の場合は上記の自然順が保たれる
+    match order {
+        SortOrder::Natural => {}
+        SortOrder::ModifiedTime => {
+            paths.sort_by_cached_key(|path| missing_last(file_time(path, |m| m.modified())))
+        }
+        SortOrder::CreatedTime => {
+            paths.sort_by_cached_key(|path| missing_last(file_time(path, |m| m.created())))
+        }
+        SortOrder::FileSize => paths.sort_by_cached_key(|path| missing_last(file_size(path))),
+        SortOrder::Dimensions => paths.sort_by_cached_key(|path| {
+            missing_last(image_dimensions(path).map(|(width, height)| width as u64 * height as u64))
+        }),
+        SortOrder::ExifDate => paths.sort_by_cached_key(|path| missing_last(capture_date(path))),
+    }
+}
+
+// None を Some より後ろに並べるためのソートキー
+fn missing_last<T: Ord>(value: Option<T>) -> (bool, Option<T>) {
+    (value.is_none(), value)
+}
+
+// アーカイブ内のエントリはアーカイブ自体の日時とする（同じアーカイブのエントリ同士は自然順のまま）
+fn file_time(
+    path: &str,
+    get_time: impl Fn(&std::fs::Metadata) -> std::io::Result<SystemTime>,
+) -> Option<SystemTime> {
+    let file_path = archive::split_entry_path(path).map_or(path, |(archive_path, _)| archive_path);
+    let metadata = std::fs::metadata(file_path).ok()?;
+    get_time(&metadata).ok()
+}
+
+fn file_size(path: &str) -> Option<u64> {
+    match archive::split_entry_path(path) {
+        Some((archive_path, entry_name)) => {
+            archive::entry_size(Path::new(archive_path), entry_name).ok()
+        }
+        None => std::fs::metadata(path).ok().map(|m| m.len()),
+    }
+}
+
+fn image_dimensions(path: &str) -> Option<(u32, u32)> {
+    match archive::split_entry_path(path) {
+        Some((archive_path, entry_name)) => {
+            let data = archive::read_entry(Path::new(archive_path), entry_name).ok()?;
+            let format = image_format::detect_image_format_from_bytes(entry_name, &data)?;
+            image_format::image_dimensions_from_bytes(format, &data).ok()
+        }
+        None => image_format::image_dimensions(Path::new(path)).ok(),
+    }
+}
+
+fn capture_date(path: &str) -> Option<String> {
+    match archive::split_entry_path(path) {
+        Some((archive_path, entry_name)) => {
+            let data = archive::read_entry(Path::new(archive_path), entry_name).ok()?;
+            let format = image_format::detect_image_format_from_bytes(entry_name, &data)?;
+            metadata::read_capture_date_from_bytes(format, &data)
+        }
+        None => metadata::read_capture_date(Path::new(path)),
+    }
+}
+
+// パスをコンポーネント（フォルダ名・ファイル名）ごとに自然順で比較する
+// 同じフォルダ内のエントリがまとまって並ぶ
+pub fn natural_path_cmp(a: &str, b: &str) -> Ordering {
+    let a_components = Path::new(a)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy());
+    let b_components = Path::new(b)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy());
+    for pair in a_components.zip(b_components) {
+        let ordering = natural_cmp(&pair.0, &pair.1);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    let a_count = Path::new(a).components().count();
+    let b_count = Path::new(b).components().count();
+    a_count.cmp(&b_count).then_with(|| a.cmp(b))
+}
+
+// 文字列を自然順で比較する
+// 連続する数字は数値として比較し、それ以外の文字は大文字小文字を区別せずに比較する
+// 上記で等しい場合は元の文字列の比較結果を返すため、異なる文字列が Equal になることはない
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_char), Some(b_char)) => {
+                let ordering = if a_char.is_ascii_digit() && b_char.is_ascii_digit() {
+                    let a_digits = take_digits(&mut a_chars);
+                    let b_digits = take_digits(&mut b_chars);
+                    cmp_digits(&a_digits, &b_digits)
+                } else {
+                    a_chars.next();
+                    b_chars.next();
+                    a_char.to_lowercase().cmp(b_char.to_lowercase())
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    digits
+}
+
+// 数字列を数値として比較する（桁数の上限なし）
+// 数値が等しい場合は先頭の0が少ない方を前にする
+fn cmp_digits(a: &str, b: &str) -> Ordering {
+    let a_trimmed = a.trim_start_matches('0');
+    let b_trimmed = b.trim_start_matches('0');
+    a_trimmed
+        .len()
+        .cmp(&b_trimmed.len())
+        .then_with(|| a_trimmed.cmp(b_trimmed))
+        .then_with(|| a.len().cmp(&b.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_format::test_files::png;
+    use crate::metadata::test_images::{ascii_field, jpeg_with_metadata};
+    use std::fs;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn sorted(paths: &[&str], order: SortOrder) -> Vec<String> {
+        let mut paths: Vec<String> = paths.iter().map(|s| s.to_string()).collect();
+        sort_image_paths(&mut paths, order);
+        paths
+    }
+
+    // ファイルを書き込んでパスを返す
+    fn write_file(temp_dir: &TempDir, name: &str, data: &[u8]) -> String {
+        let path = temp_dir.path().join(name);
+        fs::write(&path, data).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    // 存在しない（値を取得できない）ファイルのパスを返す
+    fn missing_file(temp_dir: &TempDir, name: &str) -> String {
+        temp_dir.path().join(name).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(natural_cmp("img2.png", "img10.png"), Ordering::Less);
+        assert_eq!(natural_cmp("img10.png", "img2.png"), Ordering::Greater);
+        assert_eq!(natural_cmp("IMG_b.png", "img_a.png"), Ordering::Greater);
+        assert_eq!(natural_cmp("img02.png", "img2.png"), Ordering::Greater);
+        assert_eq!(natural_cmp("img.png", "img.png"), Ordering::Equal);
+        assert_eq!(
+            natural_cmp(
+                "img99999999999999999999999.png",
+                "img100000000000000000000000.png"
+            ),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_sort_natural() {
+        let result = sorted(
+            &[
+                "/p/img10.png",
+                "/p/sub/img1.png",
+                "/p/img2.png",
+                "/p/Img1.png",
+            ],
+            SortOrder::Natural,
+        );
+
+        assert_eq!(
+            result,
+            vec![
+                "/p/Img1.png",
+                "/p/img2.png",
+                "/p/img10.png",
+                "/p/sub/img1.png"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_file_size_missing_last() {
+        let temp_dir = TempDir::new().unwrap();
+        let large = temp_dir.path().join("a.png");
+        let small = temp_dir.path().join("b.png");
+        fs::write(&large, "large content").unwrap();
+        fs::write(&small, "small").unwrap();
+        let missing = temp_dir.path().join("0.png");
+
+        let result = sorted(
+            &[
+                missing.to_str().unwrap(),
+                large.to_str().unwrap(),
+                small.to_str().unwrap(),
+            ],
+            SortOrder::FileSize,
+        );
+
+        assert_eq!(
+            result,
+            vec![
+                small.to_str().unwrap(),
+                large.to_str().unwrap(),
+                missing.to_str().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_modified_time_missing_last() {
+        let temp_dir = TempDir::new().unwrap();
+        let old = write_file(&temp_dir, "c.png", b"old");
+        let new = write_file(&temp_dir, "a.png", b"new");
+        let same_as_old = write_file(&temp_dir, "d.png", b"same as old");
+        let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for (path, offset) in [(&old, 0), (&new, 60), (&same_as_old, 0)] {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(base + Duration::from_secs(offset))
+                .unwrap();
+        }
+        let missing10 = missing_file(&temp_dir, "img10.png");
+        let missing2 = missing_file(&temp_dir, "img2.png");
+
+        let result = sorted(
+            &[&missing10, &new, &same_as_old, &missing2, &old],
+            SortOrder::ModifiedTime,
+        );
+
+        // 同じ日時のファイル同士と、日時を取得できないファイル同士は自然順
+        assert_eq!(result, vec![old, same_as_old, new, missing2, missing10]);
+    }
+
+    #[test]
+    fn test_sort_created_time_missing_last() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = write_file(&temp_dir, "b.png", b"first");
+        std::thread::sleep(Duration::from_millis(20));
+        let second = write_file(&temp_dir, "a.png", b"second");
+        let missing10 = missing_file(&temp_dir, "img10.png");
+        let missing2 = missing_file(&temp_dir, "img2.png");
+
+        let result = sorted(
+            &[&missing10, &second, &missing2, &first],
+            SortOrder::CreatedTime,
+        );
+
+        // 作成日時を取得できないファイルシステムでは、どのファイルも自然順になる
+        let created_supported = fs::metadata(&first).unwrap().created().is_ok();
+        let expected = if created_supported {
+            vec![first, second, missing2, missing10]
+        } else {
+            vec![second, first, missing2, missing10]
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_sort_dimensions_missing_last() {
+        let temp_dir = TempDir::new().unwrap();
+        let large = write_file(&temp_dir, "a.jpg", &jpeg_with_metadata(&[], None, 4, 3));
+        let small = write_file(&temp_dir, "c.png", &png(0));
+        let same_as_small = write_file(&temp_dir, "b.png", &png(1));
+        // 画像として読めないファイルと存在しないファイル
+        let broken = write_file(&temp_dir, "img10.png", b"not an image");
+        let missing = missing_file(&temp_dir, "img2.png");
+
+        let result = sorted(
+            &[&broken, &large, &small, &missing, &same_as_small],
+            SortOrder::Dimensions,
+        );
+
+        assert_eq!(result, vec![same_as_small, small, large, missing, broken]);
+    }
+
+    #[test]
+    fn test_sort_exif_date_missing_last() {
+        let temp_dir = TempDir::new().unwrap();
+        let jpeg_taken_at = |date: &str| {
+            jpeg_with_metadata(
+                &[ascii_field(exif::Tag::DateTimeOriginal, date)],
+                None,
+                1,
+                1,
+            )
+        };
+        let newer = write_file(&temp_dir, "a.jpg", &jpeg_taken_at("2024:05:06 07:08:09"));
+        let older = write_file(&temp_dir, "b.jpg", &jpeg_taken_at("2023:01:02 03:04:05"));
+        // EXIFのない画像と存在しないファイル
+        let without_exif = write_file(&temp_dir, "img10.jpg", &jpeg_with_metadata(&[], None, 1, 1));
+        let missing = missing_file(&temp_dir, "img2.jpg");
+
+        let result = sorted(
+            &[&without_exif, &newer, &missing, &older],
+            SortOrder::ExifDate,
+        );
+
+        assert_eq!(result, vec![older, newer, missing, without_exif]);
+    }
+
+    #[test]
+    fn test_sort_archive_entries_by_file_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let zip = temp_dir.path().join("pack.zip");
+        crate::archive::test_files::write_zip(
+            &zip,
+            &[("a.png", b"large content"), ("b.png", b"small")],
+        );
+        let zip = zip.to_str().unwrap();
+        let large = archive::entry_path(zip, "a.png");
+        let small = archive::entry_path(zip, "b.png");
+
+        let result = sorted(&[&large, &small], SortOrder::FileSize);
+
+        assert_eq!(result, vec![small, large]);
+    }
+
+    #[test]
+    fn test_sort_order_serialization() {
+        assert_eq!(
+            serde_json::to_string(&SortOrder::ExifDate).unwrap(),
+            "\"exifDate\""
+        );
+        let order: SortOrder = serde_json::from_str("\"modifiedTime\"").unwrap();
+        assert_eq!(order, SortOrder::ModifiedTime);
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::error::{CommandError, ErrorKind};
+use crate::file_ops;
+
+// アプリの設定ディレクトリ内の振り分け先の設定ファイル名
+pub const SORT_BINS_FILE_NAME: &str = "sort_bins.json";
+
+// 名前の重複時に連番を付ける上限（これを超える場合はエラーにする）
+const MAX_SUFFIX_NUMBER: u32 = 9999;
+
+// 振り分け先に同名のファイルがある場合の扱い
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CollisionPolicy {
+    // "name (1).jpg" のように連番を付けた名前にする
+    #[default]
+    Suffix,
+    // 既存のファイルを上書きする
+    Overwrite,
+    // 何もしない
+    Skip,
+}
+
+// 振り分けの方法
+#[derive(Clone, Copy, serde::Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferMode {
+    #[default]
+    Move,
+    Copy,
+}
+
+// 振り分け先（名前を付けたフォルダ）
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SortBin {
+    pub name: String,
+    pub dir: String,
+    #[serde(default)]
+    pub collision: CollisionPolicy,
+}
+
+// 振り分け先の一覧をJSONファイルとして保存する
+pub struct SortBinStore {
+    path: PathBuf,
+}
+
+impl SortBinStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    // 保存されている振り分け先の一覧を返す（設定ファイルがなければ空）
+    pub fn load(&self) -> Result<Vec<SortBin>, CommandError> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let json = std::fs::read_to_string(&self.path).map_err(|e| {
+            CommandError::io(format!("Failed to read sort bins: {e}"), &e)
+                .with_path(self.path.to_string_lossy())
+        })?;
+        serde_json::from_str(&json).map_err(|e| {
+            CommandError::new(
+                ErrorKind::InvalidData,
+                format!("Failed to parse sort bins: {e}"),
+            )
+            .with_path(self.path.to_string_lossy())
+        })
+    }
+
+    // 振り分け先の一覧を検証して保存する
+    // 書き込み途中で終了しても設定ファイルが壊れないように、一時ファイルに書いてから置き換える
+    pub fn save(&self, bins: &[SortBin]) -> Result<(), CommandError> {
+        validate_sort_bins(bins).map_err(|e| CommandError::new(ErrorKind::InvalidInput, e))?;
+        let to_error = |e: std::io::Error| {
+            CommandError::io(format!("Failed to write sort bins: {e}"), &e)
+                .with_path(self.path.to_string_lossy())
+        };
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(to_error)?;
+        }
+        let json = serde_json::to_string_pretty(bins).map_err(|e| {
+            CommandError::new(
+                ErrorKind::Other,
+                format!("Failed to serialize sort bins: {e}"),
+            )
+        })?;
+        let temp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&temp_path, json).map_err(to_error)?;
+        std::fs::rename(&temp_path, &self.path).map_err(to_error)
+    }
+
+    // 名前で振り分け先を探す
+    pub fn find(&self, name: &str) -> Result<SortBin, CommandError> {
+        self.load()?
+            .into_iter()
+            .find(|bin| bin.name == name)
+            .ok_or_else(|| {
+                CommandError::new(
+                    ErrorKind::InvalidInput,
+                    format!("Sort bin {name} is not configured"),
+                )
+            })
+    }
+}
+
+// 振り分け先の名前は空でなく重複しないこと、フォルダは絶対パスであること
+fn validate_sort_bins(bins: &[SortBin]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for bin in bins {
+        if bin.name.trim().is_empty() {
+            return Err("Sort bin name must not be empty".to_string());
+        }
+        if !names.insert(bin.name.as_str()) {
+            return Err(format!("Sort bin name {} is duplicated", bin.name));
+        }
+        if !Path::new(&bin.dir).is_absolute() {
+            return Err(format!("Sort bin directory {} is not absolute", bin.dir));
+        }
+    }
+    Ok(())
+}
+
+// ファイルを振り分け先のフォルダに移動またはコピーして、振り分け後のパスを返す
+// 同名のファイルがあってcollisionがSkipの場合は何もせずに None を返す
+pub fn transfer_file(
+    path: &Path,
+    dest_dir: &Path,
+    mode: TransferMode,
+    collision: CollisionPolicy,
+) -> Result<Option<PathBuf>, CommandError> {
+    let file_name = path.file_name().ok_or_else(|| {
+        CommandError::new(
+            ErrorKind::InvalidInput,
+            format!("{} has no file name", path.display()),
+        )
+        .with_path(path.to_string_lossy())
+    })?;
+    if path.parent().and_then(|dir| dir.canonicalize().ok()) == dest_dir.canonicalize().ok() {
+        return Err(CommandError::new(
+            ErrorKind::InvalidInput,
+            format!("{} is already in {}", path.display(), dest_dir.display()),
+        )
+        .with_path(path.to_string_lossy()));
+    }
+
+    let mut dest = dest_dir.join(file_name);
+    if dest.exists() {
+        match collision {
+            CollisionPolicy::Suffix => dest = suffixed_path(&dest)?,
+            CollisionPolicy::Overwrite => {}
+            CollisionPolicy::Skip => return Ok(None),
+        }
+    }
+
+    match mode {
+        TransferMode::Move => file_ops::move_file(path, &dest)?,
+        TransferMode::Copy => {
+            std::fs::copy(path, &dest).map_err(|e| {
+                CommandError::io(format!("Failed to copy {}: {e}", path.display()), &e)
+                    .with_path(path.to_string_lossy())
+            })?;
+        }
+    }
+    Ok(Some(dest))
+}
+
+// "name.jpg" に対して、存在しない "name (1).jpg", "name (2).jpg", ... を返す
+fn suffixed_path(path: &Path) -> Result<PathBuf, CommandError> {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (1..=MAX_SUFFIX_NUMBER)
+        .map(|n| path.with_file_name(format!("{stem} ({n}){ext}")))
+        .find(|candidate| !candidate.exists())
+        .ok_or_else(|| {
+            CommandError::new(
+                ErrorKind::AlreadyExists,
+                format!("Too many files named like {}", path.display()),
+            )
+            .with_path(path.to_string_lossy())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn sort_bin(name: &str, dir: &str) -> SortBin {
+        SortBin {
+            name: name.to_string(),
+            dir: dir.to_string(),
+            collision: CollisionPolicy::default(),
+        }
+    }
+
+    #[test]
+    fn test_store_save_and_load() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SortBinStore::new(temp_dir.path().join("config").join(SORT_BINS_FILE_NAME));
+        assert_eq!(store.load(), Ok(Vec::new()));
+
+        let keep = temp_dir.path().join("keep");
+        let bins = vec![SortBin {
+            collision: CollisionPolicy::Skip,
+            ..sort_bin("keep", keep.to_str().unwrap())
+        }];
+        store.save(&bins).unwrap();
+
+        assert_eq!(store.load(), Ok(bins.clone()));
+        assert_eq!(store.find("keep"), Ok(bins[0].clone()));
+        assert!(store.find("reject").is_err());
+    }
+
+    #[test]
+    fn test_store_rejects_invalid_bins() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SortBinStore::new(temp_dir.path().join(SORT_BINS_FILE_NAME));
+        let dir = temp_dir.path().to_str().unwrap();
+
+        assert!(store.save(&[sort_bin(" ", dir)]).is_err());
+        assert!(store
+            .save(&[sort_bin("keep", dir), sort_bin("keep", dir)])
+            .unwrap_err()
+            .message
+            .contains("duplicated"));
+        assert!(store.save(&[sort_bin("keep", "relative/dir")]).is_err());
+        assert!(!store.path.exists());
+    }
+
+    #[test]
+    fn test_transfer_file_collision_policies() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest_dir = temp_dir.path().join("keep");
+        fs::create_dir(&dest_dir).unwrap();
+        fs::write(dest_dir.join("a.png"), "existing").unwrap();
+        let image = temp_dir.path().join("a.png");
+        fs::write(&image, "new").unwrap();
+
+        let skipped =
+            transfer_file(&image, &dest_dir, TransferMode::Copy, CollisionPolicy::Skip).unwrap();
+        assert_eq!(skipped, None);
+
+        let copied = transfer_file(
+            &image,
+            &dest_dir,
+            TransferMode::Copy,
+            CollisionPolicy::Suffix,
+        )
+        .unwrap();
+        assert_eq!(copied, Some(dest_dir.join("a (1).png")));
+        assert!(image.exists());
+
+        let moved = transfer_file(
+            &image,
+            &dest_dir,
+            TransferMode::Move,
+            CollisionPolicy::Overwrite,
+        )
+        .unwrap();
+        assert_eq!(moved, Some(dest_dir.join("a.png")));
+        assert_eq!(fs::read_to_string(dest_dir.join("a.png")).unwrap(), "new");
+        assert!(!image.exists());
+    }
+
+    #[test]
+    fn test_transfer_file_into_same_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let image = temp_dir.path().join("a.png");
+        fs::write(&image, "image").unwrap();
+
+        let result = transfer_file(
+            &image,
+            temp_dir.path(),
+            TransferMode::Move,
+            CollisionPolicy::Suffix,
+        );
+
+        assert!(result.unwrap_err().message.contains("already in"));
+        assert!(image.exists());
+    }
+}
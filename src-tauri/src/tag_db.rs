@@ -0,0 +1,479 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use crate::error::{CommandError, ErrorKind};
+use crate::fingerprint::{self, Fingerprint};
+use crate::tag_file::DirTags;
+use crate::tag_store::{self, LoadedDirTags, ModifiedDirTags, ModifyDirTags, TagStore};
+
+// 他のアプリがデータベースに書き込み中の場合に待つ時間
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// image_tags: 画像（正規化したディレクトリのパスとファイル名）毎のタグ
+//   tagsはタグの配列のJSON、file_sizeとcontent_hashは保存したときの画像のフィンガープリント
+//   （アーカイブ内の画像や読み込めなかった画像はNULL）
+// dir_revisions: ディレクトリ毎にタグを保存した回数（他のアプリでの変更に気付くため）
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS image_tags (
+    dir_path TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    file_size INTEGER,
+    content_hash TEXT,
+    tags TEXT NOT NULL,
+    PRIMARY KEY (dir_path, file_name)
+);
+CREATE INDEX IF NOT EXISTS image_tags_fingerprint ON image_tags (file_size, content_hash);
+CREATE TABLE IF NOT EXISTS dir_revisions (
+    dir_path TEXT PRIMARY KEY,
+    revision INTEGER NOT NULL
+);
+";
+
+// アプリのデータディレクトリのSQLiteデータベースにタグを保存する
+// 全てのディレクトリのタグが1か所にあるので、他のディレクトリに移動された画像にもタグを付け直せる
+pub struct DatabaseTagStore {
+    connection: Mutex<Connection>,
+    // ディレクトリ毎に、前回取得・保存したときのリビジョン
+    revisions: Mutex<HashMap<String, i64>>,
+    // ディレクトリ毎に、タグを付け直せる画像を探したときのディレクトリの更新日時
+    relink_checked: Mutex<HashMap<String, Option<SystemTime>>>,
+}
+
+impl DatabaseTagStore {
+    // データベースを開く（なければ作る）
+    pub fn open(path: &Path) -> Result<Self, CommandError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                CommandError::io(format!("Failed to create tag database directory: {e}"), &e)
+                    .with_path(dir.to_string_lossy())
+            })?;
+        }
+        let connection = Connection::open(path)
+            .and_then(|connection| {
+                connection.busy_timeout(BUSY_TIMEOUT)?;
+                // 複数のアプリから開いても、読み込みが書き込みを待たないようにする
+                connection.pragma_update_and_check(None, "journal_mode", "WAL", |row| {
+                    row.get::<_, String>(0)
+                })?;
+                connection.execute_batch(SCHEMA)?;
+                Ok(connection)
+            })
+            .map_err(|e| database_error(e).with_path(path.to_string_lossy()))?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            revisions: Mutex::new(HashMap::new()),
+            relink_checked: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn lock_connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .expect("failed to lock tag database connection")
+    }
+
+    // 前回取得・保存したときとリビジョンが違えば（アプリの外で変更されていれば）trueを返し、
+    // 今回のリビジョンを覚えておく
+    fn update_revision(&self, dir_path: &str, seen: i64, current: i64) -> bool {
+        let mut revisions = self.revisions.lock().expect("failed to lock tag revisions");
+        let external = revisions
+            .get(dir_path)
+            .is_some_and(|&revision| revision != seen);
+        revisions.insert(dir_path.to_string(), current);
+        external
+    }
+
+    // 前回探したときからディレクトリ内のファイルが変わっていれば（初めての場合も）trueを返す
+    fn needs_relink(&self, dir_path: &str) -> bool {
+        let dir_modified = tag_store::dir_modified(dir_path);
+        let mut checked = self
+            .relink_checked
+            .lock()
+            .expect("failed to lock tag relink check");
+        checked.insert(dir_path.to_string(), dir_modified) != Some(dir_modified)
+    }
+}
+
+impl TagStore for DatabaseTagStore {
+    // データベースからの読み込みは速いので、キャッシュせずに毎回読み込む
+    fn load_dir(&self, dir_path: &str, _force: bool) -> Result<LoadedDirTags, CommandError> {
+        tag_store::ensure_directory(dir_path)?;
+        let needs_relink = self.needs_relink(dir_path);
+        let mut connection = self.lock_connection();
+        let revision = read_revision(&connection, dir_path).map_err(database_error)?;
+        let mut tags = read_dir_tags(&connection, dir_path)?;
+        let relinked = needs_relink && relink_moved_files(&mut connection, dir_path, &tags)?;
+        let mut current = revision;
+        if relinked {
+            current = read_revision(&connection, dir_path).map_err(database_error)?;
+            tags = read_dir_tags(&connection, dir_path)?;
+        }
+        drop(connection);
+
+        let external = self.update_revision(dir_path, revision, current);
+        // 移動元のディレクトリはリビジョンが変わるので、次に取得したときに読み直す
+        Ok(LoadedDirTags {
+            tags,
+            external,
+            relinked,
+            relinked_dirs: Vec::new(),
+        })
+    }
+
+    // 他のアプリが同時に書き込まないように、書き込み用のトランザクションの中で読み直して変更する
+    fn modify_dir(
+        &self,
+        dir_path: &str,
+        modify: ModifyDirTags<'_>,
+    ) -> Result<ModifiedDirTags, CommandError> {
+        tag_store::ensure_directory(dir_path)?;
+        let mut connection = self.lock_connection();
+        let transaction = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(database_error)?;
+        let revision = read_revision(&transaction, dir_path).map_err(database_error)?;
+        let before = read_dir_tags(&transaction, dir_path)?;
+        let mut tags = before.clone();
+        let changed = modify(&mut tags);
+
+        let mut current = revision;
+        if changed {
+            write_dir_tags(&transaction, dir_path, &before, &tags)?;
+            current = increment_revision(&transaction, dir_path)?;
+        }
+        transaction.commit().map_err(database_error)?;
+        drop(connection);
+
+        let external = self.update_revision(dir_path, revision, current);
+        Ok(ModifiedDirTags {
+            tags,
+            changed,
+            external,
+        })
+    }
+}
+
+// ディレクトリ内のタグのない画像のうち、データベースにある画像がなくなったタグのエントリと
+// フィンガープリントが一致するものに、そのエントリを移す（他のディレクトリのエントリも対象にする）
+// エントリを移した場合はtrueを返す
+fn relink_moved_files(
+    connection: &mut Connection,
+    dir_path: &str,
+    tags: &DirTags,
+) -> Result<bool, CommandError> {
+    let candidates = tag_store::untagged_files(dir_path, tags);
+    let sizes: HashSet<u64> = candidates
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .collect();
+    let mut orphans = Vec::new();
+    {
+        let mut statement = connection
+            .prepare_cached(
+                "SELECT dir_path, file_name, content_hash FROM image_tags
+                 WHERE file_size = ?1 AND content_hash IS NOT NULL",
+            )
+            .map_err(database_error)?;
+        for size in sizes {
+            let rows = statement
+                .query_map(params![size as i64], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                })
+                .map_err(database_error)?;
+            for row in rows {
+                let (orphan_dir, file_name, hash) = row.map_err(database_error)?;
+                if !Path::new(&orphan_dir).join(&file_name).exists() {
+                    orphans.push(((orphan_dir, file_name), Fingerprint { size, hash }));
+                }
+            }
+        }
+    }
+    let relinks: Vec<((String, String), PathBuf)> =
+        fingerprint::match_orphans(&orphans, &candidates);
+    if relinks.is_empty() {
+        return Ok(false);
+    }
+
+    let transaction = connection
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(database_error)?;
+    let mut changed_dirs = HashSet::new();
+    for ((orphan_dir, orphan_name), path) in relinks {
+        let Some(file_name) = path.file_name() else {
+            continue;
+        };
+        // 探している間に他のアプリが付け直していた場合や、付け直し先にタグが保存された場合はそのままにする
+        let moved = transaction
+            .execute(
+                "UPDATE OR IGNORE image_tags SET dir_path = ?1, file_name = ?2
+                 WHERE dir_path = ?3 AND file_name = ?4",
+                params![
+                    dir_path,
+                    file_name.to_string_lossy(),
+                    orphan_dir,
+                    orphan_name
+                ],
+            )
+            .map_err(database_error)?;
+        if moved > 0 {
+            changed_dirs.insert(orphan_dir);
+            changed_dirs.insert(dir_path.to_string());
+        }
+    }
+    for changed_dir in &changed_dirs {
+        increment_revision(&transaction, changed_dir)?;
+    }
+    transaction.commit().map_err(database_error)?;
+    Ok(!changed_dirs.is_empty())
+}
+
+// ディレクトリのリビジョンを1つ進めて、進めた後のリビジョンを返す
+fn increment_revision(connection: &Connection, dir_path: &str) -> Result<i64, CommandError> {
+    connection
+        .query_row(
+            "INSERT INTO dir_revisions (dir_path, revision) VALUES (?1, 1)
+             ON CONFLICT (dir_path) DO UPDATE SET revision = revision + 1
+             RETURNING revision",
+            params![dir_path],
+            |row| row.get(0),
+        )
+        .map_err(database_error)
+}
+
+fn read_revision(connection: &Connection, dir_path: &str) -> rusqlite::Result<i64> {
+    connection
+        .query_row(
+            "SELECT revision FROM dir_revisions WHERE dir_path = ?1",
+            params![dir_path],
+            |row| row.get(0),
+        )
+        .optional()
+        .map(|revision| revision.unwrap_or(0))
+}
+
+fn read_dir_tags(connection: &Connection, dir_path: &str) -> Result<DirTags, CommandError> {
+    let mut statement = connection
+        .prepare_cached("SELECT file_name, tags FROM image_tags WHERE dir_path = ?1")
+        .map_err(database_error)?;
+    let rows = statement
+        .query_map(params![dir_path], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(database_error)?;
+    let mut dir_tags = DirTags::new();
+    for row in rows {
+        let (file_name, tags) = row.map_err(database_error)?;
+        let tags = serde_json::from_str(&tags).map_err(|e| {
+            CommandError::new(
+                ErrorKind::InvalidData,
+                format!("Invalid tags of {file_name} in tag database: {e}"),
+            )
+        })?;
+        dir_tags.insert(file_name, tags);
+    }
+    Ok(dir_tags)
+}
+
+// beforeからafterに変わった画像の行だけを書き換える
+fn write_dir_tags(
+    connection: &Connection,
+    dir_path: &str,
+    before: &DirTags,
+    after: &DirTags,
+) -> Result<(), CommandError> {
+    for file_name in before.keys().filter(|name| !after.contains_key(*name)) {
+        connection
+            .execute(
+                "DELETE FROM image_tags WHERE dir_path = ?1 AND file_name = ?2",
+                params![dir_path, file_name],
+            )
+            .map_err(database_error)?;
+    }
+    for (file_name, tags) in after {
+        if before.get(file_name) == Some(tags) {
+            continue;
+        }
+        let tags = serde_json::to_string(tags).map_err(|e| {
+            CommandError::new(ErrorKind::Other, format!("Failed to serialize tags: {e}"))
+        })?;
+        let fingerprint = Fingerprint::of_file(&Path::new(dir_path).join(file_name));
+        connection
+            .execute(
+                "INSERT INTO image_tags (dir_path, file_name, file_size, content_hash, tags)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (dir_path, file_name)
+                 DO UPDATE SET file_size = excluded.file_size,
+                               content_hash = excluded.content_hash, tags = excluded.tags",
+                params![
+                    dir_path,
+                    file_name,
+                    fingerprint
+                        .as_ref()
+                        .map(|fingerprint| fingerprint.size as i64),
+                    fingerprint.map(|fingerprint| fingerprint.hash),
+                    tags
+                ],
+            )
+            .map_err(database_error)?;
+    }
+    Ok(())
+}
+
+fn database_error(error: rusqlite::Error) -> CommandError {
+    CommandError::new(ErrorKind::Io, format!("Tag database error: {error}")).with_source(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(file_name: &str, tags: &[&str]) -> ModifyDirTags<'static> {
+        let file_name = file_name.to_string();
+        let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+        Box::new(move |dir_tags| dir_tags.insert(file_name, tags.clone()) != Some(tags))
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let images_dir = temp_dir.path().join("images");
+        std::fs::create_dir(&images_dir).unwrap();
+        std::fs::write(images_dir.join("a.png"), "image a").unwrap();
+        let dir_path = images_dir.to_str().unwrap();
+        let store =
+            DatabaseTagStore::open(&temp_dir.path().join("db").join("tags.sqlite3")).unwrap();
+
+        let modified = store
+            .modify_dir(dir_path, insert("a.png", &["cat", "red, blue"]))
+            .unwrap();
+        assert!(modified.changed);
+        assert!(!modified.external);
+        let unchanged = store
+            .modify_dir(dir_path, insert("a.png", &["cat", "red, blue"]))
+            .unwrap();
+        assert!(!unchanged.changed);
+        store
+            .modify_dir(dir_path, insert("gone.png", &["dog"]))
+            .unwrap();
+        store
+            .modify_dir(
+                dir_path,
+                Box::new(|dir_tags| dir_tags.remove("gone.png").is_some()),
+            )
+            .unwrap();
+
+        let loaded = store.load_dir(dir_path, false).unwrap();
+        assert_eq!(
+            loaded.tags,
+            DirTags::from([(
+                "a.png".to_string(),
+                vec!["cat".to_string(), "red, blue".to_string()]
+            )])
+        );
+        // 画像のフィンガープリントも保存される
+        let (size, hash): (i64, String) = store
+            .lock_connection()
+            .query_row(
+                "SELECT file_size, content_hash FROM image_tags WHERE file_name = 'a.png'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        let fingerprint = Fingerprint::of_file(&images_dir.join("a.png")).unwrap();
+        assert_eq!(size as u64, fingerprint.size);
+        assert_eq!(hash, fingerprint.hash);
+        // ディレクトリ内にファイルは作らない
+        assert_eq!(std::fs::read_dir(&images_dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_detects_changes_by_another_instance() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir_path = temp_dir.path().to_str().unwrap();
+        let database_path = temp_dir.path().join("tags.sqlite3");
+        let store = DatabaseTagStore::open(&database_path).unwrap();
+        let other = DatabaseTagStore::open(&database_path).unwrap();
+
+        assert!(!store.load_dir(dir_path, false).unwrap().external);
+        other
+            .modify_dir(dir_path, insert("a.png", &["cat"]))
+            .unwrap();
+
+        let loaded = store.load_dir(dir_path, false).unwrap();
+        assert!(loaded.external);
+        assert_eq!(loaded.tags["a.png"], vec!["cat"]);
+        // 他のアプリの変更を消さずに保存する
+        let modified = other
+            .modify_dir(dir_path, insert("b.png", &["dog"]))
+            .unwrap();
+        assert!(!modified.external);
+        let modified = store
+            .modify_dir(dir_path, insert("c.png", &["bird"]))
+            .unwrap();
+        assert!(modified.external);
+        assert_eq!(modified.tags.len(), 3);
+    }
+
+    #[test]
+    fn test_relinks_moved_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let from_dir = temp_dir.path().join("from");
+        let to_dir = temp_dir.path().join("to");
+        std::fs::create_dir(&from_dir).unwrap();
+        std::fs::create_dir(&to_dir).unwrap();
+        std::fs::write(from_dir.join("a.png"), "image a").unwrap();
+        std::fs::write(from_dir.join("b.png"), "image b").unwrap();
+        let from_path = from_dir.to_str().unwrap();
+        let to_path = to_dir.to_str().unwrap();
+        let store = DatabaseTagStore::open(&temp_dir.path().join("tags.sqlite3")).unwrap();
+        store
+            .modify_dir(from_path, insert("a.png", &["cat"]))
+            .unwrap();
+        store
+            .modify_dir(from_path, insert("b.png", &["dog"]))
+            .unwrap();
+        store.load_dir(to_path, false).unwrap();
+
+        // アプリの外で、他のディレクトリへの移動と名前変更をする
+        std::fs::rename(from_dir.join("a.png"), to_dir.join("moved.png")).unwrap();
+        std::fs::rename(from_dir.join("b.png"), from_dir.join("renamed.png")).unwrap();
+
+        let loaded = store.load_dir(to_path, false).unwrap();
+        assert!(loaded.relinked);
+        assert!(!loaded.external);
+        assert_eq!(
+            loaded.tags,
+            DirTags::from([("moved.png".to_string(), vec!["cat".to_string()])])
+        );
+        let loaded = store.load_dir(from_path, false).unwrap();
+        assert!(loaded.relinked);
+        assert_eq!(
+            loaded.tags,
+            DirTags::from([("renamed.png".to_string(), vec!["dog".to_string()])])
+        );
+        // ディレクトリ内のファイルが変わらなければ探し直さない
+        assert!(!store.load_dir(to_path, false).unwrap().relinked);
+    }
+
+    #[test]
+    fn test_rejects_missing_directory() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = DatabaseTagStore::open(&temp_dir.path().join("tags.sqlite3")).unwrap();
+
+        let error = store
+            .load_dir("/nonexistent/directory", false)
+            .err()
+            .unwrap();
+
+        assert_eq!(error.kind, ErrorKind::NotADirectory);
+    }
+}
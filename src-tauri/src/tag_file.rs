@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::error::{CommandError, ErrorKind};
+use crate::fingerprint::Fingerprint;
+
+// タグファイルの形式のバージョン
+// バージョン1（ヘッダ行なし）は "file_name\ttag1,tag2" の行をエスケープせずに並べたもので、
+// ファイル名やタグに "," やタブ・改行が含まれると読み込み時に壊れる
+// バージョン2は先頭にヘッダ行を置き、各フィールドの "\" タブ 改行 "," をエスケープする
+// バージョン3は行の3つ目のフィールドに画像の内容のフィンガープリントを追加する（求められなかった画像は省略）
+pub const CURRENT_VERSION: u32 = 3;
+
+// バージョンを示すヘッダ行の接頭辞（"#IMAGE_TAG version=2" のようになる）
+const HEADER_PREFIX: &str = "#IMAGE_TAG version=";
+
+// ディレクトリ内の画像のファイル名とタグの対応
+pub type DirTags = HashMap<String, Vec<String>>;
+
+// ディレクトリ内の画像のファイル名と、タグを保存したときの画像の内容のフィンガープリントの対応
+pub type Fingerprints = HashMap<String, Fingerprint>;
+
+// 読み込んだ・書き込んだときのタグファイルの状態
+// 更新日時とサイズが変わっていなければ読み直さず、変わっていれば内容のハッシュで本当に変わったか確かめる
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: blake3::Hash,
+}
+
+impl Stamp {
+    fn new(metadata: &std::fs::Metadata, content: &[u8]) -> Self {
+        Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: blake3::hash(content),
+        }
+    }
+}
+
+// 読み込んだタグ情報と、そのときのタグファイルの状態（タグファイルがなければNone）
+#[derive(Debug, PartialEq, Eq)]
+pub struct Loaded {
+    pub tags: DirTags,
+    pub fingerprints: Fingerprints,
+    pub stamp: Option<Stamp>,
+}
+
+// タグファイルを読み込んで、読み込んだときの状態と一緒に返す（タグファイルがなければ空）
+// 読み込み中に書き換えられた場合に次のis_freshで気付けるように、状態は読み込む前に取得する
+pub fn read_stamped(tag_file: &Path) -> Result<Loaded, CommandError> {
+    let path = tag_file.to_string_lossy();
+    let to_error = |e: std::io::Error| {
+        CommandError::io(format!("Failed to read tag file {path}: {e}"), &e).with_path(path.clone())
+    };
+    let mut file = match std::fs::File::open(tag_file) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Loaded {
+                tags: DirTags::new(),
+                fingerprints: Fingerprints::new(),
+                stamp: None,
+            })
+        }
+        Err(e) => return Err(to_error(e)),
+    };
+    let metadata = file.metadata().map_err(to_error)?;
+    let mut content = Vec::new();
+    file.read_to_end(&mut content).map_err(to_error)?;
+
+    let stamp = Stamp::new(&metadata, &content);
+    let (tags, fingerprints) = std::str::from_utf8(&content)
+        .map_err(|e| format!("Tag file is not valid UTF-8: {e}"))
+        .and_then(parse)
+        .map_err(|e| CommandError::new(ErrorKind::InvalidData, e).with_path(path.clone()))?;
+    Ok(Loaded {
+        tags,
+        fingerprints,
+        stamp: Some(stamp),
+    })
+}
+
+// タグファイルの更新日時とサイズが、stampを取得したときから変わっていないか
+pub fn is_fresh(tag_file: &Path, stamp: Option<&Stamp>) -> bool {
+    match (std::fs::metadata(tag_file), stamp) {
+        (Ok(metadata), Some(stamp)) => {
+            metadata.modified().ok() == stamp.modified && metadata.len() == stamp.len
+        }
+        (Err(e), None) => e.kind() == std::io::ErrorKind::NotFound,
+        _ => false,
+    }
+}
+
+// 2つの状態のタグファイルの内容が同じか（どちらもタグファイルがない場合も同じとする）
+pub fn same_content(a: Option<&Stamp>, b: Option<&Stamp>) -> bool {
+    a.map(|stamp| stamp.hash) == b.map(|stamp| stamp.hash)
+}
+
+// タグ情報を一時ファイルに書き込んでから、タグファイルに置き換える
+// 置き換える前に一時ファイルをディスクに書き出すので、途中で電源が落ちても中途半端な内容にならない
+// 失敗した場合は一時ファイルを消して、元のタグファイルはそのまま残す
+// 書き込んだ後のタグファイルの状態を返す
+pub fn write(
+    tag_file: &Path,
+    temp_file: &Path,
+    dir_tags: &DirTags,
+    fingerprints: &Fingerprints,
+) -> Result<Stamp, CommandError> {
+    let content = serialize(dir_tags, fingerprints);
+    let result = write_temp_file(temp_file, &content).and_then(|metadata| {
+        std::fs::rename(temp_file, tag_file).map_err(|e| {
+            let path = tag_file.to_string_lossy();
+            CommandError::io(format!("Failed to replace tag file {path}: {e}"), &e).with_path(path)
+        })?;
+        Ok(Stamp::new(&metadata, content.as_bytes()))
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(temp_file);
+    }
+    result
+}
+
+fn write_temp_file(temp_file: &Path, content: &str) -> Result<std::fs::Metadata, CommandError> {
+    let to_error = |e: std::io::Error| {
+        let path = temp_file.to_string_lossy();
+        CommandError::io(
+            format!("Failed to write temporary tag file {path}: {e}"),
+            &e,
+        )
+        .with_path(path)
+    };
+    let mut file = std::fs::File::create(temp_file).map_err(to_error)?;
+    file.write_all(content.as_bytes()).map_err(to_error)?;
+    file.sync_all().map_err(to_error)?;
+    // 名前を変えても更新日時・サイズは変わらないので、置き換える前に取得しておく
+    file.metadata().map_err(to_error)
+}
+
+// タグファイルを書き換える間、他のウィンドウ・他のプロセスが同時に書き換えないようにロックする
+// ロックはOSのアドバイザリロックで、返したTagFileLockを破棄するとロックファイルを削除して解除される
+// ロックに対応していないファイルシステム（一部のネットワークドライブなど）ではロックせずに続ける
+pub fn lock(lock_file: &Path) -> Result<TagFileLock, CommandError> {
+    let to_error = |e: std::io::Error| {
+        let path = lock_file.to_string_lossy();
+        CommandError::io(format!("Failed to lock tag file {path}: {e}"), &e).with_path(path)
+    };
+    loop {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(lock_file)
+            .map_err(to_error)?;
+        match file.lock() {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+                return Ok(TagFileLock {
+                    file: None,
+                    path: lock_file.to_path_buf(),
+                })
+            }
+            Err(e) => return Err(to_error(e)),
+        }
+        // 待っている間に前のロックの持ち主がロックファイルを削除した場合は、作り直してロックし直す
+        if is_same_file(&file, lock_file) {
+            return Ok(TagFileLock {
+                file: Some(file),
+                path: lock_file.to_path_buf(),
+            });
+        }
+    }
+}
+
+// タグファイルのロック
+// ディレクトリにロックファイルを残さないように、ロックを持ったまま削除してから解除する
+pub struct TagFileLock {
+    file: Option<std::fs::File>,
+    path: PathBuf,
+}
+
+impl Drop for TagFileLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        self.file.take();
+    }
+}
+
+// 開いたファイルが今もそのパスにあるファイルか
+#[cfg(unix)]
+fn is_same_file(file: &std::fs::File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+// Windowsでは開いているファイルを削除しても、全て閉じられるまでパスが残るので作り直されない
+#[cfg(not(unix))]
+fn is_same_file(_file: &std::fs::File, path: &Path) -> bool {
+    path.exists()
+}
+
+// タグファイルの内容をパースする（ヘッダ行がない場合はバージョン1として読む）
+// 新しいバージョンのアプリで書かれたファイルは、上書きして壊さないようにエラーにする
+// バージョン2以前のファイルにはフィンガープリントがないので、空で返す
+pub fn parse(content: &str) -> Result<(DirTags, Fingerprints), String> {
+    let mut lines = content.lines().peekable();
+    let version = match lines
+        .peek()
+        .and_then(|line| line.strip_prefix(HEADER_PREFIX))
+    {
+        Some(version) => {
+            let version = version
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid tag file version {version}"))?;
+            lines.next();
+            version
+        }
+        None => 1,
+    };
+    let mut dir_tags = DirTags::new();
+    let mut fingerprints = Fingerprints::new();
+    match version {
+        1 => dir_tags.extend(lines.map(parse_legacy_line)),
+        2 | CURRENT_VERSION => {
+            for line in lines.filter(|line| !line.is_empty()) {
+                let (file_name, tags, fingerprint) = parse_line(line, version)?;
+                if let Some(fingerprint) = fingerprint {
+                    fingerprints.insert(file_name.clone(), fingerprint);
+                }
+                dir_tags.insert(file_name, tags);
+            }
+        }
+        _ => return Err(format!("Unsupported tag file version {version}")),
+    }
+    Ok((dir_tags, fingerprints))
+}
+
+// タグ情報を現在のバージョンの形式の文字列にする
+// 書き込む度に行が入れ替わらないように、ファイル名順に並べる
+// fingerprintsにない画像はフィンガープリントを省略する
+pub fn serialize(dir_tags: &DirTags, fingerprints: &Fingerprints) -> String {
+    let mut entries: Vec<(&String, &Vec<String>)> = dir_tags.iter().collect();
+    entries.sort();
+    let mut content = format!("{HEADER_PREFIX}{CURRENT_VERSION}\n");
+    for (file_name, tags) in entries {
+        let tags: Vec<String> = tags.iter().map(|tag| escape(tag)).collect();
+        content.push_str(&format!("{}\t{}", escape(file_name), tags.join(",")));
+        if let Some(fingerprint) = fingerprints.get(file_name) {
+            content.push_str(&format!("\t{}", escape(&fingerprint.to_string())));
+        }
+        content.push('\n');
+    }
+    content
+}
+
+// バージョン1の一行分の文字列をパースしてファイル名とタグのペアを返す
+// 行の形式は: "file_name\ttag1,tag2,tag3"
+fn parse_legacy_line(line: &str) -> (String, Vec<String>) {
+    let mut parts = line.split('\t');
+    let file_name = parts.next().unwrap_or("").to_string();
+    let tags = parts
+        .next()
+        .unwrap_or("")
+        .split(',')
+        .map(|s| s.to_string())
+        .collect();
+    (file_name, tags)
+}
+
+// バージョン2・3の一行分の文字列をパースしてファイル名とタグ、フィンガープリントを返す
+// 行の形式は "file_name\ttag1,tag2\tfingerprint" で、各フィールドはエスケープされている
+// バージョン2はフィンガープリントのフィールドがない
+fn parse_line(
+    line: &str,
+    version: u32,
+) -> Result<(String, Vec<String>, Option<Fingerprint>), String> {
+    let fields = split_unescaped(line, '\t');
+    let (file_name, tags, fingerprint) = match fields.as_slice() {
+        [file_name] => (*file_name, "", None),
+        [file_name, tags] => (*file_name, *tags, None),
+        [file_name, tags, fingerprint] if version >= 3 => (*file_name, *tags, Some(*fingerprint)),
+        _ => return Err(format!("Invalid line in tag file: {line}")),
+    };
+    let fingerprint = match fingerprint {
+        Some(fingerprint) => {
+            let fingerprint = unescape(fingerprint)?;
+            Some(
+                Fingerprint::parse(&fingerprint)
+                    .ok_or_else(|| format!("Invalid fingerprint in tag file: {fingerprint}"))?,
+            )
+        }
+        None => None,
+    };
+    let tags = if tags.is_empty() {
+        Vec::new()
+    } else {
+        split_unescaped(tags, ',')
+            .into_iter()
+            .map(unescape)
+            .collect::<Result<_, _>>()?
+    };
+    Ok((unescape(file_name)?, tags, fingerprint))
+}
+
+// エスケープされていない区切り文字で分割する（エスケープは解除しない）
+fn split_unescaped(s: &str, delimiter: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == delimiter {
+            parts.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            ',' => escaped.push_str("\\,"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(',') => unescaped.push(','),
+            Some(c) => return Err(format!("Invalid escape sequence \\{c} in tag file")),
+            None => return Err(format!("Unterminated escape sequence in tag file: {s}")),
+        }
+    }
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tag_line() {
+        let line = "test.jpg\ttag1,tag2,tag3";
+        let (file_name, tags) = parse_legacy_line(line);
+
+        assert_eq!(file_name, "test.jpg");
+        assert_eq!(tags, vec!["tag1", "tag2", "tag3"]);
+    }
+
+    #[test]
+    fn test_parse_tag_line_empty_tags() {
+        let line = "test.jpg\t";
+        let (file_name, tags) = parse_legacy_line(line);
+
+        assert_eq!(file_name, "test.jpg");
+        assert_eq!(tags, vec![""]);
+    }
+
+    #[test]
+    fn test_parse_tag_line_no_tabs() {
+        let line = "test.jpg";
+        let (file_name, tags) = parse_legacy_line(line);
+
+        assert_eq!(file_name, "test.jpg");
+        assert_eq!(tags, vec![""]);
+    }
+
+    #[test]
+    fn test_parse_legacy_format() {
+        let (tags, fingerprints) = parse("a.jpg\tred,blue\nb.png\tgreen\n").unwrap();
+
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags["a.jpg"], vec!["red", "blue"]);
+        assert_eq!(tags["b.png"], vec!["green"]);
+        assert!(fingerprints.is_empty());
+    }
+
+    #[test]
+    fn test_parse_version_2() {
+        let (tags, fingerprints) =
+            parse("#IMAGE_TAG version=2\na\\,b.png\tred\\, blue,green\n").unwrap();
+
+        assert_eq!(tags["a,b.png"], vec!["red, blue", "green"]);
+        assert!(fingerprints.is_empty());
+    }
+
+    #[test]
+    fn test_serialize_and_parse_special_characters() {
+        let dir_tags = DirTags::from([
+            (
+                "tab\tnew\nline,\\.png".to_string(),
+                vec!["red, blue".to_string(), "a\\,b".to_string()],
+            ),
+            ("empty.png".to_string(), Vec::new()),
+            ("plain.png".to_string(), vec!["cat".to_string()]),
+        ]);
+
+        let fingerprints = Fingerprints::from([(
+            "plain.png".to_string(),
+            Fingerprint {
+                size: 42,
+                hash: "abc".to_string(),
+            },
+        )]);
+
+        let content = serialize(&dir_tags, &fingerprints);
+
+        assert!(content.starts_with(&format!("{HEADER_PREFIX}{CURRENT_VERSION}\n")));
+        assert_eq!(content.lines().count(), 4);
+        assert!(content.contains("plain.png\tcat\t42:abc\n"));
+        assert!(content.contains("empty.png\t\n"));
+        assert_eq!(parse(&content), Ok((dir_tags, fingerprints)));
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let tag_file = temp_dir.path().join("IMAGE_TAG");
+        let temp_file = temp_dir.path().join("IMAGE_TAG_TEMP");
+        assert_eq!(read_stamped(&tag_file).unwrap().tags, DirTags::new());
+
+        let dir_tags = DirTags::from([("a.png".to_string(), vec!["cat".to_string()])]);
+        write(&tag_file, &temp_file, &dir_tags, &Fingerprints::new()).unwrap();
+
+        assert_eq!(read_stamped(&tag_file).unwrap().tags, dir_tags);
+        assert!(!temp_file.exists());
+    }
+
+    #[test]
+    fn test_stamp_detects_changes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let tag_file = temp_dir.path().join("IMAGE_TAG");
+        let temp_file = temp_dir.path().join("IMAGE_TAG_TEMP");
+        assert!(is_fresh(&tag_file, None));
+
+        let dir_tags = DirTags::from([("a.png".to_string(), vec!["cat".to_string()])]);
+        let stamp = write(&tag_file, &temp_file, &dir_tags, &Fingerprints::new()).unwrap();
+        assert!(is_fresh(&tag_file, Some(&stamp)));
+        let loaded = read_stamped(&tag_file).unwrap();
+        assert_eq!(loaded.tags, dir_tags);
+        assert!(same_content(loaded.stamp.as_ref(), Some(&stamp)));
+
+        // 外から書き換えられた
+        std::fs::write(&tag_file, "a.png\tcat,dog\n").unwrap();
+        assert!(!is_fresh(&tag_file, Some(&stamp)));
+        let loaded = read_stamped(&tag_file).unwrap();
+        assert!(!same_content(loaded.stamp.as_ref(), Some(&stamp)));
+
+        std::fs::remove_file(&tag_file).unwrap();
+        assert!(!is_fresh(&tag_file, loaded.stamp.as_ref()));
+        assert_eq!(read_stamped(&tag_file).unwrap().stamp, None);
+    }
+
+    #[test]
+    fn test_write_failure_keeps_tag_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let tag_file = temp_dir.path().join("IMAGE_TAG");
+        std::fs::write(&tag_file, "a.png\tcat\n").unwrap();
+        // 一時ファイルを作れないようにする
+        let temp_file = temp_dir.path().join("IMAGE_TAG_TEMP");
+        std::fs::create_dir(&temp_file).unwrap();
+
+        let result = write(&tag_file, &temp_file, &DirTags::new(), &Fingerprints::new());
+
+        assert!(result.unwrap_err().message.contains("temporary tag file"));
+        assert_eq!(std::fs::read_to_string(&tag_file).unwrap(), "a.png\tcat\n");
+    }
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let lock_file = temp_dir.path().join("IMAGE_TAG_LOCK");
+
+        let guard = lock(&lock_file).unwrap();
+        // 別に開いたファイルからはロックできない
+        let other = std::fs::File::options()
+            .write(true)
+            .open(&lock_file)
+            .unwrap();
+        assert!(other.try_lock().is_err());
+
+        drop(guard);
+        assert!(other.try_lock().is_ok());
+        // ロックファイルは残さない
+        assert!(!lock_file.exists());
+    }
+
+    #[test]
+    fn test_lock_waits_for_removed_lock_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let lock_file = temp_dir.path().join("IMAGE_TAG_LOCK");
+
+        let guard = lock(&lock_file).unwrap();
+        let waiter = {
+            let lock_file = lock_file.clone();
+            std::thread::spawn(move || {
+                let guard = lock(&lock_file).unwrap();
+                // ロックしている間は、ロックしたファイルがパスにある
+                let other = std::fs::File::options()
+                    .write(true)
+                    .open(&lock_file)
+                    .unwrap();
+                assert!(other.try_lock().is_err());
+                drop(guard);
+            })
+        };
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        drop(guard);
+        waiter.join().unwrap();
+
+        assert!(!lock_file.exists());
+    }
+
+    #[test]
+    fn test_read_unreadable_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        // タグファイルの名前のディレクトリは読み込めない
+        let tag_file = temp_dir.path().join("IMAGE_TAG");
+        std::fs::create_dir(&tag_file).unwrap();
+
+        assert!(read_stamped(&tag_file)
+            .unwrap_err()
+            .message
+            .contains("Failed to read"));
+        std::fs::remove_dir(&tag_file).unwrap();
+        std::fs::write(&tag_file, "#IMAGE_TAG version=99\n").unwrap();
+        assert_eq!(
+            read_stamped(&tag_file).unwrap_err().kind,
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_newer_or_broken_files() {
+        assert!(parse("#IMAGE_TAG version=99\na.png\tred\n")
+            .unwrap_err()
+            .contains("Unsupported"));
+        assert!(parse("#IMAGE_TAG version=x\n").is_err());
+        assert!(parse("#IMAGE_TAG version=2\na.png\tred\\q\n").is_err());
+        assert!(parse("#IMAGE_TAG version=2\na.png\tred\t1:abc\n").is_err());
+        assert!(parse("#IMAGE_TAG version=3\na.png\tred\tblue\n").is_err());
+        assert!(parse("#IMAGE_TAG version=3\na.png\tred\t1:abc\tx\n").is_err());
+    }
+}
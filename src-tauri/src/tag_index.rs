@@ -0,0 +1,199 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::archive;
+use crate::error::{CommandError, ErrorKind};
+use crate::tag_file::DirTags;
+
+// アプリの設定ディレクトリ内の、タグのあるディレクトリの一覧のファイル名
+pub const TAG_INDEX_FILE_NAME: &str = "tag_dirs.json";
+
+// タグのあるディレクトリ（タグを読み込んだ・保存した、または登録したもの）の一覧
+// 全ディレクトリの画像をタグで検索するときに使う
+// pathのJSONファイルに保存する（Noneの場合は保存しない）
+pub struct TagDirIndex {
+    path: Option<PathBuf>,
+    dirs: Mutex<BTreeSet<String>>,
+}
+
+impl TagDirIndex {
+    // 保存されている一覧を読み込む（ファイルがない・読み込めない場合は空）
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let dirs = path
+            .as_deref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            dirs: Mutex::new(dirs),
+        }
+    }
+
+    fn lock_dirs(&self) -> MutexGuard<'_, BTreeSet<String>> {
+        self.dirs.lock().expect("failed to lock tag dir index")
+    }
+
+    pub fn dirs(&self) -> Vec<String> {
+        self.lock_dirs().iter().cloned().collect()
+    }
+
+    // 一覧に追加する（既にあれば何もしない）
+    pub fn register(&self, dir_path: &str) -> Result<(), CommandError> {
+        let mut dirs = self.lock_dirs();
+        if dirs.insert(dir_path.to_string()) {
+            self.save(&dirs)?;
+        }
+        Ok(())
+    }
+
+    // 一覧から削除する（タグ自体は消さない）
+    pub fn unregister(&self, dir_paths: &[String]) -> Result<(), CommandError> {
+        let mut dirs = self.lock_dirs();
+        let mut changed = false;
+        for dir_path in dir_paths {
+            changed |= dirs.remove(dir_path);
+        }
+        if changed {
+            self.save(&dirs)?;
+        }
+        Ok(())
+    }
+
+    // 書き込み途中で終了しても一覧が壊れないように、一時ファイルに書いてから置き換える
+    fn save(&self, dirs: &BTreeSet<String>) -> Result<(), CommandError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let to_error = |e: std::io::Error| {
+            CommandError::io(format!("Failed to write tag dir index: {e}"), &e)
+                .with_path(path.to_string_lossy())
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(to_error)?;
+        }
+        let json = serde_json::to_string_pretty(dirs).map_err(|e| {
+            CommandError::new(
+                ErrorKind::Other,
+                format!("Failed to serialize tag dir index: {e}"),
+            )
+        })?;
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, json).map_err(to_error)?;
+        std::fs::rename(&temp_path, path).map_err(to_error)
+    }
+}
+
+// タグでの検索条件
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TagQuery {
+    pub tags: Vec<String>,
+    // trueの場合はすべてのタグが付いた画像、falseの場合はいずれかのタグが付いた画像（絞り込みと同じ）
+    #[serde(default)]
+    pub match_all: bool,
+}
+
+impl TagQuery {
+    pub fn matches(&self, tags: &[String]) -> bool {
+        if self.match_all {
+            self.tags.iter().all(|tag| tags.contains(tag))
+        } else {
+            self.tags.iter().any(|tag| tags.contains(tag))
+        }
+    }
+}
+
+// ディレクトリのタグ情報から、条件に一致する画像のパスを返す
+// 画像（アーカイブ内の画像の場合はアーカイブ）がなくなったエントリは除く
+pub fn search_dir(dir_path: &str, dir_tags: &DirTags, query: &TagQuery) -> Vec<String> {
+    dir_tags
+        .iter()
+        .filter(|(_, tags)| query.matches(tags))
+        .map(|(file_name, _)| Path::new(dir_path).join(file_name))
+        .filter(|path| {
+            let path_str = path.to_string_lossy();
+            match archive::split_entry_path(&path_str) {
+                Some((archive_path, _)) => Path::new(archive_path).is_file(),
+                None => path.is_file(),
+            }
+        })
+        .map(|path| path.to_string_lossy().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_index_register_and_unregister() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config").join(TAG_INDEX_FILE_NAME);
+        let index = TagDirIndex::new(Some(path.clone()));
+        assert!(index.dirs().is_empty());
+
+        index.register("/photos/b").unwrap();
+        index.register("/photos/a").unwrap();
+        index.register("/photos/b").unwrap();
+        assert_eq!(index.dirs(), vec!["/photos/a", "/photos/b"]);
+
+        // 一覧は次に起動したときも使われる
+        let index = TagDirIndex::new(Some(path.clone()));
+        assert_eq!(index.dirs(), vec!["/photos/a", "/photos/b"]);
+        index
+            .unregister(&["/photos/a".to_string(), "/photos/c".to_string()])
+            .unwrap();
+        assert_eq!(TagDirIndex::new(Some(path)).dirs(), vec!["/photos/b"]);
+    }
+
+    #[test]
+    fn test_query_matches() {
+        let any = TagQuery {
+            tags: tags(&["cat", "dog"]),
+            match_all: false,
+        };
+        let all = TagQuery {
+            tags: tags(&["cat", "dog"]),
+            match_all: true,
+        };
+
+        assert!(any.matches(&tags(&["dog"])));
+        assert!(!any.matches(&tags(&["bird"])));
+        assert!(!all.matches(&tags(&["dog"])));
+        assert!(all.matches(&tags(&["dog", "bird", "cat"])));
+    }
+
+    #[test]
+    fn test_search_dir_skips_missing_images() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("a.png"), "image a").unwrap();
+        std::fs::write(temp_dir.path().join("b.png"), "image b").unwrap();
+        let dir_path = temp_dir.path().to_str().unwrap();
+        let dir_tags = DirTags::from([
+            ("a.png".to_string(), tags(&["cat"])),
+            ("b.png".to_string(), tags(&["dog"])),
+            ("gone.png".to_string(), tags(&["cat"])),
+            ("gone.zip!/c.png".to_string(), tags(&["cat"])),
+        ]);
+
+        let found = search_dir(
+            dir_path,
+            &dir_tags,
+            &TagQuery {
+                tags: tags(&["cat"]),
+                match_all: false,
+            },
+        );
+
+        assert_eq!(
+            found,
+            vec![temp_dir.path().join("a.png").to_string_lossy().to_string()]
+        );
+    }
+}
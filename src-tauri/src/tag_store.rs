@@ -0,0 +1,831 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use crate::error::{CommandError, ErrorKind};
+use crate::fingerprint::{self, Fingerprint};
+use crate::tag_db::DatabaseTagStore;
+use crate::tag_file::{self, DirTags, Fingerprints};
+use crate::tag_index::TagDirIndex;
+
+// アプリの設定ディレクトリ内のタグの保存先の設定ファイル名
+pub const TAG_STORAGE_FILE_NAME: &str = "tag_storage.json";
+
+// アプリのデータディレクトリ内のタグのデータベースのファイル名
+pub const TAG_DATABASE_FILE_NAME: &str = "tags.sqlite3";
+
+pub const TAG_FILE_NAME: &str = "IMAGE_TAG";
+pub const TAG_TEMP_FILE_NAME: &str = "IMAGE_TAG_TEMP";
+pub const TAG_LOCK_FILE_NAME: &str = "IMAGE_TAG_LOCK";
+
+// タグの保存先
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TagBackend {
+    // ディレクトリ毎のタグファイル（IMAGE_TAG）
+    #[default]
+    File,
+    // アプリのデータディレクトリのSQLiteデータベース
+    // 共有ドライブにファイルを増やしたくない場合や、読み取り専用のメディアの画像にタグを付ける場合に使う
+    Database,
+}
+
+// ディレクトリのタグ情報を取得した結果
+pub struct LoadedDirTags {
+    pub tags: DirTags,
+    // 前回取得してから、アプリの外（他のアプリやエディタなど）で変更されていた
+    pub external: bool,
+    // アプリの外で名前変更・移動された画像に、タグを付け直した
+    // 読み取り専用のメディアなどで保存できなかった場合も、付け直したタグ情報を返す
+    pub relinked: bool,
+    // 他のディレクトリから移動された画像にタグを付け直したため、タグのエントリを外した移動元のディレクトリと
+    // そのタグ情報
+    pub relinked_dirs: Vec<(String, DirTags)>,
+}
+
+// ディレクトリのタグ情報を変更した結果
+pub struct ModifiedDirTags {
+    pub tags: DirTags,
+    // 変更があって保存した
+    pub changed: bool,
+    // 前回取得してから、アプリの外で変更されていた
+    pub external: bool,
+}
+
+// ディレクトリのタグ情報を変更する関数（変更があった場合にtrueを返す）
+pub type ModifyDirTags<'a> = Box<dyn FnOnce(&mut DirTags) -> bool + 'a>;
+
+// タグの保存先の実装
+// dir_pathは検証・正規化済みのディレクトリのパスで、タグ情報はディレクトリ内のファイル名をキーにする
+// タグと一緒に画像の内容のフィンガープリントを保存しておき、タグのエントリの画像がなくなっていれば、
+// 同じフィンガープリントの画像にタグを付け直す
+pub trait TagStore: Send + Sync {
+    // ディレクトリのタグ情報を返す（forceがtrueの場合は、キャッシュがあっても読み直す）
+    // 前回取得したときからディレクトリ内のファイルが変わっていれば、タグを付け直せる画像を探す
+    fn load_dir(&self, dir_path: &str, force: bool) -> Result<LoadedDirTags, CommandError>;
+
+    // ディレクトリのタグ情報をmodifyで変更して、変更があった場合は保存する
+    // 他のウィンドウ・アプリの変更を消さないように、保存されている最新のタグ情報に対して変更する
+    fn modify_dir(
+        &self,
+        dir_path: &str,
+        modify: ModifyDirTags<'_>,
+    ) -> Result<ModifiedDirTags, CommandError>;
+}
+
+// ディレクトリ毎のタグファイルにタグを保存する
+// 読み込んだタグ情報は、外からのタグファイルの変更に気付けるようにタグファイルの状態と一緒にキャッシュする
+// 他のディレクトリに移動された画像は、タグのあるディレクトリの一覧（tag_dirs）のタグファイルから探して
+// タグを付け直す（一覧がない場合は同じディレクトリ内で名前変更された画像だけ）
+#[derive(Default)]
+pub struct FileTagStore {
+    // Directory(String) > FileName(String) > Tags(Vec<String>) のマップ
+    cache: Mutex<HashMap<String, CachedDirTags>>,
+    tag_dirs: Option<Arc<TagDirIndex>>,
+    // 移動された画像を探すときに読み込んだ、他のディレクトリのタグのエントリ
+    other_dirs: Mutex<HashMap<String, CachedOtherDir>>,
+}
+
+struct CachedDirTags {
+    tags: DirTags,
+    stamp: Option<tag_file::Stamp>,
+    // タグを付け直せる画像を探したときのディレクトリの更新日時
+    dir_modified: Option<SystemTime>,
+}
+
+// 他のディレクトリのタグファイルのうち、フィンガープリントのあるタグのエントリ
+// （ファイル名、フィンガープリント、タグ）
+// タグファイルの状態が変わっていなければ使い回す
+struct CachedOtherDir {
+    stamp: Option<tag_file::Stamp>,
+    entries: Vec<(String, Fingerprint, Vec<String>)>,
+}
+
+// 他のディレクトリから移動された画像のタグのエントリ
+struct MovedEntry {
+    from_dir: String,
+    from: String,
+    to: String,
+    tags: Vec<String>,
+}
+
+impl FileTagStore {
+    pub fn new(tag_dirs: Option<Arc<TagDirIndex>>) -> Self {
+        Self {
+            cache: Mutex::default(),
+            tag_dirs,
+            other_dirs: Mutex::default(),
+        }
+    }
+
+    fn lock_cache(&self) -> MutexGuard<'_, HashMap<String, CachedDirTags>> {
+        self.cache.lock().expect("failed to lock tag file cache")
+    }
+
+    fn lock_other_dirs(&self) -> MutexGuard<'_, HashMap<String, CachedOtherDir>> {
+        self.other_dirs
+            .lock()
+            .expect("failed to lock other tag directories cache")
+    }
+
+    // タグのあるディレクトリの一覧の他のディレクトリから、画像がなくなったタグのエントリのうち
+    // dir_pathのタグのない画像とフィンガープリントが一致するものを探す
+    // 他のディレクトリのタグファイルは変わっていなければ読み直さず、タグのない画像と同じサイズの
+    // エントリだけ画像がなくなっているか確かめる
+    fn find_moved(&self, dir_path: &str, tags: &DirTags) -> Vec<MovedEntry> {
+        let Some(tag_dirs) = &self.tag_dirs else {
+            return Vec::new();
+        };
+        let candidates = untagged_files(dir_path, tags);
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+        let candidate_sizes: HashSet<u64> = candidates
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .collect();
+
+        let dirs = tag_dirs.dirs();
+        let mut other_dirs = self.lock_other_dirs();
+        other_dirs.retain(|other_dir, _| dirs.contains(other_dir));
+        let mut orphans = Vec::new();
+        let mut orphan_tags = HashMap::new();
+        for other_dir in dirs {
+            if other_dir == dir_path {
+                continue;
+            }
+            // マウントされていないディレクトリなどは、タグファイルを読めないので飛ばす
+            let Some(cached) = refresh_other_dir(&mut other_dirs, &other_dir) else {
+                continue;
+            };
+            let dir = Path::new(&other_dir);
+            for (file_name, fingerprint, file_tags) in &cached.entries {
+                if !candidate_sizes.contains(&fingerprint.size) || dir.join(file_name).exists() {
+                    continue;
+                }
+                let key = (other_dir.clone(), file_name.clone());
+                orphan_tags.insert(key.clone(), file_tags.clone());
+                orphans.push((key, fingerprint.clone()));
+            }
+        }
+        drop(other_dirs);
+        if orphans.is_empty() {
+            return Vec::new();
+        }
+        fingerprint::match_orphans(&orphans, &candidates)
+            .into_iter()
+            .filter_map(|(key, path)| {
+                let to = path.file_name()?.to_string_lossy().to_string();
+                let tags = orphan_tags.remove(&key)?;
+                let (from_dir, from) = key;
+                Some(MovedEntry {
+                    from_dir,
+                    from,
+                    to,
+                    tags,
+                })
+            })
+            .collect()
+    }
+
+    // 移動元のディレクトリのタグのエントリを外す
+    // 移動先には保存できているので、外せなかった場合（読み取り専用のメディアなど）もそのままにする
+    fn detach_moved(&self, moved: &[MovedEntry]) -> Vec<(String, DirTags)> {
+        let mut relinked_dirs = Vec::new();
+        let mut from_dirs: Vec<&str> = moved.iter().map(|entry| entry.from_dir.as_str()).collect();
+        from_dirs.sort();
+        from_dirs.dedup();
+        for from_dir in from_dirs {
+            let removed: Vec<String> = moved
+                .iter()
+                .filter(|entry| entry.from_dir == from_dir)
+                .map(|entry| entry.from.clone())
+                .collect();
+            let modified = self.modify_dir(
+                from_dir,
+                Box::new(move |tags| {
+                    let count = tags.len();
+                    tags.retain(|file_name, _| !removed.contains(file_name));
+                    tags.len() != count
+                }),
+            );
+            match modified {
+                Ok(modified) if modified.changed => {
+                    relinked_dirs.push((from_dir.to_string(), modified.tags))
+                }
+                _ => {}
+            }
+        }
+        relinked_dirs
+    }
+}
+
+impl TagStore for FileTagStore {
+    fn load_dir(&self, dir_path: &str, force: bool) -> Result<LoadedDirTags, CommandError> {
+        let (tag_file_name, _) = get_tag_file_names(dir_path.to_string())?;
+        let tag_file = Path::new(&tag_file_name);
+
+        let dir_modified = dir_modified(dir_path);
+
+        let mut cache = self.lock_cache();
+        let cached = cache.get(dir_path);
+        if let Some(cached) = cached {
+            if !force
+                && tag_file::is_fresh(tag_file, cached.stamp.as_ref())
+                && cached.dir_modified == dir_modified
+            {
+                return Ok(LoadedDirTags {
+                    tags: cached.tags.clone(),
+                    external: false,
+                    relinked: false,
+                    relinked_dirs: Vec::new(),
+                });
+            }
+        }
+
+        // 古い形式のタグファイルもそのまま読み込み、次に書き込むときに現在の形式に移行する
+        let loaded = tag_file::read_stamped(tag_file)?;
+        let external = cached.is_some_and(|cached| {
+            !tag_file::same_content(cached.stamp.as_ref(), loaded.stamp.as_ref())
+        });
+        let relinks = find_relinks(dir_path, &loaded.tags, &loaded.fingerprints);
+        cache.insert(
+            dir_path.to_string(),
+            CachedDirTags {
+                tags: loaded.tags.clone(),
+                stamp: loaded.stamp,
+                dir_modified,
+            },
+        );
+        drop(cache);
+
+        let mut tags = loaded.tags;
+        let mut relinked = relink(&mut tags, &relinks);
+        let moved = self.find_moved(dir_path, &tags);
+        relinked |= attach_moved(&mut tags, &moved);
+        if !relinked {
+            return Ok(LoadedDirTags {
+                tags,
+                external,
+                relinked: false,
+                relinked_dirs: Vec::new(),
+            });
+        }
+
+        let modified = self.modify_dir(
+            dir_path,
+            Box::new(|tags| relink(tags, &relinks) | attach_moved(tags, &moved)),
+        );
+        match modified {
+            Ok(modified) => Ok(LoadedDirTags {
+                tags: modified.tags,
+                external: external || modified.external,
+                relinked: modified.changed,
+                relinked_dirs: self.detach_moved(&moved),
+            }),
+            // 保存できなくても読み込みは失敗させず、付け直したタグ情報を返す
+            // 移動元のエントリはそのまま残し、保存できるようになってからタグを変更したときに保存する
+            Err(_) => {
+                if let Some(cached) = self.lock_cache().get_mut(dir_path) {
+                    cached.tags = tags.clone();
+                }
+                Ok(LoadedDirTags {
+                    tags,
+                    external,
+                    relinked: true,
+                    relinked_dirs: Vec::new(),
+                })
+            }
+        }
+    }
+
+    // タグファイルをロックしてから読み直したものを変更する
+    // タグファイルに書き込めなかった場合は、キャッシュも変更前のままにしてエラーを返す
+    fn modify_dir(
+        &self,
+        dir_path: &str,
+        modify: ModifyDirTags<'_>,
+    ) -> Result<ModifiedDirTags, CommandError> {
+        let (tag_file_name, tag_temp_file_name) = get_tag_file_names(dir_path.to_string())?;
+        let tag_file = Path::new(&tag_file_name);
+
+        // 他のプロセスを待つ間に他のスレッドがキャッシュを使えるように、キャッシュより先にロックする
+        let _lock = tag_file::lock(&Path::new(dir_path).join(TAG_LOCK_FILE_NAME))?;
+        let mut cache = self.lock_cache();
+        let loaded = tag_file::read_stamped(tag_file)?;
+        let external = cache.get(dir_path).is_some_and(|cached| {
+            !tag_file::same_content(cached.stamp.as_ref(), loaded.stamp.as_ref())
+        });
+        let mut tags = loaded.tags;
+        let changed = modify(&mut tags);
+        let stamp = if changed {
+            let fingerprints = update_fingerprints(dir_path, &tags, loaded.fingerprints);
+            Some(tag_file::write(
+                tag_file,
+                Path::new(&tag_temp_file_name),
+                &tags,
+                &fingerprints,
+            )?)
+        } else {
+            loaded.stamp
+        };
+
+        // 変更がなくても、読み直した他のウィンドウ・アプリの変更を反映する
+        cache.insert(
+            dir_path.to_string(),
+            CachedDirTags {
+                tags: tags.clone(),
+                stamp,
+                // タグファイルを置き換えるとディレクトリの更新日時も変わるので、書き込んだ後に取得する
+                dir_modified: dir_modified(dir_path),
+            },
+        );
+        Ok(ModifiedDirTags {
+            tags,
+            changed,
+            external,
+        })
+    }
+}
+
+// タグのエントリのうち画像がなくなったもの（フィンガープリントがあるものだけ）を、
+// ディレクトリ内のタグのない画像のうちフィンガープリントが一致するものに付け直す組を返す
+fn find_relinks(
+    dir_path: &str,
+    tags: &DirTags,
+    fingerprints: &Fingerprints,
+) -> Vec<(String, String)> {
+    let dir = Path::new(dir_path);
+    let orphans: Vec<(String, Fingerprint)> = fingerprints
+        .iter()
+        .filter(|(file_name, _)| tags.contains_key(*file_name) && !dir.join(file_name).exists())
+        .map(|(file_name, fingerprint)| (file_name.clone(), fingerprint.clone()))
+        .collect();
+    if orphans.is_empty() {
+        return Vec::new();
+    }
+    fingerprint::match_orphans(&orphans, &untagged_files(dir_path, tags))
+        .into_iter()
+        .filter_map(|(file_name, path)| {
+            Some((file_name, path.file_name()?.to_string_lossy().to_string()))
+        })
+        .collect()
+}
+
+// 他のディレクトリのタグのエントリを、タグファイルが変わっていれば読み直して返す
+// タグファイルを読めない場合はNone
+fn refresh_other_dir<'a>(
+    other_dirs: &'a mut HashMap<String, CachedOtherDir>,
+    other_dir: &str,
+) -> Option<&'a CachedOtherDir> {
+    let tag_file = Path::new(other_dir).join(TAG_FILE_NAME);
+    let fresh = other_dirs
+        .get(other_dir)
+        .is_some_and(|cached| tag_file::is_fresh(&tag_file, cached.stamp.as_ref()));
+    if !fresh {
+        let Ok(loaded) = tag_file::read_stamped(&tag_file) else {
+            other_dirs.remove(other_dir);
+            return None;
+        };
+        let entries = loaded
+            .fingerprints
+            .into_iter()
+            .filter_map(|(file_name, fingerprint)| {
+                let file_tags = loaded.tags.get(&file_name)?.clone();
+                Some((file_name, fingerprint, file_tags))
+            })
+            .collect();
+        other_dirs.insert(
+            other_dir.to_string(),
+            CachedOtherDir {
+                stamp: loaded.stamp,
+                entries,
+            },
+        );
+    }
+    other_dirs.get(other_dir)
+}
+
+// タグのエントリを付け直す（付け直し先の画像に既にタグが保存されていた場合はそのままにする）
+fn relink(tags: &mut DirTags, relinks: &[(String, String)]) -> bool {
+    let mut changed = false;
+    for (from, to) in relinks {
+        if tags.contains_key(to) {
+            continue;
+        }
+        if let Some(file_tags) = tags.remove(from) {
+            tags.insert(to.clone(), file_tags);
+            changed = true;
+        }
+    }
+    changed
+}
+
+// 他のディレクトリから移動された画像にタグを付ける（既にタグが保存されていた場合はそのままにする）
+fn attach_moved(tags: &mut DirTags, moved: &[MovedEntry]) -> bool {
+    let mut changed = false;
+    for entry in moved {
+        if !tags.contains_key(&entry.to) {
+            tags.insert(entry.to.clone(), entry.tags.clone());
+            changed = true;
+        }
+    }
+    changed
+}
+
+// タグのエントリに合わせてフィンガープリントを更新する
+// 既にある画像のフィンガープリントはそのまま使い、新しい画像のものだけを求める
+fn update_fingerprints(
+    dir_path: &str,
+    tags: &DirTags,
+    mut fingerprints: Fingerprints,
+) -> Fingerprints {
+    fingerprints.retain(|file_name, _| tags.contains_key(file_name));
+    for file_name in tags.keys() {
+        if fingerprints.contains_key(file_name) {
+            continue;
+        }
+        if let Some(fingerprint) = Fingerprint::of_file(&Path::new(dir_path).join(file_name)) {
+            fingerprints.insert(file_name.clone(), fingerprint);
+        }
+    }
+    fingerprints
+}
+
+// ディレクトリ内のファイルのうち、タグのエントリがないもの（タグを付け直す画像の候補）
+// タグファイルなどのアプリのファイルは除く
+pub fn untagged_files(dir_path: &str, tags: &DirTags) -> Vec<PathBuf> {
+    let Ok(read_dir) = std::fs::read_dir(dir_path) else {
+        return Vec::new();
+    };
+    read_dir
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            !tags.contains_key(&file_name)
+                && ![TAG_FILE_NAME, TAG_TEMP_FILE_NAME, TAG_LOCK_FILE_NAME]
+                    .contains(&file_name.as_str())
+        })
+        .map(|entry| entry.path())
+        .collect()
+}
+
+// ディレクトリの更新日時（ファイルが追加・削除・名前変更されると変わる）
+pub fn dir_modified(dir_path: &str) -> Option<SystemTime> {
+    std::fs::metadata(dir_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// 指定されたディレクトリのタグファイル名と一時ファイル名を取得する
+fn get_tag_file_names(dir_path: String) -> Result<(String, String), CommandError> {
+    ensure_directory(&dir_path)?;
+    let dir = Path::new(&dir_path);
+    let tag_file_name = dir.join(TAG_FILE_NAME);
+    let tag_backup_file_name = dir.join(TAG_TEMP_FILE_NAME);
+    Ok((
+        tag_file_name.to_string_lossy().to_string(),
+        tag_backup_file_name.to_string_lossy().to_string(),
+    ))
+}
+
+// タグを保存するディレクトリが存在するか確かめる
+pub fn ensure_directory(dir_path: &str) -> Result<(), CommandError> {
+    if Path::new(dir_path).is_dir() {
+        Ok(())
+    } else {
+        Err(CommandError::new(
+            ErrorKind::NotADirectory,
+            format!("{dir_path} is not exist or not a directory"),
+        )
+        .with_path(dir_path))
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct TagStorageSettings {
+    backend: TagBackend,
+}
+
+// タグの保存先の一覧と、どちらを使うかの設定
+// データベースは使うまで開かない
+pub struct TagStorage {
+    file: Arc<FileTagStore>,
+    database: Mutex<Option<Arc<DatabaseTagStore>>>,
+    database_path: Option<PathBuf>,
+    settings_path: Option<PathBuf>,
+    backend: Mutex<TagBackend>,
+}
+
+impl TagStorage {
+    // settings_pathの設定ファイルがあればその保存先を使う（なければタグファイル）
+    // database_pathがNoneの場合はデータベースを使えない
+    // tag_dirsはタグファイルで他のディレクトリに移動された画像を探すときに使う
+    pub fn new(
+        settings_path: Option<PathBuf>,
+        database_path: Option<PathBuf>,
+        tag_dirs: Option<Arc<TagDirIndex>>,
+    ) -> Self {
+        let backend = settings_path
+            .as_deref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str::<TagStorageSettings>(&json).ok())
+            .unwrap_or_default()
+            .backend;
+        Self {
+            file: Arc::new(FileTagStore::new(tag_dirs)),
+            database: Mutex::new(None),
+            database_path,
+            settings_path,
+            backend: Mutex::new(backend),
+        }
+    }
+
+    pub fn backend(&self) -> TagBackend {
+        *self.backend.lock().expect("failed to lock tag backend")
+    }
+
+    // 使う保存先を変更して設定ファイルに保存する
+    // データベースに変更する場合は、開けることを確かめてから変更する
+    pub fn set_backend(&self, backend: TagBackend) -> Result<(), CommandError> {
+        self.store(backend)?;
+        if let Some(path) = &self.settings_path {
+            write_settings(path, &TagStorageSettings { backend })?;
+        }
+        *self.backend.lock().expect("failed to lock tag backend") = backend;
+        Ok(())
+    }
+
+    // 現在の保存先
+    pub fn active(&self) -> Result<Arc<dyn TagStore>, CommandError> {
+        self.store(self.backend())
+    }
+
+    pub fn store(&self, backend: TagBackend) -> Result<Arc<dyn TagStore>, CommandError> {
+        match backend {
+            TagBackend::File => Ok(self.file.clone()),
+            TagBackend::Database => {
+                let mut database = self.database.lock().expect("failed to lock tag database");
+                if let Some(database) = database.as_ref() {
+                    return Ok(database.clone());
+                }
+                let path = self.database_path.as_deref().ok_or_else(|| {
+                    CommandError::new(ErrorKind::Other, "Tag database is not available")
+                })?;
+                let opened = Arc::new(DatabaseTagStore::open(path)?);
+                *database = Some(opened.clone());
+                Ok(opened)
+            }
+        }
+    }
+}
+
+// 書き込み途中で終了しても設定ファイルが壊れないように、一時ファイルに書いてから置き換える
+fn write_settings(path: &Path, settings: &TagStorageSettings) -> Result<(), CommandError> {
+    let to_error = |e: std::io::Error| {
+        CommandError::io(format!("Failed to write tag storage settings: {e}"), &e)
+            .with_path(path.to_string_lossy())
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(to_error)?;
+    }
+    let json = serde_json::to_string_pretty(settings).map_err(|e| {
+        CommandError::new(
+            ErrorKind::Other,
+            format!("Failed to serialize tag storage settings: {e}"),
+        )
+    })?;
+    let temp_path = path.with_extension("json.tmp");
+    std::fs::write(&temp_path, json).map_err(to_error)?;
+    std::fs::rename(&temp_path, path).map_err(to_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_tag_file_names() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir_path = temp_dir.path().to_str().unwrap().to_string();
+
+        let result = get_tag_file_names(dir_path.clone());
+
+        assert!(result.is_ok());
+        let (tag_file, temp_file) = result.unwrap();
+        assert!(tag_file.contains("IMAGE_TAG"));
+        assert!(temp_file.contains("IMAGE_TAG_TEMP"));
+    }
+
+    #[test]
+    fn test_get_tag_file_names_invalid_dir() {
+        let dir_path = "/nonexistent/directory".to_string();
+
+        let result = get_tag_file_names(dir_path);
+
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .message
+            .contains("is not exist or not a directory"));
+    }
+
+    #[test]
+    fn test_file_tag_store_relinks_renamed_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir_path = temp_dir.path().to_str().unwrap();
+        std::fs::write(temp_dir.path().join("a.png"), "image a").unwrap();
+        std::fs::write(temp_dir.path().join("b.png"), "image b").unwrap();
+        let store = FileTagStore::default();
+        store
+            .modify_dir(
+                dir_path,
+                Box::new(|tags| {
+                    tags.insert("a.png".to_string(), vec!["cat".to_string()]);
+                    tags.insert("b.png".to_string(), vec!["dog".to_string()]);
+                    true
+                }),
+            )
+            .unwrap();
+        let saved = tag_file::read_stamped(&temp_dir.path().join(TAG_FILE_NAME)).unwrap();
+        assert_eq!(saved.fingerprints.len(), 2);
+
+        // アプリの外で名前変更・削除する
+        std::fs::rename(
+            temp_dir.path().join("a.png"),
+            temp_dir.path().join("renamed.png"),
+        )
+        .unwrap();
+        std::fs::remove_file(temp_dir.path().join("b.png")).unwrap();
+
+        let loaded = store.load_dir(dir_path, false).unwrap();
+        assert!(loaded.relinked);
+        assert!(!loaded.external);
+        assert_eq!(loaded.tags["renamed.png"], vec!["cat"]);
+        assert!(!loaded.tags.contains_key("a.png"));
+        // 削除された画像のタグは残す
+        assert_eq!(loaded.tags["b.png"], vec!["dog"]);
+        let saved = tag_file::read_stamped(&temp_dir.path().join(TAG_FILE_NAME)).unwrap();
+        assert_eq!(saved.tags, loaded.tags);
+        assert!(saved.fingerprints.contains_key("renamed.png"));
+        assert!(!store.load_dir(dir_path, false).unwrap().relinked);
+    }
+
+    #[test]
+    fn test_file_tag_store_returns_relinks_that_cannot_be_saved() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir_path = temp_dir.path().to_str().unwrap();
+        std::fs::write(temp_dir.path().join("a.png"), "image a").unwrap();
+        let store = FileTagStore::default();
+        store
+            .modify_dir(
+                dir_path,
+                Box::new(|tags| {
+                    tags.insert("a.png".to_string(), vec!["cat".to_string()]);
+                    true
+                }),
+            )
+            .unwrap();
+        std::fs::rename(
+            temp_dir.path().join("a.png"),
+            temp_dir.path().join("renamed.png"),
+        )
+        .unwrap();
+        // 一時ファイルを作れないようにして、タグファイルへの書き込みを失敗させる
+        std::fs::create_dir(temp_dir.path().join(TAG_TEMP_FILE_NAME)).unwrap();
+
+        let loaded = store.load_dir(dir_path, false).unwrap();
+        assert!(loaded.relinked);
+        assert_eq!(loaded.tags["renamed.png"], vec!["cat"]);
+        let saved = tag_file::read_stamped(&temp_dir.path().join(TAG_FILE_NAME)).unwrap();
+        assert!(saved.tags.contains_key("a.png"));
+        // 付け直したタグ情報はキャッシュから返す
+        let loaded = store.load_dir(dir_path, false).unwrap();
+        assert_eq!(loaded.tags["renamed.png"], vec!["cat"]);
+    }
+
+    #[test]
+    fn test_file_tag_store_relinks_files_moved_from_other_dirs() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let from_dir = temp_dir.path().join("from");
+        let to_dir = temp_dir.path().join("to");
+        std::fs::create_dir(&from_dir).unwrap();
+        std::fs::create_dir(&to_dir).unwrap();
+        let from_path = from_dir.to_str().unwrap();
+        let to_path = to_dir.to_str().unwrap();
+        std::fs::write(from_dir.join("a.png"), "image a").unwrap();
+        std::fs::write(from_dir.join("b.png"), "image b").unwrap();
+        let tag_dirs = Arc::new(TagDirIndex::new(None));
+        tag_dirs.register(from_path).unwrap();
+        let store = FileTagStore::new(Some(tag_dirs));
+        store
+            .modify_dir(
+                from_path,
+                Box::new(|tags| {
+                    tags.insert("a.png".to_string(), vec!["cat".to_string()]);
+                    tags.insert("b.png".to_string(), vec!["dog".to_string()]);
+                    true
+                }),
+            )
+            .unwrap();
+
+        // アプリの外で別のディレクトリに移動する
+        std::fs::rename(from_dir.join("a.png"), to_dir.join("moved.png")).unwrap();
+
+        let loaded = store.load_dir(to_path, false).unwrap();
+        assert!(loaded.relinked);
+        assert_eq!(loaded.tags["moved.png"], vec!["cat"]);
+        let saved = tag_file::read_stamped(&to_dir.join(TAG_FILE_NAME)).unwrap();
+        assert_eq!(saved.tags, loaded.tags);
+        // 移動元のエントリは外して、移動元のタグ情報として返す
+        assert_eq!(loaded.relinked_dirs.len(), 1);
+        assert_eq!(loaded.relinked_dirs[0].0, from_path);
+        assert!(!loaded.relinked_dirs[0].1.contains_key("a.png"));
+        let saved = tag_file::read_stamped(&from_dir.join(TAG_FILE_NAME)).unwrap();
+        assert_eq!(saved.tags.keys().collect::<Vec<_>>(), vec!["b.png"]);
+    }
+
+    #[test]
+    fn test_file_tag_store_rereads_other_dirs_only_when_changed() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let from_dir = temp_dir.path().join("from");
+        let to_dir = temp_dir.path().join("to");
+        std::fs::create_dir(&from_dir).unwrap();
+        std::fs::create_dir(&to_dir).unwrap();
+        let from_path = from_dir.to_str().unwrap();
+        let to_path = to_dir.to_str().unwrap();
+        std::fs::write(from_dir.join("a.png"), "image a").unwrap();
+        std::fs::write(from_dir.join("b.png"), "image b").unwrap();
+        std::fs::write(to_dir.join("other.png"), "other image").unwrap();
+        let tag_dirs = Arc::new(TagDirIndex::new(None));
+        tag_dirs.register(from_path).unwrap();
+        // 一覧にあるがなくなったディレクトリは飛ばす
+        tag_dirs
+            .register(temp_dir.path().join("unmounted").to_str().unwrap())
+            .unwrap();
+        let store = FileTagStore::new(Some(tag_dirs));
+        store
+            .modify_dir(
+                from_path,
+                Box::new(|tags| {
+                    tags.insert("a.png".to_string(), vec!["cat".to_string()]);
+                    true
+                }),
+            )
+            .unwrap();
+
+        let loaded = store.load_dir(to_path, false).unwrap();
+        assert!(!loaded.relinked);
+        let stamp = store.lock_other_dirs()[from_path].stamp.clone();
+        assert!(stamp.is_some());
+
+        // 他のウィンドウ・アプリでタグを付けると、タグファイルを読み直して移動された画像を探す
+        FileTagStore::default()
+            .modify_dir(
+                from_path,
+                Box::new(|tags| {
+                    tags.insert("b.png".to_string(), vec!["dog".to_string()]);
+                    true
+                }),
+            )
+            .unwrap();
+        std::fs::rename(from_dir.join("b.png"), to_dir.join("moved.png")).unwrap();
+
+        let loaded = store.load_dir(to_path, false).unwrap();
+        assert!(loaded.relinked);
+        assert_eq!(loaded.tags["moved.png"], vec!["dog"]);
+        assert_ne!(store.lock_other_dirs()[from_path].stamp, stamp);
+    }
+
+    #[test]
+    fn test_tag_storage_backend_setting() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let settings_path = temp_dir.path().join("config").join(TAG_STORAGE_FILE_NAME);
+        let database_path = temp_dir.path().join("data").join(TAG_DATABASE_FILE_NAME);
+
+        let storage = TagStorage::new(
+            Some(settings_path.clone()),
+            Some(database_path.clone()),
+            None,
+        );
+        assert_eq!(storage.backend(), TagBackend::File);
+        storage.set_backend(TagBackend::Database).unwrap();
+        assert!(database_path.exists());
+
+        // 設定は次に起動したときも使われる
+        let storage = TagStorage::new(Some(settings_path), Some(database_path), None);
+        assert_eq!(storage.backend(), TagBackend::Database);
+    }
+
+    #[test]
+    fn test_tag_storage_without_database() {
+        let storage = TagStorage::new(None, None, None);
+
+        assert!(storage.set_backend(TagBackend::Database).is_err());
+        assert_eq!(storage.backend(), TagBackend::File);
+    }
+}
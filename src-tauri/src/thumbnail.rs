@@ -0,0 +1,304 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::DynamicImage;
+
+use crate::transcode;
+
+// アプリのキャッシュディレクトリ内のサムネイル保存先のフォルダ名
+pub const THUMBNAIL_DIR_NAME: &str = "thumbnails";
+
+// サムネイルの長辺のデフォルト値と上下限（ピクセル）
+pub const DEFAULT_MAX_EDGE: u32 = 256;
+const MIN_MAX_EDGE: u32 = 16;
+const MAX_MAX_EDGE: u32 = 2048;
+
+// キャッシュの合計サイズの上限
+pub const DEFAULT_CACHE_MAX_BYTES: u64 = 512 * 1024 * 1024;
+// 上限を超えた場合は、上限に対してこの割合になるまで古いものから削除する
+const CACHE_LOW_WATERMARK_PERCENT: u64 = 80;
+
+const JPEG_QUALITY: u8 = 85;
+const JPEG_EXT: &str = "jpg";
+const WEBP_EXT: &str = "webp";
+
+// サムネイルをファイルとしてキャッシュする
+// キャッシュのキーは元画像のパス・更新日時・サイズとサムネイルの長辺の組み合わせで、
+// 元画像が更新されると別のキーになる（古いキャッシュはいずれ削除される）
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    // キャッシュの合計サイズ（最初に追加する時点でフォルダを走査して求める）
+    total_bytes: Mutex<Option<u64>>,
+    // 一時ファイル名の重複を防ぐための連番
+    temp_counter: AtomicU64,
+}
+
+impl ThumbnailCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            total_bytes: Mutex::new(None),
+            temp_counter: AtomicU64::new(0),
+        }
+    }
+
+    // 画像のサムネイルのキャッシュファイルのパスを返す
+    // キャッシュがなければ生成してキャッシュに追加する
+    pub fn get_thumbnail(&self, image_path: &Path, max_edge: u32) -> Result<PathBuf, String> {
+        let max_edge = max_edge.clamp(MIN_MAX_EDGE, MAX_MAX_EDGE);
+        let key = cache_key(image_path, max_edge)?;
+
+        for ext in [JPEG_EXT, WEBP_EXT] {
+            let cached_path = self.dir.join(format!("{key}.{ext}"));
+            if cached_path.is_file() {
+                // 最近使われたものとして削除の対象から外れるように更新日時を更新する
+                // 失敗してもキャッシュとしては使えるので無視する
+                let _ = touch(&cached_path);
+                return Ok(cached_path);
+            }
+        }
+
+        // デコード・縮小は時間がかかるので、ロックを取らずに行う
+        let (data, ext) = generate_thumbnail(image_path, max_edge)?;
+
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create thumbnail cache directory: {e}"))?;
+        let cached_path = self.dir.join(format!("{key}.{ext}"));
+        let temp_path = self.dir.join(format!(
+            "{key}.{}.{}.tmp",
+            std::process::id(),
+            self.temp_counter.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&temp_path, &data).map_err(|e| format!("Failed to write thumbnail: {e}"))?;
+        if let Err(e) = std::fs::rename(&temp_path, &cached_path) {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(format!("Failed to write thumbnail: {e}"));
+        }
+
+        self.add_and_evict(data.len() as u64, &cached_path);
+        Ok(cached_path)
+    }
+
+    // キャッシュの合計サイズに追加し、上限を超えた場合は古いものから削除する
+    fn add_and_evict(&self, added_bytes: u64, added_path: &Path) {
+        let mut total_bytes = self
+            .total_bytes
+            .lock()
+            .expect("failed to lock thumbnail cache");
+        let total = match *total_bytes {
+            Some(total) => total + added_bytes,
+            // 追加したファイルも含めて数える
+            None => self.list_cached_files().iter().map(|f| f.size).sum(),
+        };
+        if total <= self.max_bytes {
+            *total_bytes = Some(total);
+            return;
+        }
+
+        let low_watermark = self.max_bytes * CACHE_LOW_WATERMARK_PERCENT / 100;
+        let mut files = self.list_cached_files();
+        files.sort_by_key(|f| f.modified);
+        // 他のスレッドが同時に追加・削除している場合があるので、改めて数え直す
+        let mut total: u64 = files.iter().map(|f| f.size).sum();
+        for file in files {
+            if total <= low_watermark {
+                break;
+            }
+            if file.path == added_path {
+                continue;
+            }
+            if std::fs::remove_file(&file.path).is_ok() {
+                total -= file.size;
+            }
+        }
+        *total_bytes = Some(total);
+    }
+
+    fn list_cached_files(&self) -> Vec<CachedFile> {
+        let Ok(read_dir) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        read_dir
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .path()
+                    .extension()
+                    .is_some_and(|ext| ext == JPEG_EXT || ext == WEBP_EXT)
+            })
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some(CachedFile {
+                    path: entry.path(),
+                    size: metadata.len(),
+                    modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+                })
+            })
+            .collect()
+    }
+}
+
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+// キャッシュのキーとして、元画像のパス・更新日時・サイズとサムネイルの長辺のハッシュを返す
+fn cache_key(image_path: &Path, max_edge: u32) -> Result<String, String> {
+    let canonical_path = image_path
+        .canonicalize()
+        .map_err(|e| format!("Failed to canonicalize path {}: {e}", image_path.display()))?;
+    let metadata = std::fs::metadata(&canonical_path)
+        .map_err(|e| format!("Failed to get file metadata: {e}"))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(canonical_path.as_os_str().as_encoded_bytes());
+    hasher.update(&modified.as_nanos().to_le_bytes());
+    hasher.update(&metadata.len().to_le_bytes());
+    hasher.update(&max_edge.to_le_bytes());
+    Ok(hasher.finalize().to_hex()[..32].to_string())
+}
+
+// 画像をデコードして長辺がmax_edge以下になるように縮小し、エンコードしたデータと拡張子を返す
+// 透過がある画像は透過を保つためWebP（ロスレス）、それ以外はJPEGにする
+fn generate_thumbnail(image_path: &Path, max_edge: u32) -> Result<(Vec<u8>, &'static str), String> {
+    let image = transcode::decode_image(image_path)?;
+
+    let thumbnail = if image.width() > max_edge || image.height() > max_edge {
+        image.thumbnail(max_edge, max_edge)
+    } else {
+        image
+    };
+
+    let mut data = Cursor::new(Vec::new());
+    if thumbnail.color().has_alpha() {
+        let rgba = DynamicImage::ImageRgba8(thumbnail.to_rgba8());
+        rgba.write_with_encoder(WebPEncoder::new_lossless(&mut data))
+            .map_err(|e| format!("Failed to encode thumbnail: {e}"))?;
+        Ok((data.into_inner(), WEBP_EXT))
+    } else {
+        let rgb = DynamicImage::ImageRgb8(thumbnail.to_rgb8());
+        rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY))
+            .map_err(|e| format!("Failed to encode thumbnail: {e}"))?;
+        Ok((data.into_inner(), JPEG_EXT))
+    }
+}
+
+fn touch(path: &Path) -> std::io::Result<()> {
+    std::fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+    use tempfile::TempDir;
+
+    fn create_rgb_image(path: &Path, width: u32, height: u32) {
+        RgbImage::from_pixel(width, height, Rgb([200, 100, 50]))
+            .save(path)
+            .expect("Failed to create test image");
+    }
+
+    #[test]
+    fn test_get_thumbnail_downscales_and_caches() {
+        let temp_dir = TempDir::new().unwrap();
+        let image_path = temp_dir.path().join("large.png");
+        create_rgb_image(&image_path, 400, 200);
+        let cache = ThumbnailCache::new(temp_dir.path().join("cache"), DEFAULT_CACHE_MAX_BYTES);
+
+        let thumbnail_path = cache.get_thumbnail(&image_path, 100).unwrap();
+
+        assert_eq!(thumbnail_path.extension().unwrap(), JPEG_EXT);
+        let size = imagesize::size(&thumbnail_path).unwrap();
+        assert_eq!((size.width, size.height), (100, 50));
+
+        // 2回目はキャッシュが返される
+        let cached_path = cache.get_thumbnail(&image_path, 100).unwrap();
+        assert_eq!(cached_path, thumbnail_path);
+
+        // サイズが異なれば別のキャッシュになる
+        let other_path = cache.get_thumbnail(&image_path, 50).unwrap();
+        assert_ne!(other_path, thumbnail_path);
+    }
+
+    #[test]
+    fn test_get_thumbnail_keeps_small_image_and_alpha() {
+        let temp_dir = TempDir::new().unwrap();
+        let image_path = temp_dir.path().join("alpha.png");
+        RgbaImage::from_pixel(20, 10, Rgba([0, 0, 0, 128]))
+            .save(&image_path)
+            .unwrap();
+        let cache = ThumbnailCache::new(temp_dir.path().join("cache"), DEFAULT_CACHE_MAX_BYTES);
+
+        let thumbnail_path = cache.get_thumbnail(&image_path, 100).unwrap();
+
+        assert_eq!(thumbnail_path.extension().unwrap(), WEBP_EXT);
+        let size = imagesize::size(&thumbnail_path).unwrap();
+        assert_eq!((size.width, size.height), (20, 10));
+    }
+
+    #[test]
+    fn test_get_thumbnail_invalidated_by_modification() {
+        let temp_dir = TempDir::new().unwrap();
+        let image_path = temp_dir.path().join("image.png");
+        create_rgb_image(&image_path, 40, 40);
+        let cache = ThumbnailCache::new(temp_dir.path().join("cache"), DEFAULT_CACHE_MAX_BYTES);
+        let before = cache.get_thumbnail(&image_path, 32).unwrap();
+
+        create_rgb_image(&image_path, 80, 40);
+        let after = cache.get_thumbnail(&image_path, 32).unwrap();
+
+        assert_ne!(before, after);
+    }
+
+    #[test]
+    fn test_get_thumbnail_evicts_old_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let cache = ThumbnailCache::new(cache_dir.clone(), 1);
+
+        let mut last_path = PathBuf::new();
+        for i in 0..3 {
+            let image_path = temp_dir.path().join(format!("image{i}.png"));
+            create_rgb_image(&image_path, 40, 40);
+            last_path = cache.get_thumbnail(&image_path, 32).unwrap();
+        }
+
+        // 上限を超えているので、直近に追加したもの以外は削除される
+        let remaining: Vec<_> = std::fs::read_dir(&cache_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(remaining, vec![last_path]);
+    }
+
+    #[test]
+    fn test_get_thumbnail_invalid_image() {
+        let temp_dir = TempDir::new().unwrap();
+        let image_path = temp_dir.path().join("broken.jpg");
+        std::fs::write(&image_path, "fake image content").unwrap();
+        let cache = ThumbnailCache::new(temp_dir.path().join("cache"), DEFAULT_CACHE_MAX_BYTES);
+
+        let result = cache.get_thumbnail(&image_path, 100);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Failed to decode image"));
+    }
+}
@@ -0,0 +1,274 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use image::DynamicImage;
+
+use crate::archive;
+use crate::image_format::{self, ImageFormat};
+use crate::raw;
+
+// 画像を配信するURIスキーム
+// フロントエンドからは convertFileSrc(path, "image") で変換したURLで読み込む
+pub const URI_SCHEME: &str = "image";
+
+// リクエストのURIのパス（"/" + パーセントエンコードされたファイルパス）からファイルパスを返す
+pub fn decode_uri_path(uri_path: &str) -> Result<PathBuf, String> {
+    let encoded = uri_path.strip_prefix('/').unwrap_or(uri_path);
+    let decoded = percent_encoding::percent_decode_str(encoded)
+        .decode_utf8()
+        .map_err(|e| format!("Invalid path encoding: {e}"))?;
+    if decoded.is_empty() {
+        return Err("Empty path".to_string());
+    }
+    Ok(PathBuf::from(decoded.into_owned()))
+}
+
+// Webviewで表示できる形式の画像データとそのMIMEタイプを返す
+// Webviewで表示できない形式はPNGに変換し（RAW画像はプレビューのJPEGを取り出し）、
+// それ以外はファイルの内容をそのまま返す
+pub fn load_for_webview(path: &Path) -> Result<(Vec<u8>, &'static str), String> {
+    let format = image_format::detect_image_format(path)
+        .ok_or_else(|| "File is not a supported image format".to_string())?;
+    // RAW画像は埋め込まれたプレビューのJPEGをそのまま返す
+    if format == ImageFormat::Raw {
+        return Ok((raw::extract_preview(path)?, ImageFormat::Jpeg.mime_type()));
+    }
+    if !format.needs_transcoding() {
+        let data = std::fs::read(path).map_err(|e| format!("Failed to read image: {e}"))?;
+        return Ok((data, format.mime_type()));
+    }
+
+    encode_png(&decode_image(path)?)
+}
+
+// アーカイブ内のエントリを展開して、Webviewで表示できる形式の画像データとそのMIMEタイプを返す
+// 形式毎の扱いは load_for_webview と同じ
+pub fn load_archive_entry_for_webview(
+    archive_path: &Path,
+    entry_name: &str,
+) -> Result<(Vec<u8>, &'static str), String> {
+    let data = archive::read_entry(archive_path, entry_name)?;
+    let format = image_format::detect_image_format_from_bytes(entry_name, &data)
+        .ok_or_else(|| "File is not a supported image format".to_string())?;
+    if format == ImageFormat::Raw {
+        let preview = raw::extract_preview_from_bytes(&data)?.to_vec();
+        return Ok((preview, ImageFormat::Jpeg.mime_type()));
+    }
+    if !format.needs_transcoding() {
+        return Ok((data, format.mime_type()));
+    }
+
+    encode_png(&decode_image_from_bytes(format, &data)?)
+}
+
+fn encode_png(image: &DynamicImage) -> Result<(Vec<u8>, &'static str), String> {
+    let mut data = Cursor::new(Vec::new());
+    image
+        .write_to(&mut data, image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode image: {e}"))?;
+    Ok((data.into_inner(), ImageFormat::Png.mime_type()))
+}
+
+// 画像をデコードする
+// JPEG XLはjxl-oxide、それ以外はimageクレートでデコードする
+// RAW画像は埋め込まれたプレビューをデコードする
+pub fn decode_image(path: &Path) -> Result<DynamicImage, String> {
+    match image_format::detect_image_format(path) {
+        Some(ImageFormat::Jxl) => {
+            let file =
+                std::fs::File::open(path).map_err(|e| format!("Failed to open image: {e}"))?;
+            let decoder = jxl_oxide::integration::JxlDecoder::new(std::io::BufReader::new(file))
+                .map_err(|e| format!("Failed to decode image: {e}"))?;
+            DynamicImage::from_decoder(decoder).map_err(|e| format!("Failed to decode image: {e}"))
+        }
+        Some(ImageFormat::Heic) => {
+            decode_heic(&std::fs::read(path).map_err(|e| format!("Failed to read image: {e}"))?)
+        }
+        Some(ImageFormat::Raw) => image::load_from_memory_with_format(
+            &raw::extract_preview(path)?,
+            image::ImageFormat::Jpeg,
+        )
+        .map_err(|e| format!("Failed to decode image: {e}")),
+        _ => image::ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|e| format!("Failed to open image: {e}"))?
+            .decode()
+            .map_err(|e| format!("Failed to decode image: {e}")),
+    }
+}
+
+// メモリ上の画像データをデコードする
+// 形式毎の扱いは decode_image と同じ
+fn decode_image_from_bytes(format: ImageFormat, data: &[u8]) -> Result<DynamicImage, String> {
+    match format {
+        ImageFormat::Jxl => {
+            let decoder = jxl_oxide::integration::JxlDecoder::new(Cursor::new(data))
+                .map_err(|e| format!("Failed to decode image: {e}"))?;
+            DynamicImage::from_decoder(decoder).map_err(|e| format!("Failed to decode image: {e}"))
+        }
+        ImageFormat::Heic => decode_heic(data),
+        ImageFormat::Raw => image::load_from_memory_with_format(
+            raw::extract_preview_from_bytes(data)?,
+            image::ImageFormat::Jpeg,
+        )
+        .map_err(|e| format!("Failed to decode image: {e}")),
+        _ => image::load_from_memory(data).map_err(|e| format!("Failed to decode image: {e}")),
+    }
+}
+
+// HEIC・HEIFの主画像をlibheifでデコードする
+#[cfg(feature = "heic")]
+fn decode_heic(data: &[u8]) -> Result<DynamicImage, String> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let to_error = |e: libheif_rs::HeifError| format!("Failed to decode image: {e}");
+    let context = HeifContext::read_from_bytes(data).map_err(to_error)?;
+    let handle = context.primary_image_handle().map_err(to_error)?;
+    let image = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
+        .map_err(to_error)?;
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or_else(|| "Failed to decode image: no interleaved plane".to_string())?;
+
+    // 行の末尾にパディングがある場合があるので、1行ずつ詰めてコピーする
+    let row_len = plane.width as usize * 4;
+    let pixels: Vec<u8> = plane
+        .data
+        .chunks(plane.stride)
+        .take(plane.height as usize)
+        .flat_map(|row| &row[..row_len])
+        .copied()
+        .collect();
+    image::RgbaImage::from_raw(plane.width, plane.height, pixels)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| "Failed to decode image: invalid image size".to_string())
+}
+
+// heic フィーチャーなしではHEICを画像として扱わないので、ここには来ない
+#[cfg(not(feature = "heic"))]
+fn decode_heic(_data: &[u8]) -> Result<DynamicImage, String> {
+    Err("Decoding HEIC images is not supported in this build".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write_test_image(path: &Path, format: image::ImageFormat) {
+        DynamicImage::new_rgb8(4, 3)
+            .save_with_format(path, format)
+            .expect("Failed to write test image");
+    }
+
+    #[test]
+    fn test_decode_uri_path() {
+        assert_eq!(
+            decode_uri_path("/%2Fphotos%2Fmy%20scan.tif"),
+            Ok(PathBuf::from("/photos/my scan.tif"))
+        );
+        assert_eq!(
+            decode_uri_path("/C%3A%5Cphotos%5C%E5%86%99%E7%9C%9F.jxl"),
+            Ok(PathBuf::from("C:\\photos\\写真.jxl"))
+        );
+        assert!(decode_uri_path("/").is_err());
+        assert!(decode_uri_path("/%FF").is_err());
+    }
+
+    #[test]
+    fn test_load_for_webview_transcodes_tiff() {
+        let temp_dir = TempDir::new().unwrap();
+        let tiff = temp_dir.path().join("scan.tif");
+        write_test_image(&tiff, image::ImageFormat::Tiff);
+
+        let (data, mime_type) = load_for_webview(&tiff).unwrap();
+
+        assert_eq!(mime_type, "image/png");
+        let decoded = image::load_from_memory_with_format(&data, image::ImageFormat::Png).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (4, 3));
+    }
+
+    #[test]
+    fn test_load_for_webview_detects_format_from_content() {
+        let temp_dir = TempDir::new().unwrap();
+        // 拡張子のないTIFFと、拡張子がJPEGのTIFF
+        let no_extension = temp_dir.path().join("scan");
+        write_test_image(&no_extension, image::ImageFormat::Tiff);
+        let misnamed = temp_dir.path().join("scan.jpg");
+        fs::copy(&no_extension, &misnamed).unwrap();
+
+        for path in [no_extension, misnamed] {
+            let (data, mime_type) = load_for_webview(&path).unwrap();
+            assert_eq!(mime_type, "image/png");
+            assert!(image::load_from_memory_with_format(&data, image::ImageFormat::Png).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_load_for_webview_passes_through_native_formats() {
+        let temp_dir = TempDir::new().unwrap();
+        let bmp = temp_dir.path().join("image.bmp");
+        write_test_image(&bmp, image::ImageFormat::Bmp);
+
+        let (data, mime_type) = load_for_webview(&bmp).unwrap();
+
+        assert_eq!(mime_type, "image/bmp");
+        assert_eq!(data, fs::read(&bmp).unwrap());
+    }
+
+    #[test]
+    fn test_load_for_webview_extracts_raw_preview() {
+        let temp_dir = TempDir::new().unwrap();
+        let preview = raw::test_files::jpeg(64, 48);
+        let raw_file = temp_dir.path().join("_DSC0001.ARW");
+        fs::write(&raw_file, raw::test_files::tiff_based_raw(&preview)).unwrap();
+
+        let (data, mime_type) = load_for_webview(&raw_file).unwrap();
+        assert_eq!(mime_type, "image/jpeg");
+        assert_eq!(data, preview);
+
+        let decoded = decode_image(&raw_file).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 48));
+    }
+
+    #[test]
+    fn test_load_archive_entry_for_webview() {
+        let temp_dir = TempDir::new().unwrap();
+        let tiff = temp_dir.path().join("scan.tif");
+        write_test_image(&tiff, image::ImageFormat::Tiff);
+        let bmp = temp_dir.path().join("image.bmp");
+        write_test_image(&bmp, image::ImageFormat::Bmp);
+        let zip = temp_dir.path().join("pack.zip");
+        archive::test_files::write_zip(
+            &zip,
+            &[
+                ("scan.tif", &fs::read(&tiff).unwrap()),
+                ("image.bmp", &fs::read(&bmp).unwrap()),
+                ("notes.txt", b"not an image"),
+            ],
+        );
+
+        let (data, mime_type) = load_archive_entry_for_webview(&zip, "scan.tif").unwrap();
+        assert_eq!(mime_type, "image/png");
+        let decoded = image::load_from_memory_with_format(&data, image::ImageFormat::Png).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (4, 3));
+
+        let (data, mime_type) = load_archive_entry_for_webview(&zip, "image.bmp").unwrap();
+        assert_eq!(mime_type, "image/bmp");
+        assert_eq!(data, fs::read(&bmp).unwrap());
+
+        assert!(load_archive_entry_for_webview(&zip, "notes.txt").is_err());
+    }
+
+    #[test]
+    fn test_load_for_webview_rejects_non_images() {
+        let temp_dir = TempDir::new().unwrap();
+        let text = temp_dir.path().join("notes.txt");
+        fs::write(&text, "not an image").unwrap();
+
+        assert!(load_for_webview(&text).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+
+use crate::error::{CommandError, ErrorKind};
+
+// 削除の履歴に残す件数の上限（超えた場合は古いものから破棄する）
+const MAX_JOURNAL_ENTRIES: usize = 100;
+
+// 削除した画像のパスと、画像一覧での位置
+// 削除の履歴では削除前の位置、復元の結果では復元後の位置を表す
+#[derive(Clone, serde::Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletedImage {
+    pub path: String,
+    pub index: usize,
+}
+
+// セッション毎の削除の履歴（削除を取り消すために使う）
+#[derive(Debug, Default)]
+pub struct DeleteJournal {
+    entries: VecDeque<DeletedImage>,
+}
+
+impl DeleteJournal {
+    pub fn record(&mut self, path: String, index: usize) {
+        if self.entries.len() == MAX_JOURNAL_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(DeletedImage { path, index });
+    }
+
+    // 最後に削除した画像を履歴から取り出す
+    pub fn pop(&mut self) -> Option<DeletedImage> {
+        self.entries.pop_back()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+// 画像一覧から画像を取り除いて、取り除く前の位置を返す
+pub fn remove_image(paths: &mut Vec<String>, path: &str) -> Option<usize> {
+    let index = paths.iter().position(|p| p == path)?;
+    paths.remove(index);
+    Some(index)
+}
+
+// 復元した画像を削除前の位置（一覧が短くなっている場合は末尾）に戻して、戻した位置を返す
+// ディレクトリの監視で既に一覧に追加されている場合は、削除前の位置に移動する
+// 新しい順に復元すれば、間に他の変更がない限り削除前の並びに戻る
+pub fn reinsert_image(paths: &mut Vec<String>, image: &DeletedImage) -> usize {
+    remove_image(paths, &image.path);
+    let index = image.index.min(paths.len());
+    paths.insert(index, image.path.clone());
+    index
+}
+
+// ゴミ箱に移動したファイルを元の場所に復元する
+// 同じパスのファイルがゴミ箱に複数ある場合は、最後に削除したものを復元する
+#[cfg(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+))]
+pub fn restore_from_trash(path: &str) -> Result<(), CommandError> {
+    let original_path = std::path::Path::new(path);
+    let item = trash::os_limited::list()
+        .map_err(|e| {
+            CommandError::new(ErrorKind::Io, "Failed to list the trash")
+                .with_path(path)
+                .with_source(e)
+        })?
+        .into_iter()
+        .filter(|item| item.original_path() == original_path)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| {
+            CommandError::new(ErrorKind::NotFound, format!("{path} is not in the trash"))
+                .with_path(path)
+        })?;
+    trash::os_limited::restore_all([item]).map_err(|e| match e {
+        trash::Error::RestoreCollision { .. } => crate::file_ops::already_exists(path),
+        e => CommandError::new(ErrorKind::Io, format!("Failed to restore {path}"))
+            .with_path(path)
+            .with_source(e),
+    })
+}
+
+// macOSのゴミ箱からの復元はtrashクレートが対応していない
+#[cfg(not(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+)))]
+pub fn restore_from_trash(path: &str) -> Result<(), CommandError> {
+    Err(CommandError::new(
+        ErrorKind::Other,
+        format!("Restoring {path} from the trash is not supported on this platform"),
+    )
+    .with_path(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_journal_keeps_latest_entries() {
+        let mut journal = DeleteJournal::default();
+        for i in 0..=MAX_JOURNAL_ENTRIES {
+            journal.record(format!("/p/{i}.png"), i);
+        }
+
+        let latest = journal.pop().unwrap();
+        assert_eq!(latest.path, format!("/p/{MAX_JOURNAL_ENTRIES}.png"));
+        let mut remaining = 0;
+        while journal.pop().is_some() {
+            remaining += 1;
+        }
+        assert_eq!(remaining, MAX_JOURNAL_ENTRIES - 1);
+    }
+
+    #[test]
+    fn test_reinsert_in_reverse_order_restores_original_order() {
+        let original = strings(&["/p/a.png", "/p/b.png", "/p/c.png", "/p/d.png"]);
+        let mut paths = original.clone();
+        let mut journal = DeleteJournal::default();
+        for path in ["/p/b.png", "/p/d.png", "/p/a.png"] {
+            let index = remove_image(&mut paths, path).unwrap();
+            journal.record(path.to_string(), index);
+        }
+        assert_eq!(paths, strings(&["/p/c.png"]));
+
+        while let Some(image) = journal.pop() {
+            reinsert_image(&mut paths, &image);
+        }
+
+        assert_eq!(paths, original);
+    }
+
+    #[test]
+    fn test_reinsert_moves_already_added_image() {
+        // ディレクトリの監視で先に末尾に追加されていた場合
+        let mut paths = strings(&["/p/b.png", "/p/c.png", "/p/a.png"]);
+        let image = DeletedImage {
+            path: "/p/a.png".to_string(),
+            index: 0,
+        };
+
+        assert_eq!(reinsert_image(&mut paths, &image), 0);
+        assert_eq!(paths, strings(&["/p/a.png", "/p/b.png", "/p/c.png"]));
+
+        // 一覧が短くなっている場合は末尾に戻す
+        let image = DeletedImage {
+            path: "/p/z.png".to_string(),
+            index: 10,
+        };
+        assert_eq!(reinsert_image(&mut paths, &image), 3);
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify_debouncer_full::notify::event::{ModifyKind, RenameMode};
+use notify_debouncer_full::notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{
+    new_debouncer, DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache,
+};
+
+use crate::archive;
+
+// ファイルの変更をまとめて通知するまでの待ち時間
+// コピー中のファイルや、一時ファイルへの書き込み→名前変更による保存を1回の変更として扱うため
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// 監視で検知したファイルの変更
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileChange {
+    Created(PathBuf),
+    Removed(PathBuf),
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+// 画像一覧に反映した変更
+// renamedは (変更前のパス, 変更後のパス) の配列
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AppliedChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub renamed: Vec<(String, String)>,
+}
+
+// 画像一覧の元になったディレクトリの監視
+// dropすると監視を終了する
+pub struct DirectoryWatcher {
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+}
+
+impl std::fmt::Debug for DirectoryWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DirectoryWatcher").finish_non_exhaustive()
+    }
+}
+
+impl DirectoryWatcher {
+    // 指定されたディレクトリ直下（サブフォルダは含まない）の監視を開始する
+    // 変更を検知すると、まとめた変更を別スレッドから on_changes に渡す
+    // 監視できないディレクトリ（既に削除された等）は無視する
+    pub fn watch(
+        dirs: &BTreeSet<PathBuf>,
+        mut on_changes: impl FnMut(Vec<FileChange>) + Send + 'static,
+    ) -> Result<Self, String> {
+        let mut debouncer = new_debouncer(
+            DEBOUNCE_TIMEOUT,
+            None,
+            move |result: DebounceEventResult| {
+                // 監視のエラーは次の変更の検知には影響しないので無視する
+                let Ok(events) = result else {
+                    return;
+                };
+                let changes = classify_events(&events);
+                if !changes.is_empty() {
+                    on_changes(changes);
+                }
+            },
+        )
+        .map_err(|e| format!("Failed to start watching: {e}"))?;
+        for dir in dirs {
+            let _ = debouncer.watch(dir, RecursiveMode::NonRecursive);
+        }
+        Ok(Self {
+            _debouncer: debouncer,
+        })
+    }
+}
+
+// 監視するディレクトリ（ドロップされたフォルダと、画像一覧の各画像の親ディレクトリ）を返す
+// アーカイブ内の画像はアーカイブ自体の親ディレクトリを監視する
+pub fn watched_dirs(dropped_paths: &[String], image_paths: &[String]) -> BTreeSet<PathBuf> {
+    let parents = image_paths.iter().filter_map(|path| {
+        let file_path = archive::split_entry_path(path).map_or(path.as_str(), |(a, _)| a);
+        Path::new(file_path).parent().map(Path::to_path_buf)
+    });
+    dropped_paths
+        .iter()
+        .map(PathBuf::from)
+        .filter(|path| path.is_dir())
+        .chain(parents)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect()
+}
+
+fn classify_events(events: &[DebouncedEvent]) -> Vec<FileChange> {
+    events
+        .iter()
+        .flat_map(|event| classify_event(&event.kind, &event.paths))
+        .collect()
+}
+
+// notifyのイベントを追加・削除・名前変更のいずれかに分類する
+// 内容の変更やアクセスなど、画像一覧に影響しないイベントは無視する
+fn classify_event(kind: &EventKind, paths: &[PathBuf]) -> Vec<FileChange> {
+    let created = || paths.iter().cloned().map(FileChange::Created).collect();
+    let removed = || paths.iter().cloned().map(FileChange::Removed).collect();
+    match kind {
+        EventKind::Create(_) => created(),
+        EventKind::Remove(_) => removed(),
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => match paths {
+            [from, to] => vec![FileChange::Renamed {
+                from: from.clone(),
+                to: to.clone(),
+            }],
+            _ => Vec::new(),
+        },
+        // 監視外のディレクトリとの間の移動は、片方だけのイベントになる
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => removed(),
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => created(),
+        // どちら側か分からない名前の変更（macOS等）は、ファイルが存在するかどうかで判断する
+        EventKind::Modify(ModifyKind::Name(_)) => paths
+            .iter()
+            .map(|path| {
+                if path.exists() {
+                    FileChange::Created(path.clone())
+                } else {
+                    FileChange::Removed(path.clone())
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// 変更を画像一覧に反映して、実際に反映した変更を返す
+// list_images は追加・名前変更後のファイルから一覧に載せる画像パスを返す
+// （画像でなければ空、アーカイブの場合は中の画像のパス）
+// 追加された画像は末尾に追加し、名前変更された画像は一覧の同じ位置のまま置き換える
+// アーカイブが削除・名前変更された場合は中の画像もあわせて削除・名前変更する
+pub fn apply_changes(
+    paths: &mut Vec<String>,
+    changes: Vec<FileChange>,
+    list_images: impl Fn(&Path) -> Vec<String>,
+) -> AppliedChanges {
+    let mut applied = AppliedChanges::default();
+    for change in changes {
+        match change {
+            FileChange::Created(path) => {
+                add_images(paths, list_images(&path), &mut applied);
+            }
+            FileChange::Removed(path) => {
+                remove_images(paths, &path_string(&path), &mut applied);
+            }
+            FileChange::Renamed { from, to } => {
+                let from = path_string(&from);
+                let to_string = path_string(&to);
+                let images = list_images(&to);
+                let image_set: HashSet<&str> = images.iter().map(String::as_str).collect();
+                let existing: HashSet<String> = paths.iter().cloned().collect();
+                for path in paths.iter_mut() {
+                    let Some(renamed) = renamed_path(path, &from, &to_string) else {
+                        continue;
+                    };
+                    // 既存の画像を上書きした場合は、変更後のパスが既に一覧にあるので置き換えない
+                    if image_set.contains(renamed.as_str()) && !existing.contains(&renamed) {
+                        applied
+                            .renamed
+                            .push((std::mem::replace(path, renamed.clone()), renamed));
+                    }
+                }
+                // 名前変更で画像として扱えなくなったもの（拡張子の変更等）や上書きしたものは削除する
+                remove_images(paths, &from, &mut applied);
+                add_images(paths, images, &mut applied);
+            }
+        }
+    }
+    applied
+}
+
+fn add_images(paths: &mut Vec<String>, images: Vec<String>, applied: &mut AppliedChanges) {
+    for image in images {
+        if !paths.contains(&image) {
+            paths.push(image.clone());
+            applied.added.push(image);
+        }
+    }
+}
+
+// パス（アーカイブの場合は中の画像も）を一覧から削除する
+fn remove_images(paths: &mut Vec<String>, removed_path: &str, applied: &mut AppliedChanges) {
+    paths.retain(|path| {
+        if is_same_or_entry_of(path, removed_path) {
+            applied.removed.push(path.clone());
+            false
+        } else {
+            true
+        }
+    });
+}
+
+// pathがfromそのもの、またはfromのアーカイブ内の画像の場合は、名前変更後のパスを返す
+fn renamed_path(path: &str, from: &str, to: &str) -> Option<String> {
+    if path == from {
+        return Some(to.to_string());
+    }
+    match archive::split_entry_path(path) {
+        Some((archive_path, entry_name)) if archive_path == from => {
+            Some(archive::entry_path(to, entry_name))
+        }
+        _ => None,
+    }
+}
+
+fn is_same_or_entry_of(path: &str, file_path: &str) -> bool {
+    path == file_path
+        || archive::split_entry_path(path)
+            .is_some_and(|(archive_path, _)| archive_path == file_path)
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify_debouncer_full::notify::event::{CreateKind, DataChange, RemoveKind};
+
+    fn strings(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|s| s.to_string()).collect()
+    }
+
+    // 拡張子が .png のファイルとテスト用のアーカイブ（中身は固定）を画像とみなす
+    fn list_test_images(path: &Path) -> Vec<String> {
+        let path = path.to_str().unwrap();
+        if path.ends_with(".png") {
+            vec![path.to_string()]
+        } else if path.ends_with(".zip") {
+            vec![
+                archive::entry_path(path, "1.png"),
+                archive::entry_path(path, "2.png"),
+            ]
+        } else {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_classify_event() {
+        let a = PathBuf::from("/p/a.png");
+        let b = PathBuf::from("/p/b.png");
+
+        assert_eq!(
+            classify_event(
+                &EventKind::Create(CreateKind::File),
+                std::slice::from_ref(&a)
+            ),
+            vec![FileChange::Created(a.clone())]
+        );
+        assert_eq!(
+            classify_event(
+                &EventKind::Remove(RemoveKind::Any),
+                std::slice::from_ref(&a)
+            ),
+            vec![FileChange::Removed(a.clone())]
+        );
+        assert_eq!(
+            classify_event(
+                &EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &[a.clone(), b.clone()]
+            ),
+            vec![FileChange::Renamed {
+                from: a.clone(),
+                to: b.clone()
+            }]
+        );
+        assert_eq!(
+            classify_event(
+                &EventKind::Modify(ModifyKind::Name(RenameMode::From)),
+                std::slice::from_ref(&a)
+            ),
+            vec![FileChange::Removed(a.clone())]
+        );
+        assert!(classify_event(
+            &EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+            &[a]
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_apply_created_and_removed() {
+        let mut paths = strings(&["/p/a.png", "/p/c.zip!/1.png"]);
+
+        let applied = apply_changes(
+            &mut paths,
+            vec![
+                FileChange::Created("/p/b.png".into()),
+                // 既に一覧にある画像と画像以外のファイルは追加しない
+                FileChange::Created("/p/a.png".into()),
+                FileChange::Created("/p/notes.txt".into()),
+                FileChange::Removed("/p/c.zip".into()),
+            ],
+            list_test_images,
+        );
+
+        assert_eq!(paths, strings(&["/p/a.png", "/p/b.png"]));
+        assert_eq!(
+            applied,
+            AppliedChanges {
+                added: strings(&["/p/b.png"]),
+                removed: strings(&["/p/c.zip!/1.png"]),
+                renamed: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_apply_renamed() {
+        let mut paths = strings(&["/p/a.png", "/p/b.png", "/p/c.zip!/1.png", "/p/c.zip!/2.png"]);
+
+        let applied = apply_changes(
+            &mut paths,
+            vec![
+                FileChange::Renamed {
+                    from: "/p/a.png".into(),
+                    to: "/p/z.png".into(),
+                },
+                FileChange::Renamed {
+                    from: "/p/c.zip".into(),
+                    to: "/p/d.zip".into(),
+                },
+                // 画像でなくなる名前変更は削除として扱う
+                FileChange::Renamed {
+                    from: "/p/b.png".into(),
+                    to: "/p/b.png.bak".into(),
+                },
+            ],
+            list_test_images,
+        );
+
+        assert_eq!(
+            paths,
+            strings(&["/p/z.png", "/p/d.zip!/1.png", "/p/d.zip!/2.png"])
+        );
+        assert_eq!(
+            applied,
+            AppliedChanges {
+                added: Vec::new(),
+                removed: strings(&["/p/b.png"]),
+                renamed: vec![
+                    ("/p/a.png".to_string(), "/p/z.png".to_string()),
+                    ("/p/c.zip!/1.png".to_string(), "/p/d.zip!/1.png".to_string()),
+                    ("/p/c.zip!/2.png".to_string(), "/p/d.zip!/2.png".to_string()),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_apply_renamed_from_untracked_file() {
+        // 一時ファイルから名前変更して保存された場合など、変更前が一覧にない場合は追加として扱う
+        let mut paths = strings(&["/p/old.png", "/p/a.png"]);
+
+        let applied = apply_changes(
+            &mut paths,
+            vec![
+                FileChange::Renamed {
+                    from: "/p/a.png.tmp".into(),
+                    to: "/p/a.png".into(),
+                },
+                FileChange::Renamed {
+                    from: "/p/new.tmp".into(),
+                    to: "/p/new.png".into(),
+                },
+                // 一覧にある画像で上書きした場合は変更前の画像を削除する
+                FileChange::Renamed {
+                    from: "/p/old.png".into(),
+                    to: "/p/a.png".into(),
+                },
+            ],
+            list_test_images,
+        );
+
+        assert_eq!(paths, strings(&["/p/a.png", "/p/new.png"]));
+        assert_eq!(applied.added, strings(&["/p/new.png"]));
+        assert_eq!(applied.removed, strings(&["/p/old.png"]));
+        assert!(applied.renamed.is_empty());
+    }
+
+    #[test]
+    fn test_watched_dirs() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dropped = temp_dir.path().to_str().unwrap().to_string();
+
+        let dirs = watched_dirs(
+            &[dropped.clone(), "/missing/dir".to_string()],
+            &strings(&["/p/a.png", "/p/b.png", "/q/c.zip!/sub/1.png"]),
+        );
+
+        assert_eq!(
+            dirs,
+            BTreeSet::from([
+                PathBuf::from(dropped),
+                PathBuf::from("/p"),
+                PathBuf::from("/q")
+            ])
+        );
+    }
+}
@@ -0,0 +1,72 @@
+/**
+ * コマンドが返すエラーに関する型と関数をまとめたモジュール
+ */
+
+/**
+ * エラーの種類
+ */
+export type ErrorKind =
+  | 'notFound'
+  | 'notAFile'
+  | 'notADirectory'
+  | 'permissionDenied'
+  | 'unauthorized'
+  | 'invalidInput'
+  | 'alreadyExists'
+  | 'unsupportedFormat'
+  | 'invalidData'
+  | 'io'
+  | 'other';
+
+/**
+ * drop・delete_file・get_file_info・load_tags_in_dir・save_tags などのコマンドが返すエラー
+ *
+ * messageはログ用の英語のメッセージなので、画面にはkindに応じたメッセージを表示します
+ */
+export interface CommandError {
+  kind: ErrorKind;
+  message: string;
+  /** エラーの対象のパス */
+  path: string | null;
+  /** 元になったエラー（I/Oエラーなど）のメッセージ */
+  source: string | null;
+}
+
+const ERROR_KIND_MESSAGES: Record<ErrorKind, string> = {
+  notFound: 'ファイルが見つかりません',
+  notAFile: 'ファイルではありません',
+  notADirectory: 'フォルダではありません',
+  permissionDenied: 'アクセスが拒否されました',
+  unauthorized: '一覧にない画像は操作できません',
+  invalidInput: '入力が正しくありません',
+  alreadyExists: '同じ名前のファイルが既にあります',
+  unsupportedFormat: '対応していないファイル形式です',
+  invalidData: 'ファイルの内容が壊れています',
+  io: '読み書きに失敗しました',
+  other: '',
+};
+
+export function isCommandError(error: unknown): error is CommandError {
+  return (
+    typeof error === 'object' &&
+    error !== null &&
+    'kind' in error &&
+    typeof error.kind === 'string' &&
+    error.kind in ERROR_KIND_MESSAGES
+  );
+}
+
+/**
+ * エラーを画面に表示するメッセージにします
+ *
+ * @param error コマンドが投げたエラー
+ * @param summary 何に失敗したかを表すメッセージ（例: 「タグの保存に失敗しました」）
+ * @returns CommandErrorの場合は summary に理由を付けたもの、それ以外は summary
+ */
+export function describeError(error: unknown, summary: string): string {
+  if (!isCommandError(error)) {
+    return summary;
+  }
+  const reason = ERROR_KIND_MESSAGES[error.kind];
+  return reason ? `${summary}: ${reason}` : summary;
+}
@@ -1,26 +1,360 @@
-import { invoke } from '@tauri-apps/api/core';
+import { convertFileSrc, invoke } from '@tauri-apps/api/core';
 
 /**
  * ファイル操作に関するラッパーをまとめたモジュール
  */
 
+/**
+ * フォルダ走査のオプション（省略したフィールドはバックエンドのデフォルト値）
+ */
+export interface ScanOptions {
+  maxDepth?: number | null;
+  includePatterns?: string[];
+  excludePatterns?: string[];
+  includeHidden?: boolean;
+  followSymlinks?: boolean;
+}
+
+/**
+ * 画像一覧の並び順
+ */
+export type SortOrder =
+  | 'natural'
+  | 'modifiedTime'
+  | 'createdTime'
+  | 'fileSize'
+  | 'dimensions'
+  | 'exifDate';
+
+/**
+ * 中の画像をフォルダと同様に扱うアーカイブの拡張子
+ */
+const ARCHIVE_EXTENSIONS = ['zip', 'cbz', '7z', 'cb7', 'rar', 'cbr'];
+
+/**
+ * アーカイブのパスとアーカイブ内のエントリ名の区切り
+ * アーカイブ内の画像は "{アーカイブのパス}!/{エントリ名}" というパスで扱われます
+ */
+const ARCHIVE_ENTRY_SEPARATOR = '!/';
+
+/**
+ * アーカイブ内の画像のパスを、アーカイブのパスとエントリ名に分割します
+ *
+ * @returns アーカイブ内の画像のパスでない場合は null
+ */
+export function splitArchiveEntryPath(
+  path: string
+): { archivePath: string; entryName: string } | null {
+  let index = path.indexOf(ARCHIVE_ENTRY_SEPARATOR);
+  while (index !== -1) {
+    const archivePath = path.slice(0, index);
+    const entryName = path.slice(index + ARCHIVE_ENTRY_SEPARATOR.length);
+    const ext = archivePath.split('.').pop()?.toLowerCase() ?? '';
+    if (ARCHIVE_EXTENSIONS.includes(ext) && entryName !== '') {
+      return { archivePath, entryName };
+    }
+    index = path.indexOf(ARCHIVE_ENTRY_SEPARATOR, index + 1);
+  }
+  return null;
+}
+
+/**
+ * 画像ファイルを表示するためのURLを返します
+ *
+ * すべての画像を image プロトコルで読み込みます。バックエンドがファイルの内容から形式を判定し、
+ * Webviewで表示できない形式は変換し、アーカイブ内の画像は展開して返します
+ * （拡張子がない・実際の形式と違う画像も表示できるように、拡張子では振り分けません）
+ */
+export function imageSrc(path: string): string {
+  return convertFileSrc(path, 'image');
+}
+
+/**
+ * ドロップしたフォルダの走査結果を分割して送る際の、1チャンクあたりの画像数
+ *
+ * 大量の画像があるフォルダでも、走査が終わるのを待たずに表示を始められるようにします
+ */
+export const DROP_CHUNK_SIZE = 500;
+
 /**
  * ドラッグ＆ドロップされたファイルパスを送信します
+ *
+ * アーカイブ（ZIP・CBZ・7z・CB7、rar フィーチャーでビルドした場合はRAR・CBRも）は
+ * フォルダと同様に中の画像が一覧に追加されます
+ * rar フィーチャーなしでビルドした場合、RAR・CBRが含まれていると unsupportedFormat のエラーになります
+ * chunkSizeを指定すると、走査結果は new-images-chunk イベントで分割して送られ、
+ * 完了時に new-images-complete イベントが送られます
+ * newSessionをtrueにすると、新しいビューアウィンドウで開きます
+ *
+ * @throws {import('./errors').CommandError} パスやスキャンオプションが不正な場合、
+ *   対応していないアーカイブが含まれる場合
  */
-export async function dropPaths(paths: string[]): Promise<void> {
-  return invoke('drop', { paths });
+export async function dropPaths(
+  paths: string[],
+  options?: ScanOptions,
+  sortOrder?: SortOrder,
+  chunkSize?: number,
+  newSession?: boolean
+): Promise<void> {
+  return invoke('drop', { paths, options, sortOrder, chunkSize, newSession });
 }
 
 /**
  * 以前に読み込んだ画像パス一覧を取得します
  */
-export async function getPrevImagePaths(): Promise<{ id: number; paths: string[] }> {
+export async function getPrevImagePaths(): Promise<{
+  id: number;
+  paths: string[];
+  sortOrder: SortOrder;
+}> {
   return invoke('get_prev_image_paths', {});
 }
 
+/**
+ * 分割して送られる走査結果のペイロード（new-images-chunk イベント）
+ *
+ * offsetはバックエンドの画像一覧でのpathsの先頭の位置です
+ */
+export interface ImagePathsChunk {
+  id: number;
+  offset: number;
+  paths: string[];
+}
+
+/**
+ * 分割して送る走査が完了したときのペイロード（new-images-complete イベント）
+ *
+ * reorderedがtrueの場合は並び順に従って並び替えられているので、
+ * getPrevImagePaths で並び替え後の一覧を取得してください
+ */
+export interface ImagePathsCompletion {
+  id: number;
+  total: number;
+  reordered: boolean;
+}
+
+/**
+ * ディレクトリの監視で検知した画像の追加・削除のペイロード
+ * （images-added / images-removed イベント）
+ *
+ * 追加された画像はバックエンドの画像一覧の末尾に追加されています
+ */
+export interface ImagePathsDiff {
+  id: number;
+  paths: string[];
+}
+
+/**
+ * ディレクトリの監視で検知した画像の名前変更のペイロード（images-renamed イベント）
+ *
+ * 名前変更された画像はバックエンドの画像一覧の同じ位置のままパスが置き換えられています
+ */
+export interface ImagePathsRenamed {
+  id: number;
+  renames: { from: string; to: string }[];
+}
+
 /**
  * 指定したファイルを削除します
+ *
+ * @throws {import('./errors').CommandError} 一覧にないパスの場合や、ゴミ箱に移動できない場合
  */
 export async function deleteFile(path: string): Promise<void> {
   return invoke('delete_file', { path });
 }
+
+/**
+ * まとめて削除・移動・名前変更した各ファイルの結果（タグの移行では各ディレクトリの結果）
+ */
+export interface FileOperationResult {
+  path: string;
+  /** 移動・名前変更後のパス（成功した場合のみ） */
+  destination: string | null;
+  /**
+   * 失敗した場合のエラーメッセージ
+   * 移動・名前変更した後にタグを付け替えられなかった場合は、destination とともに設定されます
+   */
+  error: string | null;
+}
+
+/**
+ * 指定したファイルをまとめてゴミ箱に移動します
+ *
+ * 一覧にないパスが1つでも含まれる場合は何も削除せずにエラーになります
+ *
+ * @returns 渡した順の各ファイルの結果
+ * @throws {import('./errors').CommandError} 一覧にないパスが含まれる場合
+ */
+export async function deleteFiles(paths: string[]): Promise<FileOperationResult[]> {
+  return invoke('delete_files', { paths });
+}
+
+/**
+ * 指定したファイルをまとめて destDir に移動します
+ *
+ * 移動先に同名のファイルがあるものは上書きせずに失敗になります
+ *
+ * @returns 渡した順の各ファイルの結果
+ * @throws {import('./errors').CommandError} 一覧にないパスが含まれる場合や、移動先がフォルダでない場合
+ */
+export async function moveFiles(
+  paths: string[],
+  destDir: string
+): Promise<FileOperationResult[]> {
+  return invoke('move_files', { paths, destDir });
+}
+
+/**
+ * 画像を同じフォルダ内で newName に名前変更します
+ *
+ * 画像のタグは新しい名前に付け替えられ、バックエンドの画像一覧のパスも置き換えられます
+ * 同名のファイルがある場合や、画像の拡張子でない名前の場合はエラーになります
+ *
+ * @returns 名前変更後のパス
+ * @throws {import('./errors').CommandError} 同名のファイルがある場合は kind が alreadyExists
+ */
+export async function renameFile(path: string, newName: string): Promise<string> {
+  return invoke('rename_file', { path, newName });
+}
+
+/**
+ * 画像をまとめてテンプレートに従った名前に変更します
+ *
+ * テンプレートでは次のプレースホルダが使えます（"{{" "}}" は波括弧そのもの）
+ * - {name}: 元のファイル名（拡張子を除く）
+ * - {ext}: 元の拡張子
+ * - {seq} / {seq:04}: paths の順に1から始まる連番（指定した桁数まで0で埋める）
+ * - {exif_date} / {exif_date:%Y%m%d}: EXIFの撮影日時（%Y %m %d %H %M %S が使える）
+ * - {tags} / {tags:-}: 画像のタグ（省略時は "_" で区切る）
+ *
+ * dryRun を true にすると名前変更せずに、名前変更後のパスと衝突などのエラーを返します
+ * dryRun が false の場合、エラーになる画像が1つでもあれば何も名前変更せずにエラーになります
+ * 名前変更した画像のタグは新しい名前に付け替えられ、バックエンドの画像一覧のパスも置き換えられます
+ *
+ * @returns 渡した順の各ファイルの結果（destination が名前変更後のパス）
+ * @throws {import('./errors').CommandError} テンプレートが不正な場合や、エラーになる画像がある場合
+ */
+export async function batchRename(
+  paths: string[],
+  template: string,
+  dryRun: boolean
+): Promise<FileOperationResult[]> {
+  return invoke('batch_rename', { paths, template, dryRun });
+}
+
+/**
+ * 振り分け先に同名のファイルがある場合の扱い
+ *
+ * - suffix: "name (1).jpg" のように連番を付けた名前にする
+ * - overwrite: 既存のファイルを上書きする
+ * - skip: 何もしない
+ */
+export type CollisionPolicy = 'suffix' | 'overwrite' | 'skip';
+
+/**
+ * 振り分けの方法
+ */
+export type TransferMode = 'move' | 'copy';
+
+/**
+ * 振り分け先（名前を付けたフォルダ）
+ */
+export interface SortBin {
+  name: string;
+  /** 振り分け先のフォルダ（絶対パス） */
+  dir: string;
+  collision: CollisionPolicy;
+}
+
+/**
+ * 保存されている振り分け先の一覧を取得します
+ */
+export async function getSortBins(): Promise<SortBin[]> {
+  return invoke('get_sort_bins', {});
+}
+
+/**
+ * 振り分け先の一覧を保存します
+ *
+ * @throws {import('./errors').CommandError} 名前が空・重複している場合や、フォルダが絶対パスでない場合
+ */
+export async function setSortBins(bins: SortBin[]): Promise<void> {
+  return invoke('set_sort_bins', { bins });
+}
+
+/**
+ * 画像を名前で指定した振り分け先のフォルダに移動またはコピーします（省略時は移動）
+ *
+ * 画像のタグは振り分け先に引き継がれ、移動の場合はバックエンドの画像一覧のパスも置き換えられます
+ *
+ * @returns 振り分け後のパス。同名のファイルがあってスキップした場合は null
+ * @throws {import('./errors').CommandError} 振り分け先が設定されていない場合や、移動・コピーできない場合
+ */
+export async function sendToSortBin(
+  path: string,
+  binName: string,
+  mode?: TransferMode
+): Promise<string | null> {
+  return invoke('send_to_sort_bin', { path, binName, mode });
+}
+
+/**
+ * 削除を取り消して復元した画像と、バックエンドの画像一覧での位置
+ */
+export interface RestoredImage {
+  path: string;
+  index: number;
+}
+
+/**
+ * 削除を取り消して、ゴミ箱から元の場所に復元します
+ *
+ * 最後に削除したものから順に count 件（省略時は1件）を復元します
+ * （macOSでは未対応のためエラーになります）
+ *
+ * @returns 復元した画像（復元した順）。取り消す削除がなければ空配列
+ * @throws {import('./errors').CommandError} 1件も復元できなかった場合
+ */
+export async function undoDelete(count?: number): Promise<RestoredImage[]> {
+  return invoke('undo_delete', { count });
+}
+
+/**
+ * 画像のサムネイルを生成し、キャッシュされたサムネイルファイルのパスを取得します
+ *
+ * @param path 画像ファイルのパス
+ * @param maxEdge サムネイルの長辺のピクセル数（省略時はバックエンドのデフォルト値）
+ * @returns サムネイルファイルのパス（convertFileSrcで表示用のURLに変換して利用する）
+ */
+export async function getThumbnail(path: string, maxEdge?: number): Promise<string> {
+  return invoke('get_thumbnail', { path, maxEdge });
+}
+
+/**
+ * 画像のメタデータ（EXIF・XMP・IPTC）
+ * 取得できなかったフィールドは null になります
+ */
+export interface ImageMetadata {
+  captureDate: string | null;
+  cameraMake: string | null;
+  cameraModel: string | null;
+  lensModel: string | null;
+  exposureTime: string | null;
+  fNumber: number | null;
+  iso: number | null;
+  focalLength: number | null;
+  focalLengthIn35mm: number | null;
+  gps: { latitude: number; longitude: number; altitude: number | null } | null;
+  orientation: number | null;
+  rating: number | null;
+  keywords: string[] | null;
+  caption: string | null;
+}
+
+/**
+ * 画像のメタデータを取得します
+ * アーカイブ内の画像は "{アーカイブのパス}!/{エントリ名}" のパスで指定します
+ */
+export async function getImageMetadata(filePath: string): Promise<ImageMetadata> {
+  return invoke('get_image_metadata', { filePath });
+}
@@ -1,25 +1,146 @@
 import { invoke } from '@tauri-apps/api/core';
+import type { FileOperationResult } from './files';
 
 /**
  * タグ操作に関するラッパーをまとめたモジュール
  */
 
+/**
+ * いずれかのウィンドウでタグを保存・付け替えたときや、タグファイルがアプリの外で変更されたことを
+ * 検知したときに全ウィンドウへ送られるペイロード（tags-changed イベント）
+ *
+ * dirPathは正規化されたディレクトリのパス（末尾の区切り文字なし）で、
+ * tagsは変更後のディレクトリのすべてのタグ情報です
+ */
+export interface TagsChanged {
+  dirPath: string;
+  tags: Record<string, string[]>;
+  /** タグファイルがアプリの外（エディタや同期ツールなど）で変更された場合にtrue */
+  external: boolean;
+}
+
 /**
  * 指定されたディレクトリのタグ情報をロードします
  *
  * @param dirPath ディレクトリのパス
  * @returns ファイル名 -> タグ配列 のマップ
+ * @throws {import('./errors').CommandError} タグファイルを読み込めない場合
  */
 export async function loadTagsInDir(dirPath: string): Promise<Record<string, string[]>> {
   return invoke('load_tags_in_dir', { dirPath });
 }
 
+/**
+ * 指定されたディレクトリのタグファイルを読み直します
+ *
+ * loadTagsInDirもタグファイルの更新日時・サイズが変わっていれば読み直しますが、
+ * こちらは変わっていなくても必ず読み直します
+ *
+ * @param dirPath ディレクトリのパス
+ * @returns ファイル名 -> タグ配列 のマップ
+ * @throws {import('./errors').CommandError} タグファイルを読み込めない場合
+ */
+export async function reloadTagsInDir(dirPath: string): Promise<Record<string, string[]>> {
+  return invoke('reload_tags_in_dir', { dirPath });
+}
+
 /**
  * 指定された画像ファイルのタグ情報を保存します
  *
  * @param imgPath 画像ファイルのパス
  * @param tags タグの配列
+ * @throws {import('./errors').CommandError} タグが不正な場合や、タグファイルに書き込めない場合
  */
 export async function saveTags(imgPath: string, tags: string[]): Promise<void> {
   return invoke('save_tags', { imgPath, tags });
 }
+
+/**
+ * タグの保存先
+ *
+ * - file: ディレクトリ毎のタグファイル（IMAGE_TAG）
+ * - database: アプリのデータディレクトリのデータベース（画像のあるディレクトリにファイルを作らない）
+ */
+export type TagBackend = 'file' | 'database';
+
+/**
+ * 現在のタグの保存先を取得します
+ */
+export async function getTagBackend(): Promise<TagBackend> {
+  return invoke('get_tag_backend');
+}
+
+/**
+ * タグの保存先を変更します（次回起動時も使われます）
+ *
+ * 保存済みのタグは移行されないので、必要に応じて migrateTags で移行してください
+ * 読み込み済みのタグのキャッシュは呼び出し側でクリアしてください
+ *
+ * @throws {import('./errors').CommandError} データベースを開けない場合
+ */
+export async function setTagBackend(backend: TagBackend): Promise<void> {
+  return invoke('set_tag_backend', { backend });
+}
+
+/**
+ * 指定したディレクトリのタグを from の保存先から to の保存先にコピーします
+ *
+ * 移行元のタグは消さず、移行先にだけあるタグも残します（両方にある画像は移行元のタグで上書きします）
+ *
+ * @returns 渡した順の各ディレクトリの結果
+ * @throws {import('./errors').CommandError} from と to が同じ場合や、保存先を開けない場合
+ */
+export async function migrateTags(
+  from: TagBackend,
+  to: TagBackend,
+  dirPaths: string[]
+): Promise<FileOperationResult[]> {
+  return invoke('migrate_tags', { from, to, dirPaths });
+}
+
+/**
+ * タグでの検索条件
+ */
+export interface TagQuery {
+  tags: string[];
+  /** trueの場合はすべてのタグが付いた画像、falseの場合はいずれかのタグが付いた画像（既定） */
+  matchAll?: boolean;
+}
+
+/**
+ * タグのあるディレクトリの一覧から、条件に一致するタグの付いた画像を検索します
+ *
+ * 検索するのはタグを読み込んだ・保存したことがあるディレクトリと、registerTagDirsで登録した
+ * ディレクトリです。結果の画像のパスは dropPaths に渡して新しい画像リストとして開けます
+ *
+ * @returns 画像のパスの一覧
+ * @throws {import('./errors').CommandError} タグが指定されていない・不正な場合
+ */
+export async function searchByTags(query: TagQuery): Promise<string[]> {
+  return invoke('search_by_tags', { query });
+}
+
+/**
+ * タグで検索するディレクトリの一覧を取得します
+ */
+export async function getTagDirs(): Promise<string[]> {
+  return invoke('get_tag_dirs');
+}
+
+/**
+ * まだ開いていないディレクトリのタグも検索できるように、検索するディレクトリの一覧に追加します
+ *
+ * @returns 渡した順の各ディレクトリの結果
+ */
+export async function registerTagDirs(dirPaths: string[]): Promise<FileOperationResult[]> {
+  return invoke('register_tag_dirs', { dirPaths });
+}
+
+/**
+ * 検索するディレクトリの一覧から削除します（タグ自体は消えません）
+ *
+ * @throws {import('./errors').CommandError} 一覧を保存できない場合
+ */
+export async function unregisterTagDirs(dirPaths: string[]): Promise<void> {
+  return invoke('unregister_tag_dirs', { dirPaths });
+}
@@ -0,0 +1,79 @@
+import { describe, it, expect, beforeEach, vi } from 'vitest';
+import { Controler } from '../controller';
+import { ImageInfoManager } from '../image-info-manager.svelte';
+import { ImageInfo } from '../image-info.svelte';
+import { DialogController } from '../dialog-controller.svelte';
+import { GotoDialogController } from '../goto-dialog-controller.svelte';
+import { FilterDialogController } from '../filter-dialog-controller.svelte';
+import { FileController } from '../file-controller';
+import { ToastController } from '../toast-controller.svelte';
+import { ViewerController } from '../viewer-controller.svelte';
+import { EditModeController } from '../edit-mode-controller.svelte';
+
+describe('Controller - Sort Bins', () => {
+  let controller: Controler;
+  let imageInfoManager: ImageInfoManager;
+  let fileController: FileController;
+  let toastController: ToastController;
+
+  beforeEach(async () => {
+    imageInfoManager = new ImageInfoManager();
+    fileController = new FileController();
+    toastController = new ToastController();
+
+    controller = new Controler(
+      imageInfoManager,
+      new DialogController(),
+      fileController,
+      toastController,
+      new ViewerController(),
+      new GotoDialogController(),
+      new FilterDialogController(imageInfoManager),
+      new EditModeController()
+    );
+
+    await imageInfoManager.addImages([new ImageInfo('/p/a.png'), new ImageInfo('/p/b.png')]);
+    vi.spyOn(fileController, 'getSortBins').mockResolvedValue([
+      { name: 'keep', dir: '/keep', collision: 'suffix' },
+      { name: 'reject', dir: '/reject', collision: 'skip' },
+    ]);
+    vi.spyOn(toastController, 'showToast');
+  });
+
+  it('should move the current image to the bin of the pressed number', async () => {
+    const send = vi.spyOn(fileController, 'sendToSortBin').mockResolvedValue('/reject/a.png');
+
+    controller.operateByKey('2');
+
+    await vi.waitFor(() => expect(toastController.showToast).toHaveBeenCalled());
+    expect(send).toHaveBeenCalledWith('/p/a.png', 'reject', 'move');
+    expect(imageInfoManager.getList().map(image => image.path)).toEqual([
+      '/reject/a.png',
+      '/p/b.png',
+    ]);
+    expect(toastController.showToast).toHaveBeenCalledWith('rejectに移動しました');
+  });
+
+  it('should copy the current image with ctrl and keep the list', async () => {
+    const send = vi.spyOn(fileController, 'sendToSortBin').mockResolvedValue('/keep/a.png');
+
+    controller.downModifierKey('ctrl');
+    controller.operateByKey('1');
+
+    await vi.waitFor(() => expect(toastController.showToast).toHaveBeenCalled());
+    expect(send).toHaveBeenCalledWith('/p/a.png', 'keep', 'copy');
+    expect(imageInfoManager.getList().map(image => image.path)).toEqual(['/p/a.png', '/p/b.png']);
+    expect(toastController.showToast).toHaveBeenCalledWith('keepにコピーしました');
+  });
+
+  it('should show a toast when the bin is not configured', async () => {
+    const send = vi.spyOn(fileController, 'sendToSortBin');
+
+    controller.operateByKey('3');
+
+    await vi.waitFor(() =>
+      expect(toastController.showToast).toHaveBeenCalledWith('振り分け先3は設定されていません')
+    );
+    expect(send).not.toHaveBeenCalled();
+  });
+});
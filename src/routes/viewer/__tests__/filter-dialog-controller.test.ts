@@ -3,6 +3,18 @@ import { FilterDialogController } from '../filter-dialog-controller.svelte';
 import { ImageInfoManager } from '../image-info-manager.svelte';
 import { TagController } from '../tag-controller.svelte';
 import { ToastController } from '../toast-controller.svelte';
+import * as filesApi from '@/lib/api/files';
+import * as tagsApi from '@/lib/api/tags';
+
+// API関数をモック
+vi.mock('@/lib/api/tags', () => ({
+  loadTagsInDir: vi.fn(),
+  saveTags: vi.fn(),
+  searchByTags: vi.fn(),
+}));
+vi.mock('@/lib/api/files', () => ({
+  dropPaths: vi.fn(),
+}));
 
 describe('FilterDialogController', () => {
   let controller: FilterDialogController;
@@ -127,6 +139,68 @@ describe('FilterDialogController', () => {
     });
   });
 
+  describe('searchAllDirs', () => {
+    beforeEach(async () => {
+      controller = new FilterDialogController(imageInfoManager, toastController);
+      vi.spyOn(toastController, 'showToast');
+      vi.mocked(tagsApi.searchByTags).mockReset();
+      vi.mocked(filesApi.dropPaths).mockReset();
+      await controller.showDialog();
+    });
+
+    it('should open search results in a new window', async () => {
+      vi.mocked(tagsApi.searchByTags).mockResolvedValue(['/a/1.jpg', '/b/2.jpg']);
+      controller.toggleTag('tag1');
+      controller.toggleTag('tag2');
+
+      await controller.searchAllDirs();
+
+      expect(tagsApi.searchByTags).toHaveBeenCalledWith({ tags: ['tag1', 'tag2'] });
+      expect(filesApi.dropPaths).toHaveBeenCalledWith(
+        ['/a/1.jpg', '/b/2.jpg'],
+        undefined,
+        undefined,
+        undefined,
+        true
+      );
+      expect(controller.isShow()).toBe(false);
+    });
+
+    it('should do nothing when no tags are selected', async () => {
+      await controller.searchAllDirs();
+
+      expect(tagsApi.searchByTags).not.toHaveBeenCalled();
+      expect(controller.isShow()).toBe(true);
+    });
+
+    it('should show toast when nothing is found', async () => {
+      vi.mocked(tagsApi.searchByTags).mockResolvedValue([]);
+      controller.toggleTag('tag1');
+
+      await controller.searchAllDirs();
+
+      expect(filesApi.dropPaths).not.toHaveBeenCalled();
+      expect(toastController.showToast).toHaveBeenCalledWith(
+        'タグの付いた画像が見つかりませんでした'
+      );
+    });
+
+    it('should show toast when search fails', async () => {
+      vi.mocked(tagsApi.searchByTags).mockRejectedValue({
+        kind: 'invalidInput',
+        message: 'Invalid tag',
+      });
+      controller.toggleTag('tag1');
+
+      await controller.searchAllDirs();
+
+      expect(filesApi.dropPaths).not.toHaveBeenCalled();
+      expect(toastController.showToast).toHaveBeenCalledWith(
+        expect.stringContaining('タグでの検索に失敗しました')
+      );
+    });
+  });
+
   describe('error handling', () => {
     it('should handle getAvailableTags error gracefully', async () => {
       vi.mocked(imageInfoManager.getAvailableTags).mockRejectedValue(new Error('API Error'));
@@ -10,11 +10,33 @@ vi.mock('$lib/api/tags', () => ({
   }),
 }));
 
+vi.mock('$lib/api/files', () => ({
+  getImageMetadata: vi.fn().mockResolvedValue({
+    captureDate: '2024-05-01T10:20:30',
+    cameraMake: 'Canon',
+    cameraModel: 'Canon EOS R5',
+    lensModel: null,
+    exposureTime: '1/250',
+    fNumber: 2.8,
+    iso: 400,
+    focalLength: null,
+    focalLengthIn35mm: null,
+    gps: null,
+    orientation: 1,
+    rating: null,
+    keywords: null,
+    caption: null,
+  }),
+}));
+
 vi.mock('@tauri-apps/api/core', () => ({
   invoke: vi.fn().mockResolvedValue({
     size: 1024000,
     width: 1920,
     height: 1080,
+    displayWidth: 1920,
+    displayHeight: 1080,
+    orientation: 1,
   }),
 }));
 
@@ -45,6 +67,20 @@ describe('ImageInfoDisplay', () => {
     expect(overlay).toBeNull();
   });
 
+  test('メタデータが表示される', async () => {
+    render(ImageInfoDisplay, {
+      props: {
+        show: true,
+        imageInfo: mockImageInfo,
+      },
+    });
+
+    expect(await screen.findByText('2024-05-01 10:20:30')).toBeTruthy();
+    expect(screen.getByText('Canon EOS R5')).toBeTruthy();
+    expect(screen.getByText('1/250秒 f/2.8 ISO400')).toBeTruthy();
+    expect(screen.queryByText('レンズ:')).toBeNull();
+  });
+
   test('ファイルサイズが正しくフォーマットされる', async () => {
     render(ImageInfoDisplay, {
       props: {
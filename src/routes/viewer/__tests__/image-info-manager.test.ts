@@ -264,6 +264,64 @@ describe('ImageInfoManager', () => {
       expect(manager.getListLength()).toBe(0);
       expect(() => manager.getCurrent()).toThrow('No images');
     });
+
+    it('should restore deleted images at their original positions', () => {
+      manager.gotoAt(2);
+      manager.deleteCurrent();
+      manager.gotoAt(1);
+
+      manager.restoreImages([{ path: '/path/to/image2.png', index: 1 }]);
+
+      expect(manager.getList().map(img => img.path)).toEqual([
+        '/path/to/image1.jpg',
+        '/path/to/image2.png',
+        '/path/to/image3.gif',
+      ]);
+      expect(manager.getCurrent().path).toBe('/path/to/image2.png');
+    });
+
+    it('should remove externally deleted images and ignore unknown paths', () => {
+      manager.gotoAt(3);
+      manager.removeImages(['/path/to/image3.gif', '/path/to/unknown.jpg']);
+
+      expect(manager.getList().map(img => img.path)).toEqual([
+        '/path/to/image1.jpg',
+        '/path/to/image2.png',
+      ]);
+      expect(manager.getCaret()).toBe(1);
+    });
+
+    it('should rename images in place', async () => {
+      manager.renameImages([{ from: '/path/to/image2.png', to: '/path/to/renamed.png' }]);
+
+      expect(manager.getList().map(img => img.path)).toEqual([
+        '/path/to/image1.jpg',
+        '/path/to/renamed.png',
+        '/path/to/image3.gif',
+      ]);
+      // 名前変更前のパスは再び追加できる
+      await manager.addImages([new ImageInfo('/path/to/image2.png')]);
+      expect(manager.getListLength()).toBe(4);
+    });
+
+    it('should reorder images and keep showing the current image', async () => {
+      await manager.addImages([new ImageInfo('/path/to/other.jpg')]);
+      manager.gotoAt(3);
+      manager.reorderImages([
+        '/path/to/image3.gif',
+        '/path/to/image1.jpg',
+        '/path/to/image2.png',
+      ]);
+
+      // 並び替えの対象でない画像は元の位置のまま
+      expect(manager.getList().map(img => img.path)).toEqual([
+        '/path/to/image3.gif',
+        '/path/to/image1.jpg',
+        '/path/to/image2.png',
+        '/path/to/other.jpg',
+      ]);
+      expect(manager.getCurrent().path).toBe('/path/to/image3.gif');
+    });
   });
 
   describe('edge cases', () => {
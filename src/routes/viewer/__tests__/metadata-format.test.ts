@@ -0,0 +1,65 @@
+import { describe, it, expect } from 'vitest';
+import { formatMetadataItems } from '../metadata-format';
+import type { ImageMetadata } from '@/lib/api/files';
+
+const emptyMetadata: ImageMetadata = {
+  captureDate: null,
+  cameraMake: null,
+  cameraModel: null,
+  lensModel: null,
+  exposureTime: null,
+  fNumber: null,
+  iso: null,
+  focalLength: null,
+  focalLengthIn35mm: null,
+  gps: null,
+  orientation: null,
+  rating: null,
+  keywords: null,
+  caption: null,
+};
+
+describe('formatMetadataItems', () => {
+  it('メタデータがない場合は項目を作らない', () => {
+    expect(formatMetadataItems(emptyMetadata)).toEqual([]);
+  });
+
+  it('取得できた項目を表示用に整形する', () => {
+    const items = formatMetadataItems({
+      ...emptyMetadata,
+      captureDate: '2024-05-01T10:20:30',
+      cameraMake: 'NIKON CORPORATION',
+      cameraModel: 'NIKON Z 6',
+      lensModel: 'NIKKOR Z 24-70mm f/4 S',
+      exposureTime: '1/250',
+      fNumber: 4,
+      iso: 100,
+      focalLength: 50,
+      focalLengthIn35mm: 50,
+      gps: { latitude: 35.681236, longitude: 139.767125, altitude: null },
+    });
+
+    expect(items).toEqual([
+      { label: '撮影日時:', value: '2024-05-01 10:20:30' },
+      { label: 'カメラ:', value: 'NIKON CORPORATION NIKON Z 6' },
+      { label: 'レンズ:', value: 'NIKKOR Z 24-70mm f/4 S' },
+      { label: '露出:', value: '1/250秒 f/4 ISO100' },
+      { label: '焦点距離:', value: '50mm（35mm判換算 50mm）' },
+      { label: '位置:', value: '35.681236, 139.767125' },
+    ]);
+  });
+
+  it('機種名にメーカー名が含まれる場合は機種名のみにする', () => {
+    const items = formatMetadataItems({
+      ...emptyMetadata,
+      cameraMake: 'Canon',
+      cameraModel: 'Canon EOS R5',
+      iso: 800,
+    });
+
+    expect(items).toEqual([
+      { label: 'カメラ:', value: 'Canon EOS R5' },
+      { label: '露出:', value: 'ISO800' },
+    ]);
+  });
+});
@@ -124,6 +124,24 @@ describe('path-utils', () => {
         expectedDir: '',
         expectedFile: 'screenshot.png',
       },
+      {
+        name: 'image in archive',
+        path: '/home/user/comics/vol1.cbz!/ch1/001.jpg',
+        expectedDir: '/home/user/comics/',
+        expectedFile: 'vol1.cbz!/ch1/001.jpg',
+      },
+      {
+        name: 'image in archive on Windows',
+        path: 'C:\\Users\\John\\Pictures\\pack.ZIP!/001.png',
+        expectedDir: 'C:\\Users\\John\\Pictures\\',
+        expectedFile: 'pack.ZIP!/001.png',
+      },
+      {
+        name: 'directory name containing separator',
+        path: '/home/user/wow!/photo.png',
+        expectedDir: '/home/user/wow!/',
+        expectedFile: 'photo.png',
+      },
     ];
 
     testCases.forEach(({ name, path, expectedDir, expectedFile }) => {
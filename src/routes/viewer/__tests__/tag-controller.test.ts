@@ -134,6 +134,83 @@ describe('TagController', () => {
       await expect(tagController.saveImageTags(imagePath, tags)).rejects.toThrow();
       expect(mockToastController.showToast).toHaveBeenCalledWith('タグの保存に失敗しました');
     });
+
+    it('should show the reason of a command error', async () => {
+      // Arrange
+      const imagePath = '/home/user/images/photo.jpg';
+      vi.mocked(tagsApi.saveTags).mockRejectedValue({
+        kind: 'permissionDenied',
+        message: 'Failed to write temporary tag file',
+        path: '/home/user/images/IMAGE_TAG_TEMP',
+        source: 'Permission denied (os error 13)',
+      });
+
+      // Act & Assert
+      await expect(tagController.saveImageTags(imagePath, ['nature'])).rejects.toBeDefined();
+      expect(mockToastController.showToast).toHaveBeenCalledWith(
+        'タグの保存に失敗しました: アクセスが拒否されました'
+      );
+    });
+  });
+
+  describe('applyTagsChanged', () => {
+    it('should replace cached tags of the changed directory', async () => {
+      // Arrange
+      const imagePath = '/home/user/images/photo.jpg';
+      vi.mocked(tagsApi.loadTagsInDir).mockResolvedValue({ 'photo.jpg': ['old'] });
+      await tagController.getImageTags(imagePath);
+
+      // Act
+      tagController.applyTagsChanged('/home/user/images', {
+        'photo.jpg': ['new'],
+        'other.jpg': ['cat'],
+      });
+
+      // Assert
+      expect(await tagController.getImageTags(imagePath)).toEqual(['new']);
+      expect(tagsApi.loadTagsInDir).toHaveBeenCalledTimes(1);
+    });
+
+    it('should notify external changes of loaded directories', async () => {
+      // Arrange
+      vi.mocked(tagsApi.loadTagsInDir).mockResolvedValue({});
+      await tagController.loadTagsInDir('/home/user/images/');
+
+      // Act
+      tagController.applyTagsChanged('/home/user/images', { 'photo.jpg': ['new'] }, true);
+      tagController.applyTagsChanged('/home/user/other', { 'photo.jpg': ['new'] }, true);
+
+      // Assert
+      expect(mockToastController.showToast).toHaveBeenCalledTimes(1);
+      expect(mockToastController.showToast).toHaveBeenCalledWith(
+        'タグファイルが外部で変更されたので読み込み直しました'
+      );
+    });
+
+    it('should ignore directories that are not loaded yet', async () => {
+      // Act
+      tagController.applyTagsChanged('/home/user/other', { 'photo.jpg': ['new'] });
+      vi.mocked(tagsApi.loadTagsInDir).mockResolvedValue({ 'photo.jpg': ['saved'] });
+
+      // Assert
+      expect(await tagController.getImageTags('/home/user/other/photo.jpg')).toEqual(['saved']);
+    });
+  });
+
+  describe('revalidateCache', () => {
+    it('should reload every cached directory', async () => {
+      // Arrange
+      vi.mocked(tagsApi.loadTagsInDir).mockResolvedValue({ 'photo.jpg': ['old'] });
+      await tagController.loadTagsInDir('/home/user/images/');
+      vi.mocked(tagsApi.loadTagsInDir).mockResolvedValue({ 'photo.jpg': ['edited'] });
+
+      // Act
+      await tagController.revalidateCache();
+
+      // Assert
+      expect(tagsApi.loadTagsInDir).toHaveBeenCalledTimes(2);
+      expect(await tagController.getImageTags('/home/user/images/photo.jpg')).toEqual(['edited']);
+    });
   });
 
   describe('path processing', () => {
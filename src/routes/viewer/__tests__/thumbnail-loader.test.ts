@@ -0,0 +1,58 @@
+import { describe, it, expect, beforeEach, vi } from 'vitest';
+import { ThumbnailLoader, thumbnailEdgeFor } from '../thumbnail-loader.svelte';
+import * as filesApi from '@/lib/api/files';
+
+vi.mock('@tauri-apps/api/core', () => ({
+  convertFileSrc: (path: string, protocol = 'asset') => `${protocol}://localhost/${path}`,
+}));
+
+vi.mock('@/lib/api/files', () => ({
+  getThumbnail: vi.fn(),
+  imageSrc: (path: string) => `image://localhost/${path}`,
+}));
+
+describe('thumbnailEdgeFor', () => {
+  it('セルに収まる最小のサムネイルの大きさを返す', () => {
+    expect(thumbnailEdgeFor(200, 150, 1)).toBe(256);
+    expect(thumbnailEdgeFor(480, 270, 2)).toBe(1024);
+  });
+
+  it('最大のサムネイルより大きいセルでは null を返す', () => {
+    expect(thumbnailEdgeFor(1920, 1080, 2)).toBeNull();
+  });
+});
+
+describe('ThumbnailLoader', () => {
+  beforeEach(() => {
+    vi.clearAllMocks();
+  });
+
+  it('読み込み前は null を返し、読み込んだ後はサムネイルのURLを返す', async () => {
+    vi.mocked(filesApi.getThumbnail).mockResolvedValue('/cache/abc.jpg');
+    const loader = new ThumbnailLoader();
+
+    expect(loader.getSrc('/photos/a.jpg', 256)).toBeNull();
+    expect(loader.getSrc('/photos/a.jpg', 256)).toBeNull();
+    await vi.waitFor(() => {
+      expect(loader.getSrc('/photos/a.jpg', 256)).toBe('asset://localhost//cache/abc.jpg');
+    });
+    // 読み込み中に何度呼ばれても1回だけ生成する
+    expect(filesApi.getThumbnail).toHaveBeenCalledTimes(1);
+    expect(filesApi.getThumbnail).toHaveBeenCalledWith('/photos/a.jpg', 256);
+  });
+
+  it('サムネイルを生成できない場合は元の画像のURLを返す', async () => {
+    vi.mocked(filesApi.getThumbnail).mockRejectedValue({
+      kind: 'notFound',
+      message: 'not found',
+    });
+    const loader = new ThumbnailLoader();
+
+    loader.getSrc('/photos/comic.cbz!/001.png', 256);
+    await vi.waitFor(() => {
+      expect(loader.getSrc('/photos/comic.cbz!/001.png', 256)).toBe(
+        'image://localhost//photos/comic.cbz!/001.png'
+      );
+    });
+  });
+});
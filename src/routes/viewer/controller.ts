@@ -6,6 +6,12 @@ import { ViewerController } from './viewer-controller.svelte';
 import { GotoDialogController } from './goto-dialog-controller.svelte';
 import { FilterDialogController } from './filter-dialog-controller.svelte';
 import { EditModeController } from './edit-mode-controller.svelte';
+import type { TransferMode } from '@/lib/api/files';
+import { describeError } from '@/lib/api/errors';
+
+// 数字キーに割り当てる振り分け先の番号（設定の1番目から9番目）
+const SORT_BIN_SLOTS = [1, 2, 3, 4, 5, 6, 7, 8, 9] as const;
+type SortBinSlot = (typeof SORT_BIN_SLOTS)[number];
 
 export type Operation =
   | 'next'
@@ -14,6 +20,10 @@ export type Operation =
   | 'prevJump'
   | 'randomJump'
   | 'delete'
+  | 'undoDelete'
+  | 'deleteBookmarked'
+  | `moveToSortBin${SortBinSlot}`
+  | `copyToSortBin${SortBinSlot}`
   | 'bookmark'
   | 'gotoBookmark'
   | 'nextHistory'
@@ -68,6 +78,18 @@ const viewModeKeyConfigs: keyConfig[] = [
   { key: 'h', operation: 'prevHistory', modifierKeys: [] },
   { key: 'h', operation: 'nextHistory', modifierKeys: ['shift'] },
   { key: 'Delete', operation: 'delete', modifierKeys: [] },
+  { key: 'z', operation: 'undoDelete', modifierKeys: ['ctrl'] },
+  { key: 'Delete', operation: 'deleteBookmarked', modifierKeys: ['shift'] },
+  ...SORT_BIN_SLOTS.map(slot => ({
+    key: `${slot}`,
+    operation: `moveToSortBin${slot}` as const,
+    modifierKeys: [],
+  })),
+  ...SORT_BIN_SLOTS.map(slot => ({
+    key: `${slot}`,
+    operation: `copyToSortBin${slot}` as const,
+    modifierKeys: ['ctrl' as const],
+  })),
   { key: 'r', operation: 'incrementRows', modifierKeys: [] },
   { key: 'r', operation: 'decrementRows', modifierKeys: ['shift'] },
   { key: 'l', operation: 'incrementCols', modifierKeys: [] },
@@ -179,6 +201,12 @@ export class Controler {
 
     console.log(`Controller operate: executing operation=${operation}`); // debug
 
+    const sortBinOperation = parseSortBinOperation(operation);
+    if (sortBinOperation !== null) {
+      this.sendToSortBin(sortBinOperation.slot, sortBinOperation.mode);
+      return;
+    }
+
     switch (operation) {
       case 'next':
         this.imageInfoManager.gotoNext();
@@ -202,12 +230,46 @@ export class Controler {
           (result: boolean) => {
             if (result) {
               this.imageInfoManager.deleteCurrent();
-              this.fileController.deleteFile(path);
+              this.fileController.deleteFile(path).catch((error) => {
+                console.error('Failed to delete file:', error);
+                this.toastController.showToast(describeError(error, '削除に失敗しました'));
+              });
+            }
+          }
+        );
+        break;
+      }
+      case 'deleteBookmarked': {
+        const paths = this.imageInfoManager.getBookmarkedPaths();
+        if (paths.length === 0) {
+          this.toastController.showToast('ブックマークした画像はありません');
+          break;
+        }
+        this.dialogController.showDialog(
+          `ブックマークした${paths.length}個の画像をゴミ箱に移動しますか？`,
+          (result: boolean) => {
+            if (result) {
+              this.deleteFiles(paths);
             }
           }
         );
         break;
       }
+      case 'undoDelete':
+        this.fileController
+          .undoDelete()
+          .then(restored => {
+            if (restored.length === 0) {
+              this.toastController.showToast('元に戻す削除はありません');
+              return;
+            }
+            this.imageInfoManager.restoreImages(restored);
+            this.toastController.showToast(`${restored.length}個の画像を元に戻しました`);
+          })
+          .catch(error => {
+            this.toastController.showToast(describeError(error, '元に戻せませんでした'));
+          });
+        break;
       case 'bookmark': {
         const current = this.imageInfoManager.getCurrent();
         const count = this.imageInfoManager.countBookmarked();
@@ -303,6 +365,48 @@ export class Controler {
     }
   }
 
+  private async deleteFiles(paths: string[]): Promise<void> {
+    try {
+      const results = await this.fileController.deleteFiles(paths);
+      const deleted = results.filter(result => result.error === null).map(result => result.path);
+      this.imageInfoManager.removeImages(deleted);
+      const failedCount = results.length - deleted.length;
+      this.toastController.showToast(
+        failedCount === 0
+          ? `${deleted.length}個の画像をゴミ箱に移動しました`
+          : `${deleted.length}個の画像をゴミ箱に移動しました（${failedCount}個は失敗）`
+      );
+    } catch (error) {
+      this.toastController.showToast(describeError(error, 'ゴミ箱に移動できませんでした'));
+    }
+  }
+
+  private async sendToSortBin(slot: SortBinSlot, mode: TransferMode): Promise<void> {
+    const path = this.imageInfoManager.getCurrent().path;
+    try {
+      const bin = (await this.fileController.getSortBins())[slot - 1];
+      if (bin === undefined) {
+        this.toastController.showToast(`振り分け先${slot}は設定されていません`);
+        return;
+      }
+      const destination = await this.fileController.sendToSortBin(path, bin.name, mode);
+      if (destination === null) {
+        this.toastController.showToast(`${bin.name}に同名の画像があるためスキップしました`);
+        return;
+      }
+      if (mode === 'move') {
+        // 移動先が既に一覧にある場合は名前変更されないので、移動前の画像を取り除く
+        this.imageInfoManager.renameImages([{ from: path, to: destination }]);
+        this.imageInfoManager.removeImages([path]);
+        this.toastController.showToast(`${bin.name}に移動しました`);
+      } else {
+        this.toastController.showToast(`${bin.name}にコピーしました`);
+      }
+    } catch (error) {
+      this.toastController.showToast(describeError(error, '振り分けできませんでした'));
+    }
+  }
+
   private keyToString(key: string, modifierKeys: ModifierKey[] = this.getModfierKeys()): string {
     const modified = modifierKeys.length === 0 ? key : `${modifierKeys.join(',')}:${key}`;
     return modified.toLowerCase();
@@ -328,3 +432,14 @@ export class Controler {
     this.modfierKeyMap.clear();
   }
 }
+
+// 振り分けの操作を振り分け先の番号と振り分けの方法に分解する（振り分けの操作でなければ null）
+function parseSortBinOperation(
+  operation: Operation
+): { slot: SortBinSlot; mode: TransferMode } | null {
+  const match = /^(move|copy)ToSortBin([1-9])$/.exec(operation);
+  if (match === null) {
+    return null;
+  }
+  return { slot: Number(match[2]) as SortBinSlot, mode: match[1] as TransferMode };
+}
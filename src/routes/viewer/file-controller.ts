@@ -1,8 +1,49 @@
-import { deleteFile } from '@/lib/api/files';
+import {
+  batchRename,
+  deleteFile,
+  deleteFiles,
+  getSortBins,
+  renameFile,
+  sendToSortBin,
+  undoDelete,
+} from '@/lib/api/files';
+import type { FileOperationResult, RestoredImage, SortBin, TransferMode } from '@/lib/api/files';
 
 export class FileController {
   public async deleteFile(path: string): Promise<void> {
     const result = await deleteFile(path);
     console.log(result);
   }
+
+  public async deleteFiles(paths: string[]): Promise<FileOperationResult[]> {
+    return deleteFiles(paths);
+  }
+
+  public async undoDelete(): Promise<RestoredImage[]> {
+    return undoDelete();
+  }
+
+  public async renameFile(path: string, newName: string): Promise<string> {
+    return renameFile(path, newName);
+  }
+
+  public async batchRename(
+    paths: string[],
+    template: string,
+    dryRun: boolean
+  ): Promise<FileOperationResult[]> {
+    return batchRename(paths, template, dryRun);
+  }
+
+  public async getSortBins(): Promise<SortBin[]> {
+    return getSortBins();
+  }
+
+  public async sendToSortBin(
+    path: string,
+    binName: string,
+    mode: TransferMode
+  ): Promise<string | null> {
+    return sendToSortBin(path, binName, mode);
+  }
 }
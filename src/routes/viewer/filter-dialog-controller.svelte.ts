@@ -1,14 +1,20 @@
 import { SvelteSet } from 'svelte/reactivity';
+import { dropPaths } from '@/lib/api/files';
+import { describeError } from '@/lib/api/errors';
+import { searchByTags } from '@/lib/api/tags';
 import type { ImageInfoManager } from './image-info-manager.svelte.ts';
+import type { ToastController } from './toast-controller.svelte';
 
 export class FilterDialogController {
   private show: boolean = $state(false);
   private availableTags: string[] = $state([]);
   private selectedTags = new SvelteSet<string>();
   private imageInfoManager: ImageInfoManager;
+  private toastController?: ToastController;
 
-  constructor(imageInfoManager: ImageInfoManager) {
+  constructor(imageInfoManager: ImageInfoManager, toastController?: ToastController) {
     this.imageInfoManager = imageInfoManager;
+    this.toastController = toastController;
   }
 
   public async showDialog(): Promise<void> {
@@ -36,6 +42,27 @@ export class FilterDialogController {
     await this.imageInfoManager.applyTagFilter(selectedTagsArray);
   }
 
+  // 選択したタグの付いた画像を、タグのあるすべてのディレクトリから検索して新しいウィンドウで開く
+  public async searchAllDirs(): Promise<void> {
+    const selectedTagsArray = Array.from(this.selectedTags);
+    if (selectedTagsArray.length === 0) {
+      return;
+    }
+    this.hideDialog();
+
+    try {
+      const paths = await searchByTags({ tags: selectedTagsArray });
+      if (paths.length === 0) {
+        this.toastController?.showToast('タグの付いた画像が見つかりませんでした');
+        return;
+      }
+      await dropPaths(paths, undefined, undefined, undefined, true);
+    } catch (error) {
+      console.error('Search by tags failed:', error);
+      this.toastController?.showToast(describeError(error, 'タグでの検索に失敗しました'));
+    }
+  }
+
   public isShow(): boolean {
     return this.show;
   }
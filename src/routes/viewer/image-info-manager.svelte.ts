@@ -163,6 +163,78 @@ export class ImageInfoManager {
     this.setCaret(this.caret);
   }
 
+  // 削除を取り消した画像を元の位置に戻し、最後に戻した画像を表示する
+  public restoreImages(restored: { path: string; index: number }[]): void {
+    if (restored.length === 0) {
+      return;
+    }
+    for (const { path, index } of restored) {
+      const list = this.originalList.filter(image => image.path !== path);
+      list.splice(Math.min(index, list.length), 0, new ImageInfo(path));
+      this.originalList = list;
+      this.pathSet.add(path);
+    }
+    this.updateDisplayList();
+    const last = this.findImageByPath(restored[restored.length - 1].path);
+    if (last !== null) {
+      this.setCaret(this.filteredList.indexOf(last));
+    }
+  }
+
+  // 外部で削除された画像を一覧から取り除く（一覧にないパスは無視する）
+  public removeImages(paths: string[]): void {
+    const removed = new SvelteSet(paths.filter(path => this.pathSet.has(path)));
+    if (removed.size === 0) {
+      return;
+    }
+    this.originalList = this.originalList.filter(image => !removed.has(image.path));
+    removed.forEach(path => this.pathSet.delete(path));
+    this.updateDisplayList();
+    this.setCaret(this.caret);
+  }
+
+  // 外部で名前変更された画像のパスを、一覧の同じ位置のまま置き換える
+  public renameImages(renames: { from: string; to: string }[]): void {
+    const renameMap = new SvelteMap(
+      renames
+        .filter(({ from, to }) => this.pathSet.has(from) && !this.pathSet.has(to))
+        .map(({ from, to }) => [from, to])
+    );
+    if (renameMap.size === 0) {
+      return;
+    }
+    this.originalList = this.originalList.map(image => {
+      const to = renameMap.get(image.path);
+      return to === undefined ? image : new ImageInfo(to);
+    });
+    renameMap.forEach((to, from) => {
+      this.pathSet.delete(from);
+      this.pathSet.add(to);
+    });
+    this.updateDisplayList();
+    this.setCaret(this.caret);
+  }
+
+  // 一覧にある画像をpathsの順に並び替える（pathsにない画像は元の位置のまま）
+  // 表示中の画像は並び替え後も表示したままにする
+  public reorderImages(paths: string[]): void {
+    const order = new SvelteMap(paths.map((path, index) => [path, index]));
+    const reordered = this.originalList
+      .filter(image => order.has(image.path))
+      .sort((a, b) => order.get(a.path)! - order.get(b.path)!);
+    if (reordered.length === 0) {
+      return;
+    }
+    const current = this.filteredList[this.caret];
+    let next = 0;
+    this.originalList = this.originalList.map(image =>
+      order.has(image.path) ? reordered[next++] : image
+    );
+    this.updateDisplayList();
+    const index = current === undefined ? -1 : this.filteredList.indexOf(current);
+    this.setCaret(index === -1 ? this.caret : index);
+  }
+
   // --- 未分類 --- //
 
   public bookmarkCurrent(): void {
@@ -171,6 +243,9 @@ export class ImageInfoManager {
   public countBookmarked(): number {
     return this.filteredList.filter(image => image.isBookmarked()).length;
   }
+  public getBookmarkedPaths(): string[] {
+    return this.filteredList.filter(image => image.isBookmarked()).map(image => image.path);
+  }
 
   // --- 補助 --- //
 
@@ -0,0 +1,64 @@
+import type { ImageMetadata } from '@/lib/api/files';
+
+/**
+ * 画像のメタデータを情報表示用に整形するユーティリティ関数
+ */
+
+export interface MetadataItem {
+  label: string;
+  value: string;
+}
+
+/**
+ * メタデータから情報表示の項目を作成します
+ * 取得できなかった（null の）項目は含めません
+ * @param metadata getImageMetadata で取得したメタデータ
+ * @returns ラベルと表示する値の配列
+ */
+export function formatMetadataItems(metadata: ImageMetadata): MetadataItem[] {
+  const items: MetadataItem[] = [];
+
+  if (metadata.captureDate) {
+    items.push({ label: '撮影日時:', value: metadata.captureDate.replace('T', ' ') });
+  }
+
+  const camera = formatCamera(metadata.cameraMake, metadata.cameraModel);
+  if (camera) {
+    items.push({ label: 'カメラ:', value: camera });
+  }
+  if (metadata.lensModel) {
+    items.push({ label: 'レンズ:', value: metadata.lensModel });
+  }
+
+  const exposure = [
+    metadata.exposureTime !== null ? `${metadata.exposureTime}秒` : null,
+    metadata.fNumber !== null ? `f/${metadata.fNumber}` : null,
+    metadata.iso !== null ? `ISO${metadata.iso}` : null,
+  ].filter(value => value !== null);
+  if (exposure.length > 0) {
+    items.push({ label: '露出:', value: exposure.join(' ') });
+  }
+
+  if (metadata.focalLength !== null) {
+    const in35mm =
+      metadata.focalLengthIn35mm !== null ? `（35mm判換算 ${metadata.focalLengthIn35mm}mm）` : '';
+    items.push({ label: '焦点距離:', value: `${metadata.focalLength}mm${in35mm}` });
+  }
+
+  if (metadata.gps) {
+    const { latitude, longitude } = metadata.gps;
+    items.push({ label: '位置:', value: `${latitude.toFixed(6)}, ${longitude.toFixed(6)}` });
+  }
+
+  return items;
+}
+
+/**
+ * カメラのメーカー名と機種名をつなげます
+ * 機種名にメーカー名が含まれている場合（"Canon" と "Canon EOS R5" 等）は機種名のみにします
+ */
+function formatCamera(make: string | null, model: string | null): string | null {
+  if (!make) return model;
+  if (!model) return make;
+  return model.toLowerCase().startsWith(make.toLowerCase()) ? model : `${make} ${model}`;
+}
@@ -1,21 +1,33 @@
+import { splitArchiveEntryPath } from '@/lib/api/files';
+
 /**
  * ファイルパス関連のユーティリティ関数
  */
 
 /**
  * ファイルパスからディレクトリパスを抽出します
+ * アーカイブ内の画像の場合はアーカイブのあるディレクトリになります
  * @param filePath ファイルのフルパス
  * @returns ディレクトリパス（末尾にスラッシュを含む）
  */
 export function getDirPath(filePath: string): string {
+  const archiveEntry = splitArchiveEntryPath(filePath);
+  if (archiveEntry) {
+    return getDirPath(archiveEntry.archivePath);
+  }
   return filePath.replace(/[^\\/]*$/, '');
 }
 
 /**
  * ファイルパスからファイル名を抽出します
+ * アーカイブ内の画像の場合は "{アーカイブのファイル名}!/{エントリ名}" になります
  * @param filePath ファイルのフルパス
  * @returns ファイル名（パスを除く）
  */
 export function getFileName(filePath: string): string {
+  const archiveEntry = splitArchiveEntryPath(filePath);
+  if (archiveEntry) {
+    return `${getFileName(archiveEntry.archivePath)}!/${archiveEntry.entryName}`;
+  }
   return filePath.replace(/^.*[\\/]/, '');
 }
@@ -1,3 +1,4 @@
+import { describeError } from '@/lib/api/errors';
 import { loadTagsInDir, saveTags } from '@/lib/api/tags';
 import { ToastController } from './toast-controller.svelte';
 import { SvelteMap } from 'svelte/reactivity';
@@ -28,7 +29,7 @@ export class TagController {
       return tagsMap[fileName] || [];
     } catch (error) {
       console.error('Failed to load tags:', error);
-      this.toastController.showToast('タグの読み込みに失敗しました');
+      this.toastController.showToast(describeError(error, 'タグの読み込みに失敗しました'));
       return [];
     }
   }
@@ -49,7 +50,9 @@ export class TagController {
       return this.tagsCache.get(dirPath)!;
     } catch (error) {
       console.error('Failed to load tags in directory:', error);
-      this.toastController.showToast('ディレクトリのタグ読み込みに失敗しました');
+      this.toastController.showToast(
+        describeError(error, 'ディレクトリのタグ読み込みに失敗しました')
+      );
       return {};
     }
   }
@@ -82,11 +85,52 @@ export class TagController {
       this.toastController.showToast('タグを保存しました');
     } catch (error) {
       console.error('Failed to save tags:', error);
-      this.toastController.showToast('タグの保存に失敗しました');
+      this.toastController.showToast(describeError(error, 'タグの保存に失敗しました'));
       throw error;
     }
   }
 
+  /**
+   * 他のウィンドウなどで変更されたディレクトリのタグ情報でキャッシュを置き換えます
+   * まだ読み込んでいないディレクトリは、次に必要になったときに読み込みます
+   * @param dirPath ディレクトリパス（末尾の区切り文字の有無は問わない）
+   * @param tags ファイル名をキーとしたタグマップ
+   * @param external タグファイルがアプリの外で変更された場合はtrue（読み込み済みならトーストで知らせる）
+   */
+  public applyTagsChanged(
+    dirPath: string,
+    tags: Record<string, string[]>,
+    external: boolean = false
+  ): void {
+    const target = trimTrailingSeparator(dirPath);
+    let applied = false;
+    for (const cachedDirPath of this.tagsCache.keys()) {
+      if (trimTrailingSeparator(cachedDirPath) === target) {
+        this.tagsCache.set(cachedDirPath, { ...tags });
+        applied = true;
+      }
+    }
+    if (applied && external) {
+      this.toastController.showToast('タグファイルが外部で変更されたので読み込み直しました');
+    }
+  }
+
+  /**
+   * 読み込み済みのディレクトリのタグ情報を取得し直します
+   * タグファイルが外部で変更されていれば、バックエンドが読み直して tags-changed イベントを送ります
+   */
+  public async revalidateCache(): Promise<void> {
+    await Promise.all(
+      Array.from(this.tagsCache.keys()).map(async dirPath => {
+        try {
+          this.tagsCache.set(dirPath, await loadTagsInDir(dirPath));
+        } catch (error) {
+          console.warn('Failed to revalidate tags:', error);
+        }
+      })
+    );
+  }
+
   /**
    * 指定したディレクトリのタグキャッシュをクリアします
    * @param dirPath ディレクトリパス
@@ -152,3 +196,7 @@ export class TagController {
   // - TTL: 30分
   // - アクセス頻度に基づくLRU削除
 }
+
+function trimTrailingSeparator(dirPath: string): string {
+  return dirPath.replace(/[\\/]+$/, '');
+}
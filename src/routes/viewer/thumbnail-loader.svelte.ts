@@ -0,0 +1,75 @@
+import { SvelteMap } from 'svelte/reactivity';
+import { convertFileSrc } from '@tauri-apps/api/core';
+import { getThumbnail, imageSrc } from '@/lib/api/files';
+
+/**
+ * 生成するサムネイルの長辺のピクセル数
+ * セルの大きさ毎に作り直さないように、この中からセルに収まる最小のものを使います
+ */
+const THUMBNAIL_EDGES = [256, 512, 1024, 2048];
+
+/**
+ * 保持するサムネイルのURLの上限（超えた場合は古いものから破棄します）
+ */
+const MAX_CACHED_SRCS = 1000;
+
+/**
+ * セルに表示するサムネイルの長辺のピクセル数を返します
+ * @param cellWidth セルの幅（CSSピクセル）
+ * @param cellHeight セルの高さ（CSSピクセル）
+ * @param pixelRatio デバイスピクセル比
+ * @returns セルが最大のサムネイルより大きい場合は null（元の画像を表示する）
+ */
+export function thumbnailEdgeFor(
+  cellWidth: number,
+  cellHeight: number,
+  pixelRatio: number
+): number | null {
+  const edge = Math.max(cellWidth, cellHeight) * pixelRatio;
+  return THUMBNAIL_EDGES.find(size => size >= edge) ?? null;
+}
+
+/**
+ * グリッド表示の各セルに表示するサムネイルを読み込みます
+ */
+export class ThumbnailLoader {
+  // "{長辺}:{パス}" ごとの表示用のURL
+  private srcs = new SvelteMap<string, string>();
+  private pending = new Set<string>();
+
+  /**
+   * サムネイルの表示用のURLを返します
+   * 読み込み前の場合は読み込みを始めて null を返し、読み込んだ後に再描画されます
+   * サムネイルを生成できない画像（アーカイブ内の画像等）は元の画像のURLになります
+   */
+  public getSrc(path: string, maxEdge: number): string | null {
+    const key = `${maxEdge}:${path}`;
+    const src = this.srcs.get(key);
+    if (src !== undefined) {
+      return src;
+    }
+    if (!this.pending.has(key)) {
+      this.pending.add(key);
+      this.load(path, maxEdge, key);
+    }
+    return null;
+  }
+
+  private async load(path: string, maxEdge: number, key: string): Promise<void> {
+    let src: string;
+    try {
+      src = convertFileSrc(await getThumbnail(path, maxEdge));
+    } catch (error) {
+      console.warn('Failed to get thumbnail:', error);
+      src = imageSrc(path);
+    }
+    this.pending.delete(key);
+    if (this.srcs.size >= MAX_CACHED_SRCS) {
+      const oldest = this.srcs.keys().next().value;
+      if (oldest !== undefined) {
+        this.srcs.delete(oldest);
+      }
+    }
+    this.srcs.set(key, src);
+  }
+}